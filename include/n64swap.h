@@ -0,0 +1,47 @@
+/* C interface to the n64swap library, build it with
+ * `cargo rustc --lib --release --features ffi --crate-type cdylib`
+ * and link against libn64swap.so (n64swap.dll on Windows).
+ *
+ * Release builds abort the process on a panic, as Cargo.toml sets
+ * panic = "abort". N64SWAP_ERR_PANIC is only returned by builds that unwind,
+ * such as debug builds. */
+#ifndef N64SWAP_H
+#define N64SWAP_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Rom types */
+#define N64SWAP_BIG_ENDIAN 0    /* .z64 */
+#define N64SWAP_BYTE_SWAP 1     /* .v64 */
+#define N64SWAP_LITTLE_ENDIAN 2 /* .n64 */
+#define N64SWAP_WORD_SWAP 3     /* also .n64, halfwords swapped */
+
+/* Return codes */
+#define N64SWAP_OK 0
+#define N64SWAP_ERR_INVALID_ARGUMENT -1
+#define N64SWAP_ERR_UNRECOGNIZED -2
+#define N64SWAP_ERR_IO -3
+#define N64SWAP_ERR_SIZE -4
+#define N64SWAP_ERR_PANIC -5 /* not in release builds, those abort */
+
+/* Identify the rom type from the 4 magic bytes at header4 */
+int n64swap_identify(const uint8_t *header4, int *out_type);
+
+/* Convert len bytes (a multiple of 4) from src_type to dst_type. out_buf
+ * holds len bytes and may be in_buf to convert in place. */
+int n64swap_convert(const uint8_t *in_buf, size_t len, int src_type, int dst_type,
+                    uint8_t *out_buf);
+
+/* Convert the rom file at src_path (UTF-8) into dst_type, writing dst_path */
+int n64swap_convert_file(const char *src_path, const char *dst_path, int dst_type);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,472 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Cursor, Read, Seek};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Mutex, PoisonError};
+use std::thread;
+
+use n64swap::{
+    convert_multi, format_size, guess_path_type, scan_roms, ConversionError, RomType, ScanOptions,
+    HEADER_SIZE,
+};
+
+use crate::hash::HashAlgorithm;
+use crate::input::{check_size, open_input, parse_header, source_type, verify_input_crc};
+use crate::logger::Logger;
+use crate::output::TempOutput;
+use crate::report::{
+    check_dat, conversion_failed, export_rom, find_duplicates, is_rejected, md5_entry,
+    print_digests, print_identify, print_inventory, read_export, read_identity, read_padding,
+    sfv_entry, validate_checksums, write_json_export, write_md5_entries, write_sfv_entries,
+    Identity,
+};
+use crate::stats::BatchStats;
+use crate::template;
+use crate::write::{backup_file, plan_output, preserve_attributes, unique_path, write_output, Job};
+use crate::Args;
+
+/// What became of a rom of a batch
+pub enum BatchOutcome {
+    Identified(Identity),
+    Converted(PathBuf, Vec<(HashAlgorithm, String)>),
+    Planned,
+}
+
+pub fn convert_batch_file(
+    args: &Args,
+    rom: &Path,
+    outfiletype: RomType,
+    // The --rename-from-dat name of the rom
+    dat_name: Option<&str>,
+    used: &Mutex<HashSet<PathBuf>>,
+    stats: &BatchStats,
+    progress: bool,
+) -> Result<BatchOutcome, ConversionError> {
+    let file = open_input(rom)?;
+    let metadata = file.metadata()?;
+    let size = metadata.len();
+    let mut buf = BufReader::new(file);
+    let mut header = Vec::with_capacity(HEADER_SIZE);
+    buf.by_ref()
+        .take(HEADER_SIZE as u64)
+        .read_to_end(&mut header)?;
+    let (filetype, repaired) = source_type(args, rom, &mut header)?;
+    if args.identify {
+        let mut identity = read_identity(args, &mut Cursor::new(header).chain(buf), Some(size))?;
+        identity.padding = read_padding(rom);
+        return Ok(BatchOutcome::Identified(identity));
+    }
+
+    if filetype == outfiletype && !repaired {
+        return Err(ConversionError::SameType(filetype));
+    }
+    check_size(args, rom, size)?;
+    if args.verify_crc {
+        verify_input_crc(args, rom)?;
+    }
+
+    // Keep the stem, swap in the standard extension for the output type
+    let parent = rom.parent().unwrap_or(Path::new(""));
+    let outdir = match (&args.output_dir, &args.directory) {
+        // Mirror the input tree below the output directory
+        (Some(output_dir), Some(directory)) => {
+            output_dir.join(parent.strip_prefix(directory).unwrap_or(Path::new("")))
+        }
+        (Some(output_dir), None) => output_dir.clone(),
+        (None, _) => parent.to_path_buf(),
+    };
+    let stem = rom.file_stem().unwrap_or_default();
+    let template = match &args.output_template {
+        Some(template) => Some(template.as_str()),
+        None => args.rename_to_title.then_some(template::TITLE_TEMPLATE),
+    };
+    let outpath = match (template, dat_name) {
+        (_, Some(name)) if args.rename_from_dat => outdir
+            .join(Path::new(name).file_name().unwrap_or_default())
+            .with_extension(&outfiletype.get_file_ext()[1..]),
+        (Some(template), _) => {
+            let header = parse_header(&header);
+            let fields = template::Fields {
+                stem: &stem.to_string_lossy(),
+                outfiletype,
+                header: header.as_ref(),
+                os: args.target_os,
+            };
+            for placeholder in template::unresolved(template, &fields) {
+                warning!(
+                    "{{{}}} is unknown for {}, it's left empty",
+                    placeholder,
+                    rom.display()
+                );
+            }
+            outdir.join(template::render(template, &fields))
+        }
+        (None, _) => outdir
+            .join(stem)
+            .with_extension(&outfiletype.get_file_ext()[1..]),
+    };
+    let mut used = used.lock().unwrap_or_else(PoisonError::into_inner);
+    let outpath = if args.rename_to_title || args.rename_from_dat {
+        unique_path(args, outpath, rom, &mut used)
+    } else if used.contains(&outpath) {
+        // Another rom of the batch, e.g. one with the same stem, already has it
+        if args.no_suffix {
+            return Err(ConversionError::OutputCollision(outpath));
+        }
+        let unique = unique_path(args, outpath.clone(), rom, &mut used);
+        warning!(
+            "{} is taken by another file, writing {} to {}",
+            outpath.display(),
+            rom.display(),
+            unique.display()
+        );
+        unique
+    } else {
+        used.insert(outpath.clone());
+        outpath
+    };
+    drop(used);
+
+    if outpath == rom {
+        return Err(ConversionError::InputOutputSameFile(outpath));
+    }
+
+    let job = Job {
+        filename: rom,
+        outfilename: &outpath,
+        filetype,
+        outfiletype,
+        size: Some(size),
+        repaired,
+        progress,
+    };
+    if args.dry_run {
+        plan_output(args, &job, &outpath, args.force)?;
+        return Ok(BatchOutcome::Planned);
+    }
+
+    fs::create_dir_all(&outdir)?;
+    if args.force && outpath.exists() {
+        backup_file(args, &outpath)?;
+    }
+    let mut output = TempOutput::create(&outpath, args.force)?;
+    // A repaired magic stands in for the damaged one
+    buf.rewind()?;
+    let magic = if repaired {
+        buf.seek_relative(4)?;
+        &header[..4]
+    } else {
+        &[]
+    };
+    let digests = write_output(
+        args,
+        &job,
+        output.file(),
+        &mut Cursor::new(magic).chain(buf),
+    )?;
+    preserve_attributes(args, &metadata, output.file())?;
+    stats.transferred(size, output.file().metadata()?.len());
+    output.persist()?;
+    Ok(BatchOutcome::Converted(outpath, digests))
+}
+
+pub fn convert_directory(args: &Args, directory: &Path) -> Result<(), ConversionError> {
+    let options = ScanOptions {
+        recursive: args.recursive || args.depth.is_some(),
+        max_depth: args.depth,
+        include_hidden: args.include_hidden,
+        symlinks: args.symlinks(),
+    };
+    let scan = scan_roms(directory, &options).map_err(|error| {
+        let message = format!(
+            "Unable to read directory {}: {}",
+            directory.display(),
+            error
+        );
+        io::Error::new(error.kind(), message)
+    })?;
+    for symlink in &scan.skipped_symlinks {
+        warning!("Skipped symlink {}", symlink.display());
+    }
+    let mut roms = scan.roms;
+    if args.strict_ext {
+        roms.retain(|rom| guess_path_type(rom).is_some());
+    }
+    if args.find_duplicates {
+        return find_duplicates(args, &roms);
+    }
+    if args.inventory {
+        return print_inventory(args, &roms);
+    }
+    convert_batch(args, &roms)
+}
+
+// Open the --error-log file, a batch still runs when it can't be
+fn open_error_log(path: &Path) -> Option<Logger> {
+    match Logger::open(path) {
+        Ok(logger) => Some(logger),
+        Err(error) => {
+            warning!(
+                "Unable to open error log {}: {}, errors only go to stderr",
+                path.display(),
+                error
+            );
+            None
+        }
+    }
+}
+
+/// Convert each rom on its own, a failing rom doesn't stop the others
+pub fn convert_batch(args: &Args, roms: &[PathBuf]) -> Result<(), ConversionError> {
+    let outfiletype = args.romtype.unwrap_or(RomType::BigEndian);
+    let rejected = validate_checksums(args)?;
+    let dat_names = check_dat(args, roms)?;
+
+    let stats = BatchStats::new(roms.len());
+    let mut roms = roms.to_vec();
+    if args.no_follow_symlinks {
+        roms.retain(|rom| {
+            let symlink = fs::symlink_metadata(rom).is_ok_and(|meta| meta.file_type().is_symlink());
+            if symlink {
+                warning!("Skipped symlink {}", rom.display());
+                stats.skipped();
+            }
+            !symlink
+        });
+    }
+    if args.min_size.is_some() || args.max_size.is_some() {
+        roms.retain(|rom| {
+            // Files that can't be read fail when they're converted
+            let Ok(size) = fs::metadata(rom).map(|meta| meta.len()) else {
+                return true;
+            };
+            let limit = match (args.min_size, args.max_size) {
+                (Some(min), _) if size < min as u64 => "below --min-size",
+                (_, Some(max)) if size > max as u64 => "above --max-size",
+                _ => return true,
+            };
+            info!(
+                args,
+                "Skipped {}, {} is {}",
+                rom.display(),
+                format_size(size),
+                limit
+            );
+            stats.skipped();
+            false
+        });
+    }
+    let roms = roms.as_slice();
+    let mut sfv_entries = Vec::new();
+    let mut md5_entries = Vec::new();
+    let used = Mutex::new(HashSet::new());
+    let mut json_entries = Vec::new();
+    let mut logger = args.error_log.as_deref().and_then(open_error_log);
+
+    let workers = args.threads().min(roms.len()).max(1);
+    let process = |rom: &Path| {
+        if is_rejected(&rejected, rom) {
+            let error = ConversionError::ValidationFailed(rom.to_path_buf());
+            return (Ok(None), Err(error));
+        }
+        let metadata = read_export(args, rom);
+        let dat_name = dat_names.get(rom).map(String::as_str);
+        let outcome = convert_batch_file(
+            args,
+            rom,
+            outfiletype,
+            dat_name,
+            &used,
+            &stats,
+            workers == 1,
+        );
+        (metadata, outcome)
+    };
+    let next = AtomicUsize::new(0);
+    let (sender, receiver) = mpsc::channel();
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            let (sender, next, process) = (sender.clone(), &next, &process);
+            scope.spawn(move || loop {
+                let idx = next.fetch_add(1, Ordering::Relaxed);
+                let Some(rom) = roms.get(idx) else {
+                    break;
+                };
+                if sender.send((idx, process(rom))).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(sender);
+
+        // Report in input order, whichever worker finishes first
+        let mut pending = BTreeMap::new();
+        let mut reported = 0;
+        for (idx, result) in receiver {
+            pending.insert(idx, result);
+            while let Some((metadata, outcome)) = pending.remove(&reported) {
+                let rom = &roms[reported];
+                reported += 1;
+                match metadata {
+                    Ok(Some(metadata)) => {
+                        if let Err(error) = export_rom(args, rom, &metadata, &mut json_entries) {
+                            error!("Unable to export {}: {}", rom.display(), error);
+                        }
+                    }
+                    Ok(None) => {}
+                    Err(error) => error!("Unable to export {}: {}", rom.display(), error),
+                }
+                match outcome {
+                    Ok(BatchOutcome::Identified(identity)) => print_identify(args, rom, &identity),
+                    Ok(BatchOutcome::Converted(outpath, digests)) => {
+                        success!(args, "Converted {} -> {}", rom.display(), outpath.display());
+                        print_digests(args, &digests);
+                        sfv_entries.extend(sfv_entry(args, &outpath, &digests));
+                        md5_entries.extend(md5_entry(args, &outpath, &digests));
+                        stats.converted();
+                    }
+                    Ok(BatchOutcome::Planned) => stats.converted(),
+                    Err(ConversionError::SameType(filetype)) => {
+                        info!(args, "Skipped {}, already {}", rom.display(), filetype);
+                        stats.skipped();
+                    }
+                    Err(ConversionError::ValidationFailed(_)) => {
+                        info!(args, "Skipped {}, failed validation", rom.display());
+                        stats.skipped();
+                    }
+                    Err(error) => {
+                        conversion_failed(args, rom, &error);
+                        if let Some(log) = &mut logger {
+                            if let Err(log_error) = log.log_error(rom, &error) {
+                                warning!(
+                                    "Unable to log to {}: {}",
+                                    log.path().display(),
+                                    log_error
+                                );
+                            }
+                        }
+                        stats.failed();
+                    }
+                }
+            }
+        }
+    });
+
+    if !sfv_entries.is_empty() {
+        write_sfv_entries(args, &sfv_entries)?;
+    }
+    if !md5_entries.is_empty() {
+        write_md5_entries(args, &md5_entries)?;
+    }
+    write_json_export(args, &json_entries)?;
+
+    let errors = stats.error_count();
+    if !args.identify {
+        if args.dry_run {
+            info!(
+                args,
+                "{} to convert, {} skipped, {} errors",
+                stats.converted_count(),
+                stats.skipped_count(),
+                errors
+            );
+        } else if !args.quiet && !args.machine {
+            stats.print_summary();
+        }
+    }
+    if let Some(log) = logger.filter(|log| log.logged() > 0) {
+        info!(
+            args,
+            "{} errors logged to {}",
+            log.logged(),
+            log.path().display()
+        );
+    }
+    if errors > 0 {
+        return Err(ConversionError::BatchFailed { errors });
+    }
+    Ok(())
+}
+
+/// Write the rom in every type for --all-types, reading the input a single time
+pub fn convert_all_types(args: &Args, rom: &Path) -> Result<(), ConversionError> {
+    let file = open_input(rom)?;
+    let metadata = file.metadata()?;
+    let size = metadata.len();
+    check_size(args, rom, size)?;
+    let mut buf = BufReader::new(file);
+    let mut header = Vec::with_capacity(HEADER_SIZE);
+    buf.by_ref()
+        .take(HEADER_SIZE as u64)
+        .read_to_end(&mut header)?;
+    let (filetype, repaired) = source_type(args, rom, &mut header)?;
+
+    let outdir = match &args.output_dir {
+        Some(output_dir) => output_dir.clone(),
+        None => rom.parent().unwrap_or(Path::new("")).to_path_buf(),
+    };
+    let stem = rom.file_stem().unwrap_or_default();
+    let mut outputs = Vec::new();
+    for outfiletype in [RomType::BigEndian, RomType::ByteSwap, RomType::LittleEndian] {
+        let outpath = outdir
+            .join(stem)
+            .with_extension(&outfiletype.get_file_ext()[1..]);
+        if outpath == rom {
+            info!(args, "Skipped {}, that's the input", outpath.display());
+            continue;
+        }
+        if args.dry_run {
+            let job = Job {
+                filename: rom,
+                outfilename: &outpath,
+                filetype,
+                outfiletype,
+                size: Some(size),
+                repaired,
+                progress: false,
+            };
+            plan_output(args, &job, &outpath, args.force)?;
+            continue;
+        }
+        if args.force && outpath.exists() {
+            backup_file(args, &outpath)?;
+        }
+        let output = TempOutput::create(&outpath, args.force)?;
+        outputs.push((outfiletype, outpath, output));
+    }
+    if outputs.is_empty() {
+        return Ok(());
+    }
+
+    // A repaired magic stands in for the damaged one
+    buf.rewind()?;
+    let magic = if repaired {
+        buf.seek_relative(4)?;
+        &header[..4]
+    } else {
+        &[]
+    };
+    let mut writers: Vec<(RomType, BufWriter<&mut File>)> = outputs
+        .iter_mut()
+        .map(|(outfiletype, _, output)| {
+            let writer = BufWriter::with_capacity(args.buffer_size, output.file());
+            (*outfiletype, writer)
+        })
+        .collect();
+    convert_multi(
+        &mut Cursor::new(magic).chain(buf),
+        filetype,
+        &mut writers,
+        args.pad,
+        args.buffer_size,
+    )?;
+    drop(writers);
+
+    for (_, outpath, mut output) in outputs {
+        preserve_attributes(args, &metadata, output.file())?;
+        output.persist()?;
+        success!(args, "Converted {} -> {}", rom.display(), outpath.display());
+    }
+    Ok(())
+}
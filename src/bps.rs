@@ -0,0 +1,389 @@
+use core::fmt;
+
+use crate::crc32;
+
+const HEADER: &[u8] = b"BPS1";
+// Source, target and patch CRC-32s
+const FOOTER_SIZE: usize = 12;
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum BpsError {
+    /// The patch doesn't start with "BPS1"
+    MissingHeader,
+    /// The patch ends in the middle of an action
+    Truncated,
+    /// An action reads or writes outside of the source or target
+    OutOfBounds,
+    /// The patch was made for a source of another size
+    SourceSize { expected: u64, actual: u64 },
+    /// The patch was made for another source
+    SourceChecksum { expected: u32, actual: u32 },
+    /// The patched rom doesn't come out as the patch expects
+    TargetChecksum { expected: u32, actual: u32 },
+    /// The patch itself is damaged
+    PatchChecksum { expected: u32, actual: u32 },
+}
+
+impl fmt::Display for BpsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BpsError::MissingHeader => write!(f, "Not a BPS patch, the BPS1 header is missing"),
+            BpsError::Truncated => write!(f, "BPS patch is truncated"),
+            BpsError::OutOfBounds => write!(f, "BPS patch copies outside of the rom"),
+            BpsError::SourceSize { expected, actual } => write!(
+                f,
+                "BPS patch is for a {} byte rom, this one is {} bytes",
+                expected, actual
+            ),
+            BpsError::SourceChecksum { expected, actual } => write!(
+                f,
+                "BPS patch is for a rom with CRC32 {:08X}, this one has {:08X}, use --force to apply it anyway",
+                expected, actual
+            ),
+            BpsError::TargetChecksum { expected, actual } => write!(
+                f,
+                "Patched rom has CRC32 {:08X} instead of {:08X}",
+                actual, expected
+            ),
+            BpsError::PatchChecksum { expected, actual } => write!(
+                f,
+                "BPS patch is damaged, its CRC32 is {:08X} instead of {:08X}",
+                actual, expected
+            ),
+        }
+    }
+}
+
+impl std::error::Error for BpsError {}
+
+// Cursor over the patch body
+struct Reader<'a> {
+    patch: &'a [u8],
+    pos: usize,
+}
+
+impl Reader<'_> {
+    fn byte(&mut self) -> Result<u8, BpsError> {
+        let byte = *self.patch.get(self.pos).ok_or(BpsError::Truncated)?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    // The BPS variable-length number encoding
+    fn number(&mut self) -> Result<u64, BpsError> {
+        let (mut value, mut shift) = (0u64, 1u64);
+        loop {
+            let byte = self.byte()?;
+            value = (byte as u64 & 0x7F)
+                .checked_mul(shift)
+                .and_then(|add| value.checked_add(add))
+                .ok_or(BpsError::OutOfBounds)?;
+            if byte & 0x80 != 0 {
+                return Ok(value);
+            }
+            shift = shift.checked_shl(7).ok_or(BpsError::OutOfBounds)?;
+            value = value.checked_add(shift).ok_or(BpsError::OutOfBounds)?;
+        }
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&[u8], BpsError> {
+        let bytes = self
+            .patch
+            .get(self.pos..self.pos + len)
+            .ok_or(BpsError::Truncated)?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    // A signed offset, the low bit being the sign
+    fn offset(&mut self, base: usize) -> Result<usize, BpsError> {
+        let value = self.number()?;
+        let distance = usize::try_from(value >> 1).map_err(|_| BpsError::OutOfBounds)?;
+        let offset = if value & 1 != 0 {
+            base.checked_sub(distance)
+        } else {
+            base.checked_add(distance)
+        };
+        offset.ok_or(BpsError::OutOfBounds)
+    }
+}
+
+fn read_le_u32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+/// Apply a BPS patch to `source`, returning the patched rom.
+///
+/// The CRC-32s of the patch, the source and the result are all checked.
+pub fn apply_bps(source: &[u8], patch: &[u8]) -> Result<Vec<u8>, BpsError> {
+    patch_bps(source, patch, true)
+}
+
+/// Like `apply_bps`, without checking the source CRC-32, for patches made for
+/// a copy of the rom in another byte order or with other header CRCs
+pub fn apply_bps_unverified(source: &[u8], patch: &[u8]) -> Result<Vec<u8>, BpsError> {
+    patch_bps(source, patch, false)
+}
+
+fn patch_bps(source: &[u8], patch: &[u8], verify_source: bool) -> Result<Vec<u8>, BpsError> {
+    if !patch.starts_with(HEADER) {
+        return Err(BpsError::MissingHeader);
+    }
+    if patch.len() < HEADER.len() + FOOTER_SIZE {
+        return Err(BpsError::Truncated);
+    }
+    let end = patch.len() - FOOTER_SIZE;
+    let footer = &patch[end..];
+    let expected = read_le_u32(&footer[8..]);
+    let actual = crc32(&patch[..end + 8]);
+    if expected != actual {
+        return Err(BpsError::PatchChecksum { expected, actual });
+    }
+
+    let mut reader = Reader {
+        patch: &patch[..end],
+        pos: HEADER.len(),
+    };
+    let source_size = reader.number()?;
+    let target_size = reader.number()?;
+    if source_size != source.len() as u64 {
+        return Err(BpsError::SourceSize {
+            expected: source_size,
+            actual: source.len() as u64,
+        });
+    }
+    if verify_source {
+        let expected = read_le_u32(footer);
+        let actual = crc32(source);
+        if expected != actual {
+            return Err(BpsError::SourceChecksum { expected, actual });
+        }
+    }
+    let metadata_size = usize::try_from(reader.number()?).map_err(|_| BpsError::Truncated)?;
+    reader.bytes(metadata_size)?;
+
+    let target_size = usize::try_from(target_size).map_err(|_| BpsError::OutOfBounds)?;
+    let mut target = Vec::with_capacity(target_size);
+    let (mut source_offset, mut target_offset) = (0, 0);
+    while reader.pos < end {
+        let action = reader.number()?;
+        let len = usize::try_from((action >> 2) + 1).map_err(|_| BpsError::OutOfBounds)?;
+        if target.len() + len > target_size {
+            return Err(BpsError::OutOfBounds);
+        }
+        match action & 3 {
+            // SourceRead, from the same offset of the source
+            0 => {
+                let start = target.len();
+                let bytes = source
+                    .get(start..start + len)
+                    .ok_or(BpsError::OutOfBounds)?;
+                target.extend_from_slice(bytes);
+            }
+            // TargetRead, from the patch
+            1 => target.extend_from_slice(reader.bytes(len)?),
+            // SourceCopy, from anywhere in the source
+            2 => {
+                source_offset = reader.offset(source_offset)?;
+                let bytes = source
+                    .get(source_offset..source_offset + len)
+                    .ok_or(BpsError::OutOfBounds)?;
+                target.extend_from_slice(bytes);
+                source_offset += len;
+            }
+            // TargetCopy, from earlier in the target, a byte at a time as it may overlap
+            _ => {
+                target_offset = reader.offset(target_offset)?;
+                for _ in 0..len {
+                    let byte = *target.get(target_offset).ok_or(BpsError::OutOfBounds)?;
+                    target.push(byte);
+                    target_offset += 1;
+                }
+            }
+        }
+    }
+
+    let expected = read_le_u32(&footer[4..]);
+    let actual = crc32(&target);
+    if target.len() != target_size || expected != actual {
+        return Err(BpsError::TargetChecksum { expected, actual });
+    }
+    Ok(target)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &[u8] = b"ABCDEFGH";
+    const TARGET: &[u8] = b"ABxyzGHGHGHBC";
+
+    fn number(patch: &mut Vec<u8>, mut value: u64) {
+        loop {
+            let byte = (value & 0x7F) as u8;
+            value >>= 7;
+            if value == 0 {
+                patch.push(byte | 0x80);
+                return;
+            }
+            patch.push(byte);
+            value -= 1;
+        }
+    }
+
+    fn action(patch: &mut Vec<u8>, kind: u64, len: u64) {
+        number(patch, (len - 1) << 2 | kind);
+    }
+
+    fn offset(patch: &mut Vec<u8>, offset: i64) {
+        number(patch, offset.unsigned_abs() << 1 | u64::from(offset < 0));
+    }
+
+    // A patch of `actions` from a source of `source_size` bytes into a target of
+    // `target_size`, with the given source and target CRC-32s
+    fn build(source_size: u64, target_size: u64, actions: &[u8], crcs: [u32; 2]) -> Vec<u8> {
+        let mut patch = HEADER.to_vec();
+        number(&mut patch, source_size);
+        number(&mut patch, target_size);
+        // No metadata
+        number(&mut patch, 0);
+        patch.extend_from_slice(actions);
+        for crc in crcs {
+            patch.extend_from_slice(&crc.to_le_bytes());
+        }
+        let crc = crc32(&patch);
+        patch.extend_from_slice(&crc.to_le_bytes());
+        patch
+    }
+
+    // SOURCE into TARGET, with each of the four actions
+    fn patch() -> Vec<u8> {
+        let mut actions = Vec::new();
+        // SourceRead "AB"
+        action(&mut actions, 0, 2);
+        // TargetRead "xyz"
+        action(&mut actions, 1, 3);
+        actions.extend_from_slice(b"xyz");
+        // SourceCopy "GH"
+        action(&mut actions, 2, 2);
+        offset(&mut actions, 6);
+        // TargetCopy "GHGH", overlapping itself
+        action(&mut actions, 3, 4);
+        offset(&mut actions, 5);
+        // SourceCopy "BC", going back
+        action(&mut actions, 2, 2);
+        offset(&mut actions, -7);
+        build(8, 13, &actions, [crc32(SOURCE), crc32(TARGET)])
+    }
+
+    #[test]
+    fn applies_every_action() {
+        assert_eq!(apply_bps(SOURCE, &patch()).unwrap(), TARGET);
+    }
+
+    #[test]
+    fn decodes_numbers() {
+        for value in [0, 1, 127, 128, 129, 16_511, 16_512, 1 << 32, u64::MAX >> 8] {
+            let mut encoded = Vec::new();
+            number(&mut encoded, value);
+            let mut reader = Reader {
+                patch: &encoded,
+                pos: 0,
+            };
+            assert_eq!(reader.number(), Ok(value));
+            assert_eq!(reader.pos, encoded.len());
+        }
+        let mut reader = Reader {
+            patch: &[0x00, 0x80],
+            pos: 0,
+        };
+        assert_eq!(reader.number(), Ok(128));
+
+        let mut reader = Reader {
+            patch: &[0x7F; 12],
+            pos: 0,
+        };
+        assert_eq!(reader.number(), Err(BpsError::OutOfBounds));
+        let mut reader = Reader {
+            patch: &[0x00, 0x00],
+            pos: 0,
+        };
+        assert_eq!(reader.number(), Err(BpsError::Truncated));
+    }
+
+    #[test]
+    fn checks_the_checksums() {
+        let wrong_source = b"ABCDEFGX";
+        assert_eq!(
+            apply_bps(wrong_source, &patch()),
+            Err(BpsError::SourceChecksum {
+                expected: crc32(SOURCE),
+                actual: crc32(wrong_source),
+            })
+        );
+        // The source CRC-32 isn't checked, but the result comes out wrong
+        assert_eq!(
+            apply_bps_unverified(b"XBCDEFGH", &patch()),
+            Err(BpsError::TargetChecksum {
+                expected: crc32(TARGET),
+                actual: crc32(b"XBxyzGHGHGHBC"),
+            })
+        );
+
+        let mut damaged = patch();
+        damaged[8] ^= 0xFF;
+        assert!(matches!(
+            apply_bps(SOURCE, &damaged),
+            Err(BpsError::PatchChecksum { .. })
+        ));
+
+        assert_eq!(
+            apply_bps(b"ABCD", &patch()),
+            Err(BpsError::SourceSize {
+                expected: 8,
+                actual: 4
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_out_of_range_copies() {
+        let copy = |kind, len, from| {
+            let mut actions = Vec::new();
+            action(&mut actions, kind, len);
+            offset(&mut actions, from);
+            actions
+        };
+        let mut source_read = Vec::new();
+        action(&mut source_read, 0, 5);
+        let cases = [
+            // SourceCopy past the end of the source
+            copy(2, 4, 6),
+            // SourceCopy before its start
+            copy(2, 1, -1),
+            // TargetCopy of bytes not written yet
+            copy(3, 1, 0),
+            // SourceRead past the target size
+            source_read,
+        ];
+        for actions in cases {
+            let patch = build(8, 4, &actions, [crc32(SOURCE), 0]);
+            assert_eq!(apply_bps(SOURCE, &patch), Err(BpsError::OutOfBounds));
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_patches() {
+        let mut patch = patch();
+        patch[0] = b'X';
+        assert_eq!(apply_bps(SOURCE, &patch), Err(BpsError::MissingHeader));
+        assert_eq!(apply_bps(SOURCE, b"BPS1\0\0\0\0"), Err(BpsError::Truncated));
+
+        // A TargetRead longer than the patch
+        let mut actions = Vec::new();
+        action(&mut actions, 1, 3);
+        actions.push(b'x');
+        let patch = build(8, 3, &actions, [crc32(SOURCE), 0]);
+        assert_eq!(apply_bps(SOURCE, &patch), Err(BpsError::Truncated));
+    }
+}
@@ -0,0 +1,249 @@
+use core::fmt;
+use std::io::{Read, Seek, SeekFrom, Write};
+
+use crate::cic::{detect_ipl3, CicVariant, IPL3_SIZE, IPL3_START};
+use crate::{normalize, swapper, ConversionError, RomType};
+
+/// Start of the region covered by the rom checksum
+pub const CHECKSUM_START: usize = 0x1000;
+/// Length of the region covered by the rom checksum
+pub const CHECKSUM_LENGTH: usize = 0x100000;
+
+// Header offset of CRC1, CRC2 follows it
+const CRC_OFFSET: u64 = 0x10;
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum ChecksumError {
+    /// The bootcode doesn't match a known CIC, carries its CRC-32
+    UnknownBootcode(u32),
+    /// The rom is too small to contain the checksummed region
+    TooSmall(u64),
+    /// The header CRCs don't match the rom contents
+    Mismatch {
+        stored: (u32, u32),
+        computed: (u32, u32),
+    },
+}
+
+impl fmt::Display for ChecksumError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ChecksumError::UnknownBootcode(crc) => {
+                write!(f, "Unknown bootcode (CRC: 0x{:08X})", crc)
+            }
+            ChecksumError::TooSmall(size) => {
+                write!(f, "Rom is too small to checksum ({} bytes)", size)
+            }
+            ChecksumError::Mismatch { stored, computed } => write!(
+                f,
+                "CRC mismatch, stored 0x{:08X} 0x{:08X}, computed 0x{:08X} 0x{:08X}",
+                stored.0, stored.1, computed.0, computed.1
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ChecksumError {}
+
+fn read_be_u32(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+/// Calculate CRC1 and CRC2 for a big-endian rom using the given CIC's algorithm
+pub fn calculate_crc_for(rom: &[u8], cic: CicVariant) -> Result<(u32, u32), ChecksumError> {
+    let end = CHECKSUM_START + CHECKSUM_LENGTH;
+    if rom.len() < end {
+        return Err(ChecksumError::TooSmall(rom.len() as u64));
+    }
+
+    let seed = cic.checksum_seed();
+    let (mut t1, mut t2, mut t3, mut t4, mut t5, mut t6) = (seed, seed, seed, seed, seed, seed);
+
+    for i in (CHECKSUM_START..end).step_by(4) {
+        let d = read_be_u32(&rom[i..]);
+        let (sum, carry) = t6.overflowing_add(d);
+        if carry {
+            t4 = t4.wrapping_add(1);
+        }
+        t6 = sum;
+        t3 ^= d;
+        let r = d.rotate_left(d & 0x1F);
+        t5 = t5.wrapping_add(r);
+        if t2 > d {
+            t2 ^= r;
+        } else {
+            t2 ^= t6 ^ d;
+        }
+
+        if cic == CicVariant::Cic6105 {
+            let offset = IPL3_START + 0x0710 + (i & 0xFF);
+            t1 = t1.wrapping_add(read_be_u32(&rom[offset..]) ^ d);
+        } else {
+            t1 = t1.wrapping_add(t5 ^ d);
+        }
+    }
+
+    Ok(match cic {
+        CicVariant::Cic6103 => ((t6 ^ t4).wrapping_add(t3), (t5 ^ t2).wrapping_add(t1)),
+        CicVariant::Cic6106 => (
+            t6.wrapping_mul(t4).wrapping_add(t3),
+            t5.wrapping_mul(t2).wrapping_add(t1),
+        ),
+        _ => (t6 ^ t4 ^ t3, t5 ^ t2 ^ t1),
+    })
+}
+
+/// Calculate CRC1 and CRC2 for a big-endian rom, detecting the CIC from its bootcode
+pub fn calculate_crc(rom: &[u8]) -> Result<(u32, u32), ChecksumError> {
+    let Some(bootcode) = rom.get(IPL3_START..IPL3_START + IPL3_SIZE) else {
+        return Err(ChecksumError::TooSmall(rom.len() as u64));
+    };
+    let Some(cic) = detect_ipl3(bootcode) else {
+        return Err(ChecksumError::UnknownBootcode(crate::crc32::crc32(
+            bootcode,
+        )));
+    };
+    calculate_crc_for(rom, cic)
+}
+
+/// Check the CRCs stored in the header of a big-endian rom against its contents,
+/// returning them when they match
+pub fn verify_crc(rom: &[u8]) -> Result<(u32, u32), ChecksumError> {
+    let computed = calculate_crc(rom)?;
+    let stored = (read_be_u32(&rom[0x10..]), read_be_u32(&rom[0x14..]));
+    if stored != computed {
+        return Err(ChecksumError::Mismatch { stored, computed });
+    }
+    Ok(computed)
+}
+
+/// Read the checksummed part of a rom (from the start) and normalize it to big-endian
+pub fn read_checksum_region<R: Read>(
+    reader: &mut R,
+    rom_type: RomType,
+) -> std::io::Result<Vec<u8>> {
+    let mut rom = Vec::with_capacity(CHECKSUM_START + CHECKSUM_LENGTH);
+    reader
+        .take((CHECKSUM_START + CHECKSUM_LENGTH) as u64)
+        .read_to_end(&mut rom)?;
+    normalize(&mut rom, rom_type);
+    Ok(rom)
+}
+
+/// Recalculate the CRCs of a rom file and patch them into its header
+pub fn fix_crc<F: Read + Write + Seek>(
+    file: &mut F,
+    rom_type: RomType,
+) -> Result<(u32, u32), ConversionError> {
+    file.seek(SeekFrom::Start(0))?;
+    let rom = read_checksum_region(file, rom_type)?;
+    let (crc1, crc2) = calculate_crc(&rom)?;
+
+    // The CRCs are word aligned, so each one only needs its bytes reordered
+    let mut crc1_bytes = crc1.to_be_bytes();
+    let mut crc2_bytes = crc2.to_be_bytes();
+    swapper(&mut crc1_bytes, RomType::BigEndian, rom_type);
+    swapper(&mut crc2_bytes, RomType::BigEndian, rom_type);
+
+    file.seek(SeekFrom::Start(CRC_OFFSET))?;
+    file.write_all(&crc1_bytes)?;
+    file.write_all(&crc2_bytes)?;
+    file.flush()?;
+
+    Ok((crc1, crc2))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::convert_bytes;
+
+    // A big-endian rom just large enough to checksum, of xorshift words
+    fn rom() -> Vec<u8> {
+        let mut state = 0x1234_5678u32;
+        let mut rom = Vec::with_capacity(CHECKSUM_START + CHECKSUM_LENGTH);
+        while rom.len() < CHECKSUM_START + CHECKSUM_LENGTH {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            rom.extend_from_slice(&state.to_be_bytes());
+        }
+        rom
+    }
+
+    #[test]
+    fn crcs_of_each_cic() {
+        // Worked out with a separate implementation of n64crc.c
+        let rom = rom();
+        for (cic, expected) in [
+            (CicVariant::Cic6101, (0x50F87305, 0xC42613B2)),
+            (CicVariant::Cic6102, (0x50F87305, 0xC42613B2)),
+            (CicVariant::Cic6103, (0x45CB6248, 0x9CA3D7E4)),
+            (CicVariant::Cic6105, (0x791D1537, 0x35E5B421)),
+            (CicVariant::Cic6106, (0x63AED81B, 0xE276EA71)),
+        ] {
+            assert_eq!(calculate_crc_for(&rom, cic), Ok(expected), "{}", cic);
+        }
+    }
+
+    #[test]
+    fn too_small_to_checksum() {
+        let rom = rom();
+        let short = &rom[..rom.len() - 4];
+        assert_eq!(
+            calculate_crc_for(short, CicVariant::Cic6102),
+            Err(ChecksumError::TooSmall(short.len() as u64))
+        );
+    }
+
+    // The rom with a bootcode whose CRC-32 is that of the 6102's IPL3
+    fn rom_6102() -> Vec<u8> {
+        let mut rom = rom();
+        rom[IPL3_START..IPL3_START + IPL3_SIZE].fill(0);
+        rom[IPL3_START + IPL3_SIZE - 4..IPL3_START + IPL3_SIZE]
+            .copy_from_slice(&[0x89, 0x26, 0x79, 0xFB]);
+        rom
+    }
+
+    #[test]
+    fn verify_against_header() {
+        let mut rom = rom_6102();
+        rom[0x10..0x14].copy_from_slice(&0x50F87305u32.to_be_bytes());
+        rom[0x14..0x18].copy_from_slice(&0xC42613B2u32.to_be_bytes());
+        assert_eq!(verify_crc(&rom), Ok((0x50F87305, 0xC42613B2)));
+
+        rom[0x14] ^= 1;
+        assert_eq!(
+            verify_crc(&rom),
+            Err(ChecksumError::Mismatch {
+                stored: (0x50F87305, 0xC52613B2),
+                computed: (0x50F87305, 0xC42613B2),
+            })
+        );
+    }
+
+    #[test]
+    fn unknown_bootcode() {
+        let mut rom = rom_6102();
+        rom[IPL3_START] = 1;
+        assert!(matches!(
+            calculate_crc(&rom),
+            Err(ChecksumError::UnknownBootcode(_))
+        ));
+    }
+
+    #[test]
+    fn fix_crc_in_byte_order() {
+        let mut rom = rom_6102();
+        convert_bytes(RomType::BigEndian, RomType::ByteSwap, &mut rom).unwrap();
+        let mut file = std::io::Cursor::new(rom);
+        assert_eq!(
+            fix_crc(&mut file, RomType::ByteSwap).unwrap(),
+            (0x50F87305, 0xC42613B2)
+        );
+
+        let mut rom = file.into_inner();
+        normalize(&mut rom, RomType::ByteSwap);
+        assert_eq!(verify_crc(&rom), Ok((0x50F87305, 0xC42613B2)));
+    }
+}
@@ -0,0 +1,99 @@
+use core::fmt;
+
+use crate::crc32::crc32;
+
+/// Offset of the IPL3 bootcode, right after the header
+pub const IPL3_START: usize = 0x40;
+/// Size of the IPL3 bootcode
+pub const IPL3_SIZE: usize = 0x1000 - IPL3_START;
+
+/// CIC lockout chip variants, identified by their IPL3 bootcode
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum CicVariant {
+    Cic6101,
+    Cic6102,
+    Cic6103,
+    Cic6105,
+    Cic6106,
+}
+
+impl fmt::Display for CicVariant {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CicVariant::Cic6101 => write!(f, "CIC-6101"),
+            CicVariant::Cic6102 => write!(f, "CIC-6102"),
+            CicVariant::Cic6103 => write!(f, "CIC-6103"),
+            CicVariant::Cic6105 => write!(f, "CIC-6105"),
+            CicVariant::Cic6106 => write!(f, "CIC-6106"),
+        }
+    }
+}
+
+impl CicVariant {
+    /// Chips sharing this bootcode, NTSC then PAL
+    pub fn chips(&self) -> &str {
+        match *self {
+            CicVariant::Cic6101 => "NUS-6101",
+            CicVariant::Cic6102 => "NUS-6102 / NUS-7101",
+            CicVariant::Cic6103 => "NUS-6103 / NUS-7103",
+            CicVariant::Cic6105 => "NUS-6105 / NUS-7105",
+            CicVariant::Cic6106 => "NUS-6106 / NUS-7106",
+        }
+    }
+
+    /// Initial value of the rom checksum registers
+    pub fn checksum_seed(&self) -> u32 {
+        match *self {
+            CicVariant::Cic6101 | CicVariant::Cic6102 => 0xF8CA4DDC,
+            CicVariant::Cic6103 => 0xA3886759,
+            CicVariant::Cic6105 => 0xDF26F436,
+            CicVariant::Cic6106 => 0x1FEA617A,
+        }
+    }
+}
+
+/// Identify the CIC variant from the (big-endian) IPL3 bootcode
+pub fn detect_ipl3(body: &[u8]) -> Option<CicVariant> {
+    match crc32(body.get(..IPL3_SIZE)?) {
+        0x6170A4A1 => Some(CicVariant::Cic6101),
+        0x90BB6CB5 => Some(CicVariant::Cic6102),
+        0x0B050EE0 => Some(CicVariant::Cic6103),
+        0x98BC2C86 => Some(CicVariant::Cic6105),
+        0xACC8580A => Some(CicVariant::Cic6106),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A zero-filled bootcode with its last word chosen to give the CRC-32 of a known IPL3
+    fn bootcode(tail: [u8; 4]) -> Vec<u8> {
+        let mut body = vec![0; IPL3_SIZE];
+        body[IPL3_SIZE - 4..].copy_from_slice(&tail);
+        body
+    }
+
+    #[test]
+    fn detects_each_cic() {
+        for (tail, cic) in [
+            ([0xE2, 0x66, 0x56, 0xB7], Some(CicVariant::Cic6101)),
+            ([0x89, 0x26, 0x79, 0xFB], Some(CicVariant::Cic6102)),
+            ([0x87, 0x5E, 0x92, 0x3F], Some(CicVariant::Cic6103)),
+            ([0x8F, 0xE6, 0x5E, 0x21], Some(CicVariant::Cic6105)),
+            ([0xED, 0x93, 0xDC, 0xB9], Some(CicVariant::Cic6106)),
+            ([0, 0, 0, 0], None),
+        ] {
+            assert_eq!(detect_ipl3(&bootcode(tail)), cic, "{:02X?}", tail);
+        }
+    }
+
+    #[test]
+    fn only_the_bootcode_counts() {
+        let mut body = bootcode([0x89, 0x26, 0x79, 0xFB]);
+        body.extend([0xFF; 4]);
+        assert_eq!(detect_ipl3(&body), Some(CicVariant::Cic6102));
+        assert_eq!(detect_ipl3(&body[..IPL3_SIZE - 1]), None);
+    }
+}
@@ -0,0 +1,50 @@
+use std::env;
+use std::io::{self, IsTerminal};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static STDOUT: AtomicBool = AtomicBool::new(false);
+static STDERR: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Color {
+    Red,
+    Green,
+    Yellow,
+    Cyan,
+}
+
+impl Color {
+    fn code(&self) -> &str {
+        match *self {
+            Color::Red => "31",
+            Color::Green => "32",
+            Color::Yellow => "33",
+            Color::Cyan => "36",
+        }
+    }
+}
+
+/// Enable colors for the streams that are terminals, unless disabled by flag or NO_COLOR
+pub fn init(no_color: bool) {
+    let allowed = !no_color && env::var_os("NO_COLOR").is_none_or(|value| value.is_empty());
+    STDOUT.store(allowed && io::stdout().is_terminal(), Ordering::Relaxed);
+    STDERR.store(allowed && io::stderr().is_terminal(), Ordering::Relaxed);
+}
+
+fn paint(enabled: &AtomicBool, color: Color, text: &str) -> String {
+    if enabled.load(Ordering::Relaxed) {
+        format!("\x1b[{}m{}\x1b[0m", color.code(), text)
+    } else {
+        text.to_string()
+    }
+}
+
+/// Color text to print on stdout
+pub fn stdout(color: Color, text: &str) -> String {
+    paint(&STDOUT, color, text)
+}
+
+/// Color text to print on stderr
+pub fn stderr(color: Color, text: &str) -> String {
+    paint(&STDERR, color, text)
+}
@@ -0,0 +1,168 @@
+use std::io::{self, BufReader, Read};
+use std::path::Path;
+
+use n64swap::{
+    format_size, identify_header, merge_roms, normalize, prepend_magic, search_rom, split_paths,
+    split_rom, ConversionError, RomType, HEADER_SIZE,
+};
+
+use crate::dump;
+use crate::input::open_input;
+use crate::{Args, STDIO};
+
+/// Print the (possibly truncated) header of a rom for --dump-header
+pub fn dump_file_header(args: &Args, filename: &Path) -> Result<(), ConversionError> {
+    let mut header = Vec::with_capacity(HEADER_SIZE);
+    let reader: Box<dyn Read> = if filename == Path::new(STDIO) {
+        Box::new(io::stdin().lock())
+    } else {
+        Box::new(open_input(filename)?)
+    };
+    reader.take(HEADER_SIZE as u64).read_to_end(&mut header)?;
+
+    let magic = header
+        .get(..4)
+        .and_then(|magic| <[u8; 4]>::try_from(magic).ok());
+    match magic.and_then(|magic| identify_header(&magic)) {
+        Some(rom_type) => normalize(&mut header, rom_type),
+        None => warning!(
+            "{}: header not recognized, showing the bytes as they are",
+            filename.display()
+        ),
+    }
+    if args.filenames.len() > 1 {
+        println!("{}:", filename.display());
+    }
+    print!("{}", dump::dump_header(&header));
+    Ok(())
+}
+
+/// Write a headerless rom with the magic of --inject-header in front, next to it
+/// unless --output says otherwise
+pub fn inject_file_header(
+    args: &Args,
+    filename: &Path,
+    rom_type: RomType,
+) -> Result<(), ConversionError> {
+    let outfilename = args
+        .destination_filename
+        .clone()
+        .unwrap_or_else(|| filename.with_extension(&rom_type.get_file_ext()[1..]));
+    if filename == outfilename {
+        return Err(ConversionError::InputOutputSameFile(outfilename));
+    }
+    if !args.force && outfilename.exists() {
+        return Err(ConversionError::OutputExists(outfilename));
+    }
+    if args.dry_run {
+        println!(
+            "Would add a {} header to {} -> {}",
+            rom_type.name(),
+            filename.display(),
+            outfilename.display()
+        );
+        return Ok(());
+    }
+
+    prepend_magic(filename, &outfilename, rom_type)?;
+    success!(
+        args,
+        "Added a {} header to {} -> {}",
+        rom_type.name(),
+        filename.display(),
+        outfilename.display()
+    );
+    Ok(())
+}
+
+/// Split a rom into --split sized chunks
+pub fn split_file(args: &Args, filename: &Path, chunk_size: u64) -> Result<(), ConversionError> {
+    if filename == Path::new(STDIO) {
+        let error = io::Error::new(io::ErrorKind::InvalidInput, "--split can't read stdin");
+        return Err(error.into());
+    }
+    let dir = match &args.output_dir {
+        Some(dir) => dir.clone(),
+        None => filename.parent().unwrap_or(Path::new("")).to_path_buf(),
+    };
+    let paths = split_paths(filename, chunk_size, &dir)?;
+    if !args.force {
+        if let Some(path) = paths.iter().find(|path| path.exists()) {
+            return Err(ConversionError::OutputExists(path.clone()));
+        }
+    }
+    if args.dry_run {
+        for path in &paths {
+            println!("Would write {}", path.display());
+        }
+        return Ok(());
+    }
+
+    split_rom(filename, chunk_size, &dir)?;
+    success!(
+        args,
+        "Split {} into {} chunks of {}",
+        filename.display(),
+        paths.len(),
+        format_size(chunk_size)
+    );
+    Ok(())
+}
+
+/// Join the input files into the --output file for --merge
+pub fn merge_files(args: &Args) -> Result<(), ConversionError> {
+    let Some(outfilename) = args.destination_filename.as_deref() else {
+        return Ok(());
+    };
+    if args.filenames.iter().any(|name| name == Path::new(STDIO)) || outfilename == Path::new(STDIO)
+    {
+        let error = io::Error::new(io::ErrorKind::InvalidInput, "--merge can't use stdio");
+        return Err(error.into());
+    }
+    if args.filenames.iter().any(|name| name == outfilename) {
+        return Err(ConversionError::InputOutputSameFile(
+            outfilename.to_path_buf(),
+        ));
+    }
+    if !args.force && outfilename.exists() {
+        return Err(ConversionError::OutputExists(outfilename.to_path_buf()));
+    }
+    if args.dry_run {
+        println!(
+            "Would merge {} chunks -> {}",
+            args.filenames.len(),
+            outfilename.display()
+        );
+        return Ok(());
+    }
+
+    merge_roms(&args.filenames, outfilename)?;
+    success!(
+        args,
+        "Merged {} chunks -> {}",
+        args.filenames.len(),
+        outfilename.display()
+    );
+    Ok(())
+}
+
+/// Print where --search finds its pattern in a rom
+pub fn search_file(args: &Args, filename: &Path, pattern: &[u8]) -> Result<(), ConversionError> {
+    let mut reader: Box<dyn Read> = if filename == Path::new(STDIO) {
+        Box::new(io::stdin().lock())
+    } else {
+        Box::new(BufReader::new(open_input(filename)?))
+    };
+    let matches = search_rom(&mut reader, pattern)?;
+    if matches.is_empty() {
+        info!(args, "No matches in {}", filename.display());
+    }
+    for offset in matches {
+        if args.filenames.len() > 1 {
+            println!("{}: 0x{:08X}", filename.display(), offset);
+        } else {
+            println!("0x{:08X}", offset);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,242 @@
+use clap::{Command, ValueEnum};
+
+/// Shells --generate-completions writes a script for
+#[derive(Debug, PartialEq, Copy, Clone, ValueEnum)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+    Powershell,
+    Elvish,
+}
+
+impl Shell {
+    /// How to load the generated script, printed to stderr
+    pub fn instructions(&self, name: &str) -> String {
+        match *self {
+            Shell::Bash => format!(
+                "Add `source <({} --generate-completions bash)` to ~/.bashrc",
+                name
+            ),
+            Shell::Zsh => format!(
+                "Save the script as _{} in a directory on $fpath, or add `source <({} --generate-completions zsh)` to ~/.zshrc",
+                name, name
+            ),
+            Shell::Fish => format!(
+                "Save the script as ~/.config/fish/completions/{}.fish, or run `{} --generate-completions fish | source`",
+                name, name
+            ),
+            Shell::Powershell => format!(
+                "Add `{} --generate-completions powershell | Out-String | Invoke-Expression` to $PROFILE",
+                name
+            ),
+            Shell::Elvish => format!(
+                "Add `eval ({} --generate-completions elvish | slurp)` to ~/.config/elvish/rc.elv",
+                name
+            ),
+        }
+    }
+}
+
+// An option of the command, as the scripts need it
+struct Flag {
+    short: Option<char>,
+    long: Option<String>,
+    help: String,
+    takes_value: bool,
+    values: Vec<String>,
+}
+
+impl Flag {
+    fn names(&self) -> Vec<String> {
+        let short = self.short.map(|short| format!("-{}", short));
+        let long = self.long.as_ref().map(|long| format!("--{}", long));
+        short.into_iter().chain(long).collect()
+    }
+}
+
+fn flags(cmd: &mut Command) -> Vec<Flag> {
+    cmd.build();
+    cmd.get_arguments()
+        .filter(|arg| !arg.is_positional() && !arg.is_hide_set())
+        .map(|arg| Flag {
+            short: arg.get_short(),
+            long: arg.get_long().map(str::to_string),
+            help: arg
+                .get_help()
+                .map(|help| help.to_string().lines().next().unwrap_or("").to_string())
+                .unwrap_or_default(),
+            takes_value: arg.get_action().takes_values(),
+            values: arg
+                .get_possible_values()
+                .iter()
+                .filter(|value| !value.is_hide_set())
+                .map(|value| value.get_name().to_string())
+                .collect(),
+        })
+        .collect()
+}
+
+/// The completion script for `shell`
+pub fn generate(shell: Shell, cmd: &mut Command) -> String {
+    let name = cmd.get_name().to_string();
+    let flags = flags(cmd);
+    match shell {
+        Shell::Bash => bash(&name, &flags),
+        Shell::Zsh => zsh(&name, &flags),
+        Shell::Fish => fish(&name, &flags),
+        Shell::Powershell => powershell(&name, &flags),
+        Shell::Elvish => elvish(&name, &flags),
+    }
+}
+
+fn all_names(flags: &[Flag]) -> String {
+    let names: Vec<String> = flags.iter().flat_map(Flag::names).collect();
+    names.join(" ")
+}
+
+fn bash(name: &str, flags: &[Flag]) -> String {
+    let function = format!("_{}", name.replace('-', "_"));
+    let mut script = format!(
+        "{}() {{\n    local cur=\"${{COMP_WORDS[COMP_CWORD]}}\"\n    local prev=\"${{COMP_WORDS[COMP_CWORD-1]}}\"\n    case \"$prev\" in\n",
+        function
+    );
+    for flag in flags.iter().filter(|flag| !flag.values.is_empty()) {
+        script.push_str(&format!(
+            "        {})\n            COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))\n            return 0\n            ;;\n",
+            flag.names().join("|"),
+            flag.values.join(" ")
+        ));
+    }
+    script.push_str(&format!(
+        "    esac\n    if [[ \"$cur\" == -* ]]; then\n        COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))\n        return 0\n    fi\n    COMPREPLY=($(compgen -f -- \"$cur\"))\n}}\n\ncomplete -F {} -o filenames {}\n",
+        all_names(flags),
+        function,
+        name
+    ));
+    script
+}
+
+fn zsh(name: &str, flags: &[Flag]) -> String {
+    let help = |help: &str| {
+        help.replace('\'', "'\\''")
+            .replace('[', "\\[")
+            .replace(']', "\\]")
+            .replace(':', "\\:")
+    };
+    let mut script = format!(
+        "#compdef {}\n\n_{}() {{\n    _arguments -s \\\n",
+        name, name
+    );
+    for flag in flags {
+        let value = if !flag.values.is_empty() {
+            format!(":value:({})", flag.values.join(" "))
+        } else if flag.takes_value {
+            ":value:_files".to_string()
+        } else {
+            String::new()
+        };
+        if let Some(short) = flag.short {
+            let suffix = if flag.takes_value { "+" } else { "" };
+            script.push_str(&format!(
+                "        '-{}{}[{}]{}' \\\n",
+                short,
+                suffix,
+                help(&flag.help),
+                value
+            ));
+        }
+        if let Some(long) = &flag.long {
+            let suffix = if flag.takes_value { "=" } else { "" };
+            script.push_str(&format!(
+                "        '--{}{}[{}]{}' \\\n",
+                long,
+                suffix,
+                help(&flag.help),
+                value
+            ));
+        }
+    }
+    script.push_str(&format!(
+        "        '*:file:_files'\n}}\n\nif [ \"$funcstack[1]\" = \"_{}\" ]; then\n    _{} \"$@\"\nelse\n    compdef _{} {}\nfi\n",
+        name, name, name, name
+    ));
+    script
+}
+
+fn fish(name: &str, flags: &[Flag]) -> String {
+    let quote = |text: &str| format!("'{}'", text.replace('\\', "\\\\").replace('\'', "\\'"));
+    let mut script = String::new();
+    for flag in flags {
+        let mut line = format!("complete -c {}", name);
+        if let Some(short) = flag.short {
+            line.push_str(&format!(" -s {}", short));
+        }
+        if let Some(long) = &flag.long {
+            line.push_str(&format!(" -l {}", long));
+        }
+        if !flag.help.is_empty() {
+            line.push_str(&format!(" -d {}", quote(&flag.help)));
+        }
+        if !flag.values.is_empty() {
+            line.push_str(&format!(" -x -a {}", quote(&flag.values.join(" "))));
+        } else if flag.takes_value {
+            line.push_str(" -r -F");
+        }
+        script.push_str(&line);
+        script.push('\n');
+    }
+    script
+}
+
+fn powershell(name: &str, flags: &[Flag]) -> String {
+    let quote = |text: &str| format!("'{}'", text.replace('\'', "''"));
+    let list = |items: Vec<String>| {
+        items
+            .iter()
+            .map(|item| quote(item))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let mut script = format!(
+        "using namespace System.Management.Automation\n\nRegister-ArgumentCompleter -Native -CommandName {} -ScriptBlock {{\n    param($wordToComplete, $commandAst, $cursorPosition)\n\n    $elements = $commandAst.CommandElements\n    $previous = if ($wordToComplete) {{ $elements[-2] }} else {{ $elements[-1] }}\n    $values = switch (\"$previous\") {{\n",
+        quote(name)
+    );
+    for flag in flags.iter().filter(|flag| !flag.values.is_empty()) {
+        script.push_str(&format!(
+            "        {{ $_ -in {} }} {{ {}; break }}\n",
+            list(flag.names()),
+            list(flag.values.clone())
+        ));
+    }
+    let names: Vec<String> = flags.iter().flat_map(Flag::names).collect();
+    script.push_str(&format!(
+        "        default {{ {} }}\n    }}\n    $values | Where-Object {{ $_ -like \"$wordToComplete*\" }} | ForEach-Object {{\n        [CompletionResult]::new($_, $_, [CompletionResultType]::ParameterValue, $_)\n    }}\n}}\n",
+        list(names)
+    ));
+    script
+}
+
+fn elvish(name: &str, flags: &[Flag]) -> String {
+    let mut script = format!(
+        "use str\n\nset edit:completion:arg-completer[{}] = {{|@words|\n    var previous = ''\n    if (> (count $words) 2) {{\n        set previous = $words[-2]\n    }}\n",
+        name
+    );
+    for flag in flags.iter().filter(|flag| !flag.values.is_empty()) {
+        let tests: Vec<String> = flag
+            .names()
+            .iter()
+            .map(|name| format!("(eq $previous {})", name))
+            .collect();
+        script.push_str(&format!(
+            "    if (or {}) {{\n        put {}\n        return\n    }}\n",
+            tests.join(" "),
+            flag.values.join(" ")
+        ));
+    }
+    script.push_str(&format!(
+        "    if (str:has-prefix $words[-1] -) {{\n        put {}\n    }} else {{\n        edit:complete-filename $words[-1]\n    }}\n}}\n",
+        all_names(flags)
+    ));
+    script
+}
@@ -0,0 +1,400 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use clap::ValueEnum;
+use n64swap::RomType;
+
+use crate::hash::HashAlgorithm;
+
+/// Defaults from `~/.config/n64swap/config.toml` or the environment, command line
+/// flags win over these
+#[derive(Debug, Default)]
+pub struct Config {
+    pub default_romtype: Option<RomType>,
+    pub output_dir: Option<String>,
+    pub quiet: Option<bool>,
+    pub force: Option<bool>,
+    pub hash_algorithm: Vec<HashAlgorithm>,
+    /// In bytes
+    pub buffer_size: Option<usize>,
+}
+
+impl Config {
+    /// Take what this config leaves unset from `fallback`
+    pub fn or(self, fallback: Config) -> Config {
+        Config {
+            default_romtype: self.default_romtype.or(fallback.default_romtype),
+            output_dir: self.output_dir.or(fallback.output_dir),
+            quiet: self.quiet.or(fallback.quiet),
+            force: self.force.or(fallback.force),
+            hash_algorithm: match self.hash_algorithm.is_empty() {
+                true => fallback.hash_algorithm,
+                false => self.hash_algorithm,
+            },
+            buffer_size: self.buffer_size.or(fallback.buffer_size),
+        }
+    }
+}
+
+// The subset of TOML values the config uses
+enum Value {
+    String(String),
+    Integer(i64),
+    Boolean(bool),
+    Array(Vec<Value>),
+}
+
+// A directory from an environment variable, unset when empty
+fn env_dir(name: &str) -> Option<PathBuf> {
+    env::var_os(name)
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+}
+
+// Where the config file can be, the platform's usual place first
+fn candidate_paths() -> Vec<PathBuf> {
+    let xdg =
+        env_dir("XDG_CONFIG_HOME").or_else(|| env_dir("HOME").map(|home| home.join(".config")));
+    let mut bases = Vec::new();
+    #[cfg(windows)]
+    bases.extend(env_dir("APPDATA"));
+    bases.extend(xdg);
+    #[cfg(target_os = "macos")]
+    bases.extend(env_dir("HOME").map(|home| home.join("Library").join("Application Support")));
+    bases
+        .into_iter()
+        .map(|base| base.join("n64swap").join("config.toml"))
+        .collect()
+}
+
+/// `$XDG_CONFIG_HOME/n64swap/config.toml`, or under `~/.config` without it. On macOS
+/// `~/Library/Application Support/n64swap/config.toml` is used when only that one
+/// exists, on Windows `%APPDATA%\n64swap\config.toml` comes first.
+pub fn config_path() -> Option<PathBuf> {
+    let candidates = candidate_paths();
+    candidates
+        .iter()
+        .find(|path| path.exists())
+        .or(candidates.first())
+        .cloned()
+}
+
+/// Load the config file, a missing file is an empty config
+pub fn load() -> Result<Config, String> {
+    let Some(path) = config_path() else {
+        return Ok(Config::default());
+    };
+    match fs::read_to_string(&path) {
+        Ok(text) => parse(&text)
+            .map_err(|error| format!("Invalid config file {}, {}", path.display(), error)),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(Config::default()),
+        Err(error) => Err(format!("Unable to read {}: {}", path.display(), error)),
+    }
+}
+
+// An environment variable, unset when empty
+fn env_var(name: &str) -> Result<Option<String>, String> {
+    match env::var(name) {
+        Ok(value) if value.is_empty() => Ok(None),
+        Ok(value) => Ok(Some(value)),
+        Err(env::VarError::NotPresent) => Ok(None),
+        Err(env::VarError::NotUnicode(_)) => Err(format!("{} isn't valid UTF-8", name)),
+    }
+}
+
+fn env_bool(name: &str) -> Result<Option<bool>, String> {
+    let Some(value) = env_var(name)? else {
+        return Ok(None);
+    };
+    match value.to_ascii_lowercase().as_str() {
+        "1" | "true" | "yes" | "on" => Ok(Some(true)),
+        "0" | "false" | "no" | "off" => Ok(Some(false)),
+        _ => Err(format!("{} must be true or false, not '{}'", name, value)),
+    }
+}
+
+/// Defaults from the N64SWAP_* environment variables, which win over the config file
+pub fn from_env() -> Result<Config, String> {
+    let mut config = Config::default();
+    if let Some(value) = env_var("N64SWAP_ROMTYPE")? {
+        config.default_romtype = Some(parse_enum("N64SWAP_ROMTYPE", &value)?);
+    }
+    config.output_dir = env_var("N64SWAP_OUTPUT_DIR")?;
+    if let Some(value) = env_var("N64SWAP_BUFFER_SIZE")? {
+        let size =
+            crate::parse_size(&value).map_err(|error| format!("N64SWAP_BUFFER_SIZE: {}", error))?;
+        config.buffer_size = Some(size);
+    }
+    config.quiet = env_bool("N64SWAP_QUIET")?;
+    config.force = env_bool("N64SWAP_FORCE")?;
+    Ok(config)
+}
+
+// Strip a comment, leaving any # inside a string alone
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    let mut escaped = false;
+    for (idx, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            '#' if !in_string => return &line[..idx],
+            _ => {}
+        }
+    }
+    line
+}
+
+fn parse_string(text: &str) -> Result<(String, &str), String> {
+    let mut value = String::new();
+    let mut chars = text.char_indices().skip(1);
+    while let Some((idx, c)) = chars.next() {
+        match c {
+            '"' => return Ok((value, &text[idx + 1..])),
+            '\\' => match chars.next() {
+                Some((_, '"')) => value.push('"'),
+                Some((_, '\\')) => value.push('\\'),
+                Some((_, 'n')) => value.push('\n'),
+                Some((_, 't')) => value.push('\t'),
+                _ => return Err("unsupported escape in string".to_string()),
+            },
+            c => value.push(c),
+        }
+    }
+    Err("unterminated string".to_string())
+}
+
+// Parse one value, returning it with the unparsed rest of the line
+fn parse_value(text: &str) -> Result<(Value, &str), String> {
+    let text = text.trim_start();
+    if text.starts_with('"') {
+        let (value, rest) = parse_string(text)?;
+        return Ok((Value::String(value), rest));
+    }
+    if let Some(mut rest) = text.strip_prefix('[') {
+        let mut values = Vec::new();
+        loop {
+            rest = rest.trim_start();
+            if let Some(after) = rest.strip_prefix(']') {
+                return Ok((Value::Array(values), after));
+            }
+            if rest.is_empty() {
+                return Err("unterminated array".to_string());
+            }
+            let (value, after) = parse_value(rest)?;
+            values.push(value);
+            let after = after.trim_start();
+            rest = match after.strip_prefix(',') {
+                Some(after) => after,
+                None if after.starts_with(']') => after,
+                None => return Err("expected ',' or ']' in array".to_string()),
+            };
+        }
+    }
+
+    let end = text.find([',', ']']).unwrap_or(text.len());
+    let (word, rest) = (text[..end].trim(), &text[end..]);
+    match word {
+        "true" => Ok((Value::Boolean(true), rest)),
+        "false" => Ok((Value::Boolean(false), rest)),
+        _ => word
+            .replace('_', "")
+            .parse()
+            .map(|number| (Value::Integer(number), rest))
+            .map_err(|_| format!("unsupported value '{}'", word)),
+    }
+}
+
+fn expect_string(key: &str, value: Value) -> Result<String, String> {
+    match value {
+        Value::String(value) => Ok(value),
+        _ => Err(format!("{} must be a string", key)),
+    }
+}
+
+fn parse_enum<T: ValueEnum>(key: &str, value: &str) -> Result<T, String> {
+    T::from_str(value, true).map_err(|_| format!("invalid {} '{}'", key, value))
+}
+
+fn parse(text: &str) -> Result<Config, String> {
+    let mut config = Config::default();
+
+    for (number, line) in text.lines().enumerate() {
+        let line = strip_comment(line).trim();
+        if line.is_empty() {
+            continue;
+        }
+        let error = |message: String| format!("line {}: {}", number + 1, message);
+
+        let Some((key, value)) = line.split_once('=') else {
+            return Err(error("expected key = value".to_string()));
+        };
+        let key = key.trim();
+        let (value, rest) = parse_value(value).map_err(error)?;
+        if !rest.trim().is_empty() {
+            return Err(error(format!("unexpected '{}'", rest.trim())));
+        }
+
+        match key {
+            "default_romtype" => {
+                let value = expect_string(key, value).map_err(error)?;
+                config.default_romtype = Some(parse_enum(key, &value).map_err(error)?);
+            }
+            "output_dir" => config.output_dir = Some(expect_string(key, value).map_err(error)?),
+            "quiet" => match value {
+                Value::Boolean(quiet) => config.quiet = Some(quiet),
+                _ => return Err(error("quiet must be true or false".to_string())),
+            },
+            "hash_algorithm" => {
+                let names = match value {
+                    Value::Array(values) => values
+                        .into_iter()
+                        .map(|value| expect_string(key, value))
+                        .collect::<Result<Vec<_>, _>>()
+                        .map_err(error)?,
+                    value => vec![expect_string(key, value).map_err(error)?],
+                };
+                for name in names {
+                    config
+                        .hash_algorithm
+                        .push(parse_enum(key, &name).map_err(error)?);
+                }
+            }
+            "buffer_size_kb" => match value {
+                Value::Integer(size) if size > 0 => {
+                    config.buffer_size = Some((size as usize).saturating_mul(1024));
+                }
+                _ => {
+                    return Err(error(
+                        "buffer_size_kb must be a positive integer".to_string(),
+                    ))
+                }
+            },
+            _ => return Err(error(format!("unknown key '{}'", key))),
+        }
+    }
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::{CommandFactory, FromArgMatches};
+
+    use super::*;
+    use crate::{Args, DEFAULT_BUFFER_SIZE};
+
+    #[test]
+    fn parses_every_key() {
+        let config = parse(
+            "default_romtype = \"byte-swap\"\n\
+             output_dir = \"out\"\n\
+             quiet = true\n\
+             hash_algorithm = [\"md5\", \"SHA1\"]\n\
+             buffer_size_kb = 1_024\n",
+        )
+        .unwrap();
+        assert_eq!(config.default_romtype, Some(RomType::ByteSwap));
+        assert_eq!(config.output_dir.as_deref(), Some("out"));
+        assert_eq!(config.quiet, Some(true));
+        assert_eq!(
+            config.hash_algorithm,
+            [HashAlgorithm::Md5, HashAlgorithm::Sha1]
+        );
+        assert_eq!(config.buffer_size, Some(1024 * 1024));
+
+        let config = parse("hash_algorithm = \"crc32\"").unwrap();
+        assert_eq!(config.hash_algorithm, [HashAlgorithm::Crc32]);
+    }
+
+    #[test]
+    fn parses_quoted_strings() {
+        let config = parse(r#"output_dir = "a \"b\" \\ # c""#).unwrap();
+        assert_eq!(config.output_dir.as_deref(), Some(r#"a "b" \ # c"#));
+        let config = parse(r#"output_dir = "tab\there""#).unwrap();
+        assert_eq!(config.output_dir.as_deref(), Some("tab\there"));
+
+        assert!(parse(r#"output_dir = "open"#).is_err());
+        assert!(parse(r#"output_dir = "\x""#).is_err());
+        assert!(parse(r#"output_dir = "a" "b""#).is_err());
+    }
+
+    #[test]
+    fn skips_comments_and_blank_lines() {
+        let config = parse("# defaults\n\n  quiet = false # not yet\n\t\n").unwrap();
+        assert_eq!(config.quiet, Some(false));
+        assert!(parse("# quiet = maybe").unwrap().quiet.is_none());
+    }
+
+    #[test]
+    fn rejects_unknown_keys() {
+        assert_eq!(
+            parse("quiet = true\nverbose = true").unwrap_err(),
+            "line 2: unknown key 'verbose'"
+        );
+        assert_eq!(parse("quiet").unwrap_err(), "line 1: expected key = value");
+    }
+
+    #[test]
+    fn rejects_bad_values() {
+        for text in [
+            "default_romtype = \"v128\"",
+            "default_romtype = 1",
+            "output_dir = true",
+            "quiet = \"yes\"",
+            "quiet = maybe",
+            "hash_algorithm = [\"md5\", 5]",
+            "hash_algorithm = [\"md5\"",
+            "hash_algorithm = [\"md5\" \"sha1\"]",
+            "hash_algorithm = \"md4\"",
+            "buffer_size_kb = 0",
+            "buffer_size_kb = -4",
+            "buffer_size_kb = \"64\"",
+        ] {
+            assert!(parse(text).is_err(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn env_wins_over_config_over_defaults() {
+        env::set_var("N64SWAP_ROMTYPE", "little-endian");
+        env::set_var("N64SWAP_BUFFER_SIZE", "64k");
+        let from_env = from_env();
+        env::remove_var("N64SWAP_ROMTYPE");
+        env::remove_var("N64SWAP_BUFFER_SIZE");
+        let file =
+            parse("default_romtype = \"byte-swap\"\nbuffer_size_kb = 8\nquiet = true").unwrap();
+        let config = from_env.unwrap().or(file);
+        assert_eq!(config.default_romtype, Some(RomType::LittleEndian));
+        assert_eq!(config.buffer_size, Some(64 * 1024));
+        assert_eq!(config.quiet, Some(true));
+
+        let matches = Args::command()
+            .try_get_matches_from(["n64swap", "rom.z64"])
+            .unwrap();
+        let mut args = Args::from_arg_matches(&matches).unwrap();
+        args.apply_config(config, &matches);
+        assert_eq!(args.romtype, Some(RomType::LittleEndian));
+        assert_eq!(args.buffer_size, 64 * 1024);
+        assert!(args.quiet);
+        // Nothing set it, so it keeps its default
+        assert!(!args.force);
+
+        // Flags win over both
+        let matches = Args::command()
+            .try_get_matches_from(["n64swap", "--buffer-size", "4k", "rom.z64"])
+            .unwrap();
+        let mut args = Args::from_arg_matches(&matches).unwrap();
+        args.apply_config(parse("buffer_size_kb = 8").unwrap(), &matches);
+        assert_eq!(args.buffer_size, 4 * 1024);
+
+        let matches = Args::command()
+            .try_get_matches_from(["n64swap", "rom.z64"])
+            .unwrap();
+        let mut args = Args::from_arg_matches(&matches).unwrap();
+        args.apply_config(Config::default(), &matches);
+        assert_eq!(args.buffer_size, DEFAULT_BUFFER_SIZE);
+    }
+}
@@ -0,0 +1,196 @@
+use std::io::{self, Read};
+
+use crate::{swapper, ConversionError, RomType};
+
+/// Pull-based conversion of a rom, a 4-byte word at a time.
+///
+/// As an `Iterator` it yields each word of `reader` swapped from `src_type` into
+/// `dst_type`, and ends after the last one. A rom that stops partway through a
+/// word yields `SizeMisaligned`, a read error yields `IoError`, and the iterator
+/// ends after either. As a `Read` it gives the same words as a byte stream.
+///
+/// Every word is a small read, so wrap unbuffered readers in a `BufReader`.
+pub struct RomConverter<R: Read> {
+    reader: R,
+    src_type: RomType,
+    dst_type: RomType,
+    offset: u64,
+    done: bool,
+    // A word `Read` only handed out part of
+    word: [u8; 4],
+    word_pos: usize,
+    // An error `Read` hit after some bytes, for its next call
+    error: Option<ConversionError>,
+}
+
+impl<R: Read> RomConverter<R> {
+    pub fn new(reader: R, src_type: RomType, dst_type: RomType) -> RomConverter<R> {
+        RomConverter {
+            reader,
+            src_type,
+            dst_type,
+            offset: 0,
+            done: false,
+            word: [0; 4],
+            word_pos: 4,
+            error: None,
+        }
+    }
+
+    /// Get the wrapped reader back
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    // Fill `word` as far as the reader goes, returning how many bytes it got
+    fn read_word(&mut self, word: &mut [u8; 4]) -> io::Result<usize> {
+        let mut filled = 0;
+        while filled < word.len() {
+            match self.reader.read(&mut word[filled..]) {
+                Ok(0) => break,
+                Ok(read) => filled += read,
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+                Err(error) => return Err(error),
+            }
+        }
+        Ok(filled)
+    }
+}
+
+impl<R: Read> Iterator for RomConverter<R> {
+    type Item = Result<[u8; 4], ConversionError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let mut word = [0; 4];
+        let filled = match self.read_word(&mut word) {
+            Ok(filled) => filled,
+            Err(error) => {
+                self.done = true;
+                return Some(Err(error.into()));
+            }
+        };
+        match filled {
+            0 => {
+                self.done = true;
+                None
+            }
+            4 => {
+                self.offset += 4;
+                swapper(&mut word, self.src_type, self.dst_type);
+                Some(Ok(word))
+            }
+            _ => {
+                self.done = true;
+                let actual = self.offset + filled as u64;
+                Some(Err(ConversionError::SizeMisaligned {
+                    actual,
+                    expected: actual.next_multiple_of(4),
+                }))
+            }
+        }
+    }
+}
+
+impl<R: Read> Read for RomConverter<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut written = 0;
+        while written < buf.len() {
+            if self.word_pos == self.word.len() {
+                let next = self.error.take().map(Err).or_else(|| self.next());
+                match next {
+                    Some(Ok(word)) => {
+                        self.word = word;
+                        self.word_pos = 0;
+                    }
+                    // The bytes read so far go out first
+                    Some(Err(error)) if written > 0 => {
+                        self.error = Some(error);
+                        break;
+                    }
+                    Some(Err(ConversionError::IoError(error))) => return Err(error),
+                    Some(Err(error)) => {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            error.to_string(),
+                        ))
+                    }
+                    None => break,
+                }
+            }
+            let take = (self.word.len() - self.word_pos).min(buf.len() - written);
+            buf[written..written + take]
+                .copy_from_slice(&self.word[self.word_pos..self.word_pos + take]);
+            self.word_pos += take;
+            written += take;
+        }
+        Ok(written)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A reader that hands out a single byte per read
+    struct OneByte<'a>(&'a [u8]);
+
+    impl Read for OneByte<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let Some((&byte, rest)) = self.0.split_first() else {
+                return Ok(0);
+            };
+            let Some(first) = buf.first_mut() else {
+                return Ok(0);
+            };
+            *first = byte;
+            self.0 = rest;
+            Ok(1)
+        }
+    }
+
+    const ROM: [u8; 8] = [0x37, 0x80, 0x40, 0x12, 1, 2, 3, 4];
+
+    #[test]
+    fn collects_from_a_slow_reader() {
+        let converter = RomConverter::new(OneByte(&ROM), RomType::ByteSwap, RomType::BigEndian);
+        let words: Vec<[u8; 4]> = converter.collect::<Result<_, _>>().unwrap();
+        assert_eq!(words, [crate::BIG_ENDIAN, [2, 1, 4, 3]]);
+    }
+
+    #[test]
+    fn reads_a_byte_at_a_time() {
+        let mut converter = RomConverter::new(OneByte(&ROM), RomType::ByteSwap, RomType::BigEndian);
+        let mut out = Vec::new();
+        let mut buf = [0; 1];
+        loop {
+            match converter.read(&mut buf).unwrap() {
+                0 => break,
+                read => out.extend_from_slice(&buf[..read]),
+            }
+        }
+        assert_eq!(out, [0x80, 0x37, 0x12, 0x40, 2, 1, 4, 3]);
+    }
+
+    #[test]
+    fn stops_at_a_partial_word() {
+        let mut converter = RomConverter::new(&ROM[..6], RomType::ByteSwap, RomType::BigEndian);
+        assert_eq!(converter.next().unwrap().unwrap(), crate::BIG_ENDIAN);
+        assert!(matches!(
+            converter.next(),
+            Some(Err(ConversionError::SizeMisaligned {
+                actual: 6,
+                expected: 8
+            }))
+        ));
+        assert!(converter.next().is_none());
+
+        let mut converter = RomConverter::new(&ROM[..6], RomType::ByteSwap, RomType::BigEndian);
+        let mut out = Vec::new();
+        let error = converter.read_to_end(&mut out).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(out, crate::BIG_ENDIAN);
+    }
+}
@@ -0,0 +1,109 @@
+use core::fmt;
+
+/// Destination region, from the country code byte at header offset 0x3E
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum CountryCode {
+    Germany,
+    Usa,
+    France,
+    Italy,
+    Japan,
+    Europe,
+    Spain,
+    Australia,
+    /// 'X', used by some PAL releases
+    RegionX,
+    /// 'Y', used by some PAL releases
+    RegionY,
+    Unknown(u8),
+}
+
+impl CountryCode {
+    pub fn from_byte(b: u8) -> CountryCode {
+        match b {
+            0x44 => CountryCode::Germany,
+            0x45 => CountryCode::Usa,
+            0x46 => CountryCode::France,
+            0x49 => CountryCode::Italy,
+            0x4A => CountryCode::Japan,
+            0x50 => CountryCode::Europe,
+            0x53 => CountryCode::Spain,
+            0x55 => CountryCode::Australia,
+            0x58 => CountryCode::RegionX,
+            0x59 => CountryCode::RegionY,
+            other => CountryCode::Unknown(other),
+        }
+    }
+
+    /// Look up a region by name (case-insensitive), e.g. "USA" or "Japan"
+    pub fn from_name(name: &str) -> Option<CountryCode> {
+        match name.to_ascii_lowercase().as_str() {
+            "germany" => Some(CountryCode::Germany),
+            "usa" => Some(CountryCode::Usa),
+            "france" => Some(CountryCode::France),
+            "italy" => Some(CountryCode::Italy),
+            "japan" => Some(CountryCode::Japan),
+            "europe" => Some(CountryCode::Europe),
+            "spain" => Some(CountryCode::Spain),
+            "australia" => Some(CountryCode::Australia),
+            "x" => Some(CountryCode::RegionX),
+            "y" => Some(CountryCode::RegionY),
+            _ => None,
+        }
+    }
+
+    pub fn to_byte(&self) -> u8 {
+        match *self {
+            CountryCode::Germany => 0x44,
+            CountryCode::Usa => 0x45,
+            CountryCode::France => 0x46,
+            CountryCode::Italy => 0x49,
+            CountryCode::Japan => 0x4A,
+            CountryCode::Europe => 0x50,
+            CountryCode::Spain => 0x53,
+            CountryCode::Australia => 0x55,
+            CountryCode::RegionX => 0x58,
+            CountryCode::RegionY => 0x59,
+            CountryCode::Unknown(b) => b,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match *self {
+            CountryCode::Germany => "Germany",
+            CountryCode::Usa => "USA",
+            CountryCode::France => "France",
+            CountryCode::Italy => "Italy",
+            CountryCode::Japan => "Japan",
+            CountryCode::Europe => "Europe",
+            CountryCode::Spain => "Spain",
+            CountryCode::Australia => "Australia",
+            CountryCode::RegionX => "X (any)",
+            CountryCode::RegionY => "Y (any)",
+            CountryCode::Unknown(_) => "Unknown",
+        }
+    }
+
+    /// The region with its video standard, e.g. "USA (NTSC)"
+    pub fn region_string(&self) -> &str {
+        match *self {
+            CountryCode::Germany => "Germany (PAL)",
+            CountryCode::Usa => "USA (NTSC)",
+            CountryCode::France => "France (PAL)",
+            CountryCode::Italy => "Italy (PAL)",
+            CountryCode::Japan => "Japan (NTSC)",
+            CountryCode::Europe => "Europe (PAL)",
+            CountryCode::Spain => "Spain (PAL)",
+            CountryCode::Australia => "Australia (PAL)",
+            CountryCode::RegionX => "X (PAL)",
+            CountryCode::RegionY => "Y (PAL)",
+            CountryCode::Unknown(_) => "Unknown",
+        }
+    }
+}
+
+impl fmt::Display for CountryCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "0x{:02X} ({})", self.to_byte(), self.name())
+    }
+}
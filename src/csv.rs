@@ -0,0 +1,28 @@
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+
+// Quote a field when it holds a separator, quote or line break
+fn field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Write a row of fields, separated by commas
+pub fn write_row<W: Write>(writer: &mut W, row: &[&str]) -> io::Result<()> {
+    let fields: Vec<String> = row.iter().map(|value| field(value)).collect();
+    writeln!(writer, "{}", fields.join(","))
+}
+
+/// Append a row to the CSV file at `path`, starting a new file with `columns`
+pub fn append_row(path: &Path, columns: &[&str], row: &[String]) -> io::Result<()> {
+    let mut file = File::options().create(true).append(true).open(path)?;
+    if file.metadata()?.len() == 0 {
+        write_row(&mut file, columns)?;
+    }
+    let row: Vec<&str> = row.iter().map(String::as_str).collect();
+    write_row(&mut file, &row)
+}
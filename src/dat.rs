@@ -0,0 +1,219 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// A `<rom>` entry of a Logiqx DAT file, as No-Intro publishes them
+#[derive(Debug, Clone)]
+pub struct DatRom {
+    /// Name of the `<game>` it belongs to
+    pub game: String,
+    /// Canonical filename of the dump
+    pub name: String,
+    pub size: Option<u64>,
+    pub crc: u32,
+}
+
+/// How a rom compares with a DAT file
+pub enum DatStatus<'a> {
+    /// Its CRC32 is listed
+    KnownGood(&'a DatRom),
+    /// Its name is listed, with another CRC32
+    KnownBad(&'a DatRom),
+    NotInDatabase,
+}
+
+/// The `<rom>` entries of a DAT file
+pub struct Dat {
+    roms: Vec<DatRom>,
+}
+
+impl Dat {
+    pub fn load(path: &Path) -> io::Result<Dat> {
+        fs::read_to_string(path).map(|content| Dat::parse(&content))
+    }
+
+    /// Pick the `<game>` and `<rom>` tags out of a DAT, anything else is skipped.
+    /// Roms without a valid `crc` attribute are left out.
+    pub fn parse(content: &str) -> Dat {
+        let mut roms = Vec::new();
+        let mut game = String::new();
+        for tag in content.split('<').skip(1) {
+            let (name, rest) = tag
+                .split_once(|c: char| c.is_ascii_whitespace() || c == '/' || c == '>')
+                .unwrap_or((tag, ""));
+            match name {
+                "game" | "machine" => game = attribute(rest, "name").unwrap_or_default(),
+                "rom" => {
+                    let crc = attribute(rest, "crc")
+                        .filter(|crc| crc.len() == 8)
+                        .and_then(|crc| u32::from_str_radix(&crc, 16).ok());
+                    let (Some(name), Some(crc)) = (attribute(rest, "name"), crc) else {
+                        continue;
+                    };
+                    roms.push(DatRom {
+                        game: game.clone(),
+                        name,
+                        size: attribute(rest, "size").and_then(|size| size.parse().ok()),
+                        crc,
+                    });
+                }
+                _ => {}
+            }
+        }
+        Dat { roms }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.roms.is_empty()
+    }
+
+    /// Look a rom up by its size and CRC32, then by its filename (the extension
+    /// left out, a dump can be in any byte order)
+    pub fn lookup(&self, filename: &Path, size: u64, crc: u32) -> DatStatus<'_> {
+        let matches = |rom: &&DatRom| rom.crc == crc && rom.size.is_none_or(|len| len == size);
+        if let Some(rom) = self.roms.iter().find(matches) {
+            return DatStatus::KnownGood(rom);
+        }
+        let stem = filename.file_stem().unwrap_or_default();
+        match self
+            .roms
+            .iter()
+            .find(|rom| Path::new(&rom.name).file_stem() == Some(stem))
+        {
+            Some(rom) => DatStatus::KnownBad(rom),
+            None => DatStatus::NotInDatabase,
+        }
+    }
+}
+
+// The unescaped value of an attribute in the rest of a tag
+fn attribute(tag: &str, key: &str) -> Option<String> {
+    let mut rest = tag;
+    loop {
+        let rest_trimmed = rest.trim_start();
+        if rest_trimmed.starts_with(['>', '/']) || rest_trimmed.is_empty() {
+            return None;
+        }
+        let (name, after) = rest_trimmed.split_once('=')?;
+        let after = after.trim_start();
+        let quote = after.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+        let (value, after) = after[1..].split_once(quote)?;
+        if name.trim() == key {
+            return Some(unescape(value));
+        }
+        rest = after;
+    }
+}
+
+// Replace the XML entities of an attribute value
+fn unescape(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(idx) = rest.find('&') {
+        unescaped.push_str(&rest[..idx]);
+        rest = &rest[idx..];
+        let Some(end) = rest.find(';') else {
+            break;
+        };
+        let entity = &rest[1..end];
+        let c = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => entity
+                .strip_prefix("#x")
+                .map(|hex| u32::from_str_radix(hex, 16))
+                .or_else(|| entity.strip_prefix('#').map(str::parse))
+                .and_then(Result::ok)
+                .and_then(char::from_u32),
+        };
+        match c {
+            Some(c) => {
+                unescaped.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                unescaped.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    unescaped.push_str(rest);
+    unescaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAT: &str = r#"<?xml version="1.0"?>
+<datafile>
+	<game name="Tom &amp; Jerry in Fists of Furry (Europe) (En,Fr,De,Es,It,Nl)">
+		<description>Tom &amp; Jerry</description>
+		<rom name="Tom &amp; Jerry &#x28;Europe&#41; &quot;Furry&quot;.z64" size="8388608" crc="1fa056e0" md5="00" sha1="00"/>
+	</game>
+	<game name="Other">
+		<rom sha1="00" crc='A1B2C3D4' name='Other.z64' size="4096"/>
+	</game>
+	<game name="No CRC">
+		<rom name="No CRC.z64" size="4096" md5="00" sha1="00"/>
+		<rom name="Bad CRC.z64" size="4096" crc="1234"/>
+	</game>
+	<game name="Only CRC">
+		<rom name="Only CRC.z64" crc="0badf00d"/>
+	</game>
+</datafile>
+"#;
+
+    #[test]
+    fn parses_entries() {
+        let dat = Dat::parse(DAT);
+        let names: Vec<&str> = dat.roms.iter().map(|rom| rom.name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "Tom & Jerry (Europe) \"Furry\".z64",
+                "Other.z64",
+                "Only CRC.z64"
+            ]
+        );
+
+        let tom = &dat.roms[0];
+        assert_eq!(
+            tom.game,
+            "Tom & Jerry in Fists of Furry (Europe) (En,Fr,De,Es,It,Nl)"
+        );
+        assert_eq!((tom.size, tom.crc), (Some(8388608), 0x1FA056E0));
+
+        // Attributes in another order, and single quotes
+        let other = &dat.roms[1];
+        assert_eq!((other.size, other.crc), (Some(4096), 0xA1B2C3D4));
+
+        // md5 and sha1 aren't needed
+        assert_eq!((dat.roms[2].size, dat.roms[2].crc), (None, 0x0BADF00D));
+    }
+
+    #[test]
+    fn looks_up_by_crc_then_name() {
+        let dat = Dat::parse(DAT);
+        assert!(matches!(
+            dat.lookup(Path::new("whatever.v64"), 4096, 0xA1B2C3D4),
+            DatStatus::KnownGood(rom) if rom.name == "Other.z64"
+        ));
+        assert!(matches!(
+            dat.lookup(Path::new("Other.n64"), 4096, 0),
+            DatStatus::KnownBad(rom) if rom.name == "Other.z64"
+        ));
+        // A listed size has to match too
+        assert!(matches!(
+            dat.lookup(Path::new("whatever.v64"), 8192, 0xA1B2C3D4),
+            DatStatus::NotInDatabase
+        ));
+        assert!(matches!(
+            dat.lookup(Path::new("whatever.v64"), 8192, 0x0BADF00D),
+            DatStatus::KnownGood(rom) if rom.name == "Only CRC.z64"
+        ));
+    }
+}
@@ -0,0 +1,450 @@
+use std::io;
+
+// Base and extra bits of the length codes 257..285
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+// Base and extra bits of the distance codes 0..29
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+// Order the code length code lengths of a dynamic block come in
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+const MAX_BITS: usize = 15;
+
+const END_OF_BLOCK: u16 = 256;
+const WINDOW_SIZE: usize = 32 * 1024;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+// Candidates tried per position, more compresses better but slower
+const MAX_CHAIN: usize = 32;
+const HASH_BITS: u32 = 15;
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("Invalid deflate data: {}", message),
+    )
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    buffer: u32,
+    count: u32,
+}
+
+impl BitReader<'_> {
+    fn bits(&mut self, count: u32) -> io::Result<u32> {
+        while self.count < count {
+            let byte = *self
+                .data
+                .get(self.pos)
+                .ok_or_else(|| invalid("truncated"))?;
+            self.pos += 1;
+            self.buffer |= u32::from(byte) << self.count;
+            self.count += 8;
+        }
+        let value = self.buffer & ((1 << count) - 1);
+        self.buffer >>= count;
+        self.count -= count;
+        Ok(value)
+    }
+
+    // Stored blocks start on a byte boundary
+    fn align(&mut self) {
+        self.buffer = 0;
+        self.count = 0;
+    }
+}
+
+// Canonical Huffman code, as symbol counts per code length
+struct Huffman {
+    counts: [u16; MAX_BITS + 1],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Huffman {
+        let mut counts = [0; MAX_BITS + 1];
+        for &length in lengths {
+            counts[usize::from(length)] += 1;
+        }
+        counts[0] = 0;
+        let mut offsets = [0; MAX_BITS + 2];
+        for length in 1..=MAX_BITS {
+            offsets[length + 1] = offsets[length] + counts[length];
+        }
+        let mut symbols = vec![0; usize::from(offsets[MAX_BITS + 1])];
+        for (symbol, &length) in lengths.iter().enumerate() {
+            if length != 0 {
+                let offset = &mut offsets[usize::from(length)];
+                symbols[usize::from(*offset)] = symbol as u16;
+                *offset += 1;
+            }
+        }
+        Huffman { counts, symbols }
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> io::Result<u16> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for length in 1..=MAX_BITS {
+            code |= reader.bits(1)? as i32;
+            let count = i32::from(self.counts[length]);
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(invalid("bad code"))
+    }
+}
+
+fn fixed_lengths() -> ([u8; 288], [u8; 30]) {
+    let mut litlen = [8; 288];
+    litlen[144..256].fill(9);
+    litlen[256..280].fill(7);
+    (litlen, [5; 30])
+}
+
+// The literal/length and distance codes of a dynamic block
+fn dynamic_codes(reader: &mut BitReader) -> io::Result<(Huffman, Huffman)> {
+    let litlen_count = reader.bits(5)? as usize + 257;
+    let dist_count = reader.bits(5)? as usize + 1;
+    let code_count = reader.bits(4)? as usize + 4;
+    let mut code_lengths = [0; 19];
+    for &idx in &CODE_LENGTH_ORDER[..code_count] {
+        code_lengths[idx] = reader.bits(3)? as u8;
+    }
+    let code = Huffman::new(&code_lengths);
+
+    let mut lengths = Vec::with_capacity(litlen_count + dist_count);
+    while lengths.len() < litlen_count + dist_count {
+        let (length, repeat) = match code.decode(reader)? {
+            symbol @ 0..=15 => (symbol as u8, 1),
+            16 => {
+                let previous = *lengths
+                    .last()
+                    .ok_or_else(|| invalid("repeat without a length"))?;
+                (previous, 3 + reader.bits(2)?)
+            }
+            17 => (0, 3 + reader.bits(3)?),
+            _ => (0, 11 + reader.bits(7)?),
+        };
+        lengths.extend((0..repeat).map(|_| length));
+    }
+    if lengths.len() > litlen_count + dist_count {
+        return Err(invalid("too many code lengths"));
+    }
+    let (litlen, dist) = lengths.split_at(litlen_count);
+    Ok((Huffman::new(litlen), Huffman::new(dist)))
+}
+
+/// Decompress raw deflate data, as stored in zip archives
+pub fn inflate(data: &[u8], size_hint: usize) -> io::Result<Vec<u8>> {
+    let mut reader = BitReader {
+        data,
+        pos: 0,
+        buffer: 0,
+        count: 0,
+    };
+    let mut out = Vec::with_capacity(size_hint);
+    loop {
+        let last = reader.bits(1)? == 1;
+        let (litlen, dist) = match reader.bits(2)? {
+            0 => {
+                reader.align();
+                let header = data
+                    .get(reader.pos..reader.pos + 4)
+                    .ok_or_else(|| invalid("truncated"))?;
+                let len = usize::from(u16::from_le_bytes([header[0], header[1]]));
+                let block = data
+                    .get(reader.pos + 4..reader.pos + 4 + len)
+                    .ok_or_else(|| invalid("truncated"))?;
+                out.extend_from_slice(block);
+                reader.pos += 4 + len;
+                if last {
+                    return Ok(out);
+                }
+                continue;
+            }
+            1 => {
+                let (litlen, dist) = fixed_lengths();
+                (Huffman::new(&litlen), Huffman::new(&dist))
+            }
+            2 => dynamic_codes(&mut reader)?,
+            _ => return Err(invalid("bad block type")),
+        };
+
+        loop {
+            let symbol = litlen.decode(&mut reader)?;
+            if symbol < END_OF_BLOCK {
+                out.push(symbol as u8);
+                continue;
+            }
+            if symbol == END_OF_BLOCK {
+                break;
+            }
+            let idx = usize::from(symbol - 257);
+            let (Some(&base), Some(&extra)) = (LENGTH_BASE.get(idx), LENGTH_EXTRA.get(idx)) else {
+                return Err(invalid("bad length code"));
+            };
+            let length = usize::from(base) + reader.bits(u32::from(extra))? as usize;
+            let idx = usize::from(dist.decode(&mut reader)?);
+            let (Some(&base), Some(&extra)) = (DIST_BASE.get(idx), DIST_EXTRA.get(idx)) else {
+                return Err(invalid("bad distance code"));
+            };
+            let distance = usize::from(base) + reader.bits(u32::from(extra))? as usize;
+            if distance > out.len() {
+                return Err(invalid("distance too far back"));
+            }
+            // Byte by byte, a match can overlap what it copies
+            let start = out.len() - distance;
+            for offset in 0..length {
+                out.push(out[start + offset]);
+            }
+        }
+        if last {
+            return Ok(out);
+        }
+    }
+}
+
+struct BitWriter {
+    out: Vec<u8>,
+    buffer: u64,
+    count: u32,
+}
+
+impl BitWriter {
+    fn bits(&mut self, value: u32, count: u32) {
+        self.buffer |= u64::from(value) << self.count;
+        self.count += count;
+        while self.count >= 8 {
+            self.out.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    // Huffman codes go most significant bit first
+    fn code(&mut self, code: u32, length: u32) {
+        self.bits(code.reverse_bits() >> (32 - length), length);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.out.push(self.buffer as u8);
+        }
+        self.out
+    }
+}
+
+// A literal/length symbol in the fixed code
+fn write_litlen(writer: &mut BitWriter, symbol: u16) {
+    let symbol = u32::from(symbol);
+    match symbol {
+        0..=143 => writer.code(0x30 + symbol, 8),
+        144..=255 => writer.code(0x190 + symbol - 144, 9),
+        256..=279 => writer.code(symbol - 256, 7),
+        _ => writer.code(0xC0 + symbol - 280, 8),
+    }
+}
+
+fn write_match(writer: &mut BitWriter, length: usize, distance: usize) {
+    let idx = LENGTH_BASE.partition_point(|&base| usize::from(base) <= length) - 1;
+    write_litlen(writer, 257 + idx as u16);
+    writer.bits(
+        (length - usize::from(LENGTH_BASE[idx])) as u32,
+        u32::from(LENGTH_EXTRA[idx]),
+    );
+    let idx = DIST_BASE.partition_point(|&base| usize::from(base) <= distance) - 1;
+    writer.code(idx as u32, 5);
+    writer.bits(
+        (distance - usize::from(DIST_BASE[idx])) as u32,
+        u32::from(DIST_EXTRA[idx]),
+    );
+}
+
+// Chain `pos` into the match finder
+fn insert(head: &mut [usize], prev: &mut [usize], data: &[u8], pos: usize) {
+    if pos + MIN_MATCH <= data.len() {
+        let hash = hash(&data[pos..]);
+        prev[pos % WINDOW_SIZE] = head[hash];
+        head[hash] = pos;
+    }
+}
+
+fn hash(data: &[u8]) -> usize {
+    let value = u32::from(data[0]) << 16 | u32::from(data[1]) << 8 | u32::from(data[2]);
+    (value.wrapping_mul(0x9E37_79B1) >> (32 - HASH_BITS)) as usize
+}
+
+/// Compress data as a single raw deflate block with the fixed Huffman code
+pub fn deflate(data: &[u8]) -> Vec<u8> {
+    let mut writer = BitWriter {
+        out: Vec::with_capacity(data.len() / 2),
+        buffer: 0,
+        count: 0,
+    };
+    // Last block, fixed code
+    writer.bits(1, 1);
+    writer.bits(1, 2);
+
+    // Most recent position of each hash, and the previous one with the same hash
+    let mut head = vec![usize::MAX; 1 << HASH_BITS];
+    let mut prev = vec![usize::MAX; WINDOW_SIZE];
+
+    let mut pos = 0;
+    while pos < data.len() {
+        let (mut best_len, mut best_dist) = (0, 0);
+        if pos + MIN_MATCH <= data.len() {
+            let max_len = MAX_MATCH.min(data.len() - pos);
+            let mut candidate = head[hash(&data[pos..])];
+            for _ in 0..MAX_CHAIN {
+                if candidate == usize::MAX || pos - candidate > WINDOW_SIZE {
+                    break;
+                }
+                let len = data[candidate..]
+                    .iter()
+                    .zip(&data[pos..pos + max_len])
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                if len > best_len {
+                    (best_len, best_dist) = (len, pos - candidate);
+                    if len == max_len {
+                        break;
+                    }
+                }
+                let next = prev[candidate % WINDOW_SIZE];
+                if next == usize::MAX || next >= candidate {
+                    break;
+                }
+                candidate = next;
+            }
+        }
+
+        if best_len >= MIN_MATCH {
+            write_match(&mut writer, best_len, best_dist);
+            for idx in pos..pos + best_len {
+                insert(&mut head, &mut prev, data, idx);
+            }
+            pos += best_len;
+        } else {
+            write_litlen(&mut writer, u16::from(data[pos]));
+            insert(&mut head, &mut prev, data, pos);
+            pos += 1;
+        }
+    }
+    write_litlen(&mut writer, END_OF_BLOCK);
+    writer.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Bytes that don't compress, from a xorshift generator
+    fn noise(len: usize) -> Vec<u8> {
+        let mut state = 0x2545_F491u32;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn fixed_huffman_round_trip() {
+        let repeated: Vec<u8> = (0..100_000).map(|idx| (idx % 7) as u8).collect();
+        let inputs = [
+            Vec::new(),
+            b"a".to_vec(),
+            b"abcabcabcabcabcabc".to_vec(),
+            repeated,
+            noise(70_000),
+        ];
+        for data in inputs {
+            let compressed = deflate(&data);
+            assert_eq!(inflate(&compressed, data.len()).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn stored_blocks() {
+        // A stored block, then a last stored block
+        let mut data = vec![0x00, 0x03, 0x00, 0xFC, 0xFF];
+        data.extend_from_slice(b"abc");
+        data.extend_from_slice(&[0x01, 0x02, 0x00, 0xFD, 0xFF]);
+        data.extend_from_slice(b"de");
+        assert_eq!(inflate(&data, 0).unwrap(), b"abcde");
+    }
+
+    #[test]
+    fn dynamic_huffman_from_zlib() {
+        // zlib.compressobj(9, zlib.DEFLATED, -15) of the lines below
+        let compressed = [
+            0x95, 0xd1, 0xc7, 0x01, 0x80, 0x20, 0x14, 0x04, 0xd1, 0x3b, 0x55, 0x6c, 0x09, 0x62,
+            0xb6, 0x1c, 0x03, 0x66, 0xfd, 0x8a, 0x60, 0xaa, 0x5e, 0x5b, 0xd8, 0xf3, 0xbc, 0xdb,
+            0xb8, 0xde, 0x60, 0xf7, 0x43, 0x3d, 0xa1, 0xb2, 0x72, 0xad, 0x68, 0xe5, 0xc6, 0xe8,
+            0x97, 0xed, 0x80, 0x9c, 0xc6, 0xc2, 0xfd, 0x79, 0x2e, 0xdf, 0x07, 0x8d, 0x74, 0x08,
+            0x94, 0x23, 0xb4, 0xa6, 0x74, 0x48, 0xe9, 0x88, 0xd2, 0x31, 0xa5, 0x13, 0x4a, 0xa7,
+            0x94, 0xce, 0x28, 0x9d, 0x53, 0xba, 0xe0, 0xee, 0x90, 0x33, 0xb5, 0xfa, 0x00,
+        ];
+        let expected: String = (0..12)
+            .map(|idx| format!("the quick brown fox jumps over the lazy dog {}\n", idx))
+            .collect();
+        assert_eq!(compressed[0] >> 1 & 3, 2, "not a dynamic block");
+        assert_eq!(inflate(&compressed, 0).unwrap(), expected.as_bytes());
+
+        for len in 0..compressed.len() {
+            assert!(inflate(&compressed[..len], 0).is_err(), "{} bytes", len);
+        }
+    }
+
+    #[test]
+    fn truncated_input_fails() {
+        let compressed = deflate(&noise(1000));
+        for len in 0..compressed.len() {
+            assert!(inflate(&compressed[..len], 0).is_err(), "{} bytes", len);
+        }
+        // A stored block shorter than its length says
+        assert!(inflate(&[0x01, 0x05, 0x00, 0xFA, 0xFF, b'a'], 0).is_err());
+    }
+
+    #[test]
+    fn corrupt_input_fails() {
+        // Block type 3 is reserved
+        assert!(inflate(&[0x07], 0).is_err());
+
+        // A match before the start of the output
+        let mut writer = BitWriter {
+            out: Vec::new(),
+            buffer: 0,
+            count: 0,
+        };
+        writer.bits(1, 1);
+        writer.bits(1, 2);
+        write_litlen(&mut writer, u16::from(b'a'));
+        write_match(&mut writer, 3, 2);
+        write_litlen(&mut writer, END_OF_BLOCK);
+        let error = inflate(&writer.finish(), 0).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,63 @@
+use n64swap::HEADER_SIZE;
+
+const ROW: usize = 16;
+
+// Header fields by offset and length
+const FIELDS: [(usize, usize, &str); 13] = [
+    (0x00, 4, "magic"),
+    (0x04, 4, "clock rate"),
+    (0x08, 4, "boot address"),
+    (0x0C, 4, "release"),
+    (0x10, 4, "CRC1"),
+    (0x14, 4, "CRC2"),
+    (0x18, 8, "padding"),
+    (0x20, 20, "title"),
+    (0x34, 4, "padding"),
+    (0x38, 4, "manufacturer"),
+    (0x3C, 2, "cart ID"),
+    (0x3E, 1, "country"),
+    (0x3F, 1, "version"),
+];
+
+/// Format a big-endian header as hex rows of 16 bytes, each followed by the
+/// bytes as ASCII and the fields it overlaps. A truncated header gives fewer rows.
+pub fn dump_header(header: &[u8]) -> String {
+    let mut dump = String::new();
+    for (row, bytes) in header[..header.len().min(HEADER_SIZE)]
+        .chunks(ROW)
+        .enumerate()
+    {
+        let start = row * ROW;
+        let mut hex = String::new();
+        for (idx, byte) in bytes.iter().enumerate() {
+            if idx == ROW / 2 {
+                hex.push(' ');
+            }
+            hex.push_str(&format!("{:02X} ", byte));
+        }
+        let ascii: String = bytes
+            .iter()
+            .map(|&byte| {
+                if byte.is_ascii_graphic() || byte == b' ' {
+                    byte as char
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+        let fields: Vec<&str> = FIELDS
+            .iter()
+            .filter(|(offset, len, _)| *offset < start + bytes.len() && offset + len > start)
+            .map(|(_, _, name)| *name)
+            .collect();
+
+        dump.push_str(&format!(
+            "0x{:02X}  {:<49} |{:<16}|  {}\n",
+            start,
+            hex,
+            ascii,
+            fields.join(", ")
+        ));
+    }
+    dump
+}
@@ -0,0 +1,196 @@
+use core::fmt;
+use std::io;
+use std::path::PathBuf;
+
+use crate::{BpsError, ChecksumError, HeaderError, IpsError, RomDifference, RomType};
+
+#[derive(Debug)]
+pub enum ConversionError {
+    /// Reading or writing failed
+    IoError(io::Error),
+    /// The header magic doesn't match any known rom type
+    UnrecognizedHeader([u8; 4]),
+    /// The rom size isn't a multiple of 4 bytes, `expected` is the padded size
+    SizeMisaligned { actual: u64, expected: u64 },
+    /// The rom is already the requested type
+    SameType(RomType),
+    /// The output file exists and overwriting wasn't forced
+    OutputExists(PathBuf),
+    /// The backup of a replaced file exists and overwriting wasn't forced
+    BackupExists(PathBuf),
+    /// The input doesn't have a .z64, .v64 or .n64 extension, with --strict-ext
+    UnknownExtension(PathBuf),
+    /// The output would overwrite the input
+    InputOutputSameFile(PathBuf),
+    /// Two files of a batch would be written to the same output
+    OutputCollision(PathBuf),
+    /// The rom checksum couldn't be calculated
+    Checksum(ChecksumError),
+    /// Some files of a batch failed to convert
+    BatchFailed { errors: usize },
+    /// The conversion buffer isn't a non-zero multiple of 4 bytes
+    InvalidBufferSize(usize),
+    /// The size of split chunks isn't a non-zero multiple of 4 bytes
+    InvalidChunkSize(u64),
+    /// A chunk to merge starts with the magic of another rom type than the first one
+    ChunkTypeMismatch {
+        path: PathBuf,
+        expected: RomType,
+        found: RomType,
+    },
+    /// The input doesn't match its entry in the --validate checksum file
+    ValidationFailed(PathBuf),
+    /// The written output doesn't read back as the input
+    VerifyFailed { path: PathBuf, offset: Option<u64> },
+    /// Two compared roms don't hold the same data
+    RomsDiffer(RomDifference),
+    /// An IPS patch couldn't be applied
+    Ips(IpsError),
+    /// A BPS patch couldn't be applied
+    Bps(BpsError),
+}
+
+impl fmt::Display for ConversionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConversionError::IoError(error) => write!(f, "I/O error: {}", error),
+            ConversionError::UnrecognizedHeader(bytes) => write!(
+                f,
+                "Header {:02x} {:02x} {:02x} {:02x} not recognized",
+                bytes[0], bytes[1], bytes[2], bytes[3]
+            ),
+            ConversionError::SizeMisaligned { actual, expected } => write!(
+                f,
+                "Size {} bytes is not a multiple of 4 (expected {} bytes), use --pad to zero-pad it",
+                actual, expected
+            ),
+            ConversionError::SameType(rom_type) => write!(f, "File is already {}!", rom_type),
+            ConversionError::OutputExists(path) => write!(
+                f,
+                "Output file {} already exists, use --force to overwrite",
+                path.display()
+            ),
+            ConversionError::BackupExists(path) => write!(
+                f,
+                "Backup file {} already exists, use --force to overwrite",
+                path.display()
+            ),
+            ConversionError::UnknownExtension(path) => write!(
+                f,
+                "{} doesn't have a .z64, .v64 or .n64 extension",
+                path.display()
+            ),
+            ConversionError::InputOutputSameFile(path) => write!(
+                f,
+                "Input and Output filenames are identical {}, consider renaming input file",
+                path.display()
+            ),
+            ConversionError::OutputCollision(path) => write!(
+                f,
+                "Output file {} is already written by another file of the batch",
+                path.display()
+            ),
+            ConversionError::Checksum(error) => write!(f, "{}", error),
+            ConversionError::BatchFailed { errors } => {
+                write!(f, "{} file(s) failed to convert", errors)
+            }
+            ConversionError::InvalidBufferSize(size) => write!(
+                f,
+                "Buffer size {} bytes is not a non-zero multiple of 4",
+                size
+            ),
+            ConversionError::InvalidChunkSize(size) => write!(
+                f,
+                "Chunk size {} bytes is not a non-zero multiple of 4",
+                size
+            ),
+            ConversionError::ChunkTypeMismatch {
+                path,
+                expected,
+                found,
+            } => write!(
+                f,
+                "{} is {}, not {} like the first chunk",
+                path.display(),
+                found,
+                expected
+            ),
+            ConversionError::ValidationFailed(path) => write!(
+                f,
+                "{} failed checksum validation, use --force to convert it anyway",
+                path.display()
+            ),
+            ConversionError::VerifyFailed {
+                path,
+                offset: Some(offset),
+            } => write!(
+                f,
+                "Verification of {} failed at offset 0x{:08X}",
+                path.display(),
+                offset
+            ),
+            ConversionError::VerifyFailed { path, offset: None } => {
+                write!(f, "Verification of {} failed", path.display())
+            }
+            ConversionError::RomsDiffer(difference) => {
+                let byte = |byte: Option<u8>| match byte {
+                    Some(byte) => format!("0x{:02X}", byte),
+                    None => "end of file".to_string(),
+                };
+                write!(
+                    f,
+                    "Roms differ at offset 0x{:08X}: {} != {}",
+                    difference.offset,
+                    byte(difference.left),
+                    byte(difference.right)
+                )
+            }
+            ConversionError::Ips(error) => write!(f, "{}", error),
+            ConversionError::Bps(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for ConversionError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConversionError::IoError(error) => Some(error),
+            ConversionError::Checksum(error) => Some(error),
+            ConversionError::Ips(error) => Some(error),
+            ConversionError::Bps(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ConversionError {
+    fn from(error: io::Error) -> Self {
+        ConversionError::IoError(error)
+    }
+}
+
+impl From<HeaderError> for ConversionError {
+    fn from(error: HeaderError) -> Self {
+        match error {
+            HeaderError::UnrecognizedMagic(bytes) => ConversionError::UnrecognizedHeader(bytes),
+        }
+    }
+}
+
+impl From<IpsError> for ConversionError {
+    fn from(error: IpsError) -> Self {
+        ConversionError::Ips(error)
+    }
+}
+
+impl From<BpsError> for ConversionError {
+    fn from(error: BpsError) -> Self {
+        ConversionError::Bps(error)
+    }
+}
+
+impl From<ChecksumError> for ConversionError {
+    fn from(error: ChecksumError) -> Self {
+        ConversionError::Checksum(error)
+    }
+}
@@ -0,0 +1,241 @@
+//! C interface, see `include/n64swap.h`.
+//!
+//! Rom types are 0 for big-endian (.z64), 1 for byte-swapped (.v64), 2 for
+//! little-endian (.n64) and 3 for word-swapped. Functions return 0 on success
+//! and a negative `N64SWAP_ERR_*` code otherwise. No panic unwinds into the
+//! caller, release builds abort on one (`panic = "abort"`) and others return
+//! `ERR_PANIC`.
+
+use std::ffi::{c_char, c_int, CStr};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::slice;
+
+use crate::{convert_file, identify_header, swapper_simd, ConversionError, RomType};
+
+const OK: c_int = 0;
+const ERR_INVALID_ARGUMENT: c_int = -1;
+const ERR_UNRECOGNIZED: c_int = -2;
+const ERR_IO: c_int = -3;
+const ERR_SIZE: c_int = -4;
+const ERR_PANIC: c_int = -5;
+
+fn rom_type(value: c_int) -> Option<RomType> {
+    match value {
+        0 => Some(RomType::BigEndian),
+        1 => Some(RomType::ByteSwap),
+        2 => Some(RomType::LittleEndian),
+        3 => Some(RomType::WordSwap),
+        _ => None,
+    }
+}
+
+fn rom_type_value(rom_type: RomType) -> c_int {
+    match rom_type {
+        RomType::BigEndian => 0,
+        RomType::ByteSwap => 1,
+        RomType::LittleEndian => 2,
+        RomType::WordSwap => 3,
+    }
+}
+
+fn error_code(error: &ConversionError) -> c_int {
+    match error {
+        ConversionError::IoError(_) => ERR_IO,
+        ConversionError::UnrecognizedHeader(_) => ERR_UNRECOGNIZED,
+        ConversionError::SizeMisaligned { .. } => ERR_SIZE,
+        _ => ERR_INVALID_ARGUMENT,
+    }
+}
+
+// Run `f`, turning a panic into ERR_PANIC. Nothing `f` touches is looked at
+// again after a panic, so unwind safety doesn't matter here.
+fn guarded<F: FnOnce() -> c_int>(f: F) -> c_int {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or(ERR_PANIC)
+}
+
+/// Identify the rom type from the 4 magic bytes at `header4`.
+///
+/// # Safety
+///
+/// `header4` must point to 4 readable bytes and `out_type` to a writable int.
+#[no_mangle]
+pub unsafe extern "C" fn n64swap_identify(header4: *const u8, out_type: *mut c_int) -> c_int {
+    if header4.is_null() || out_type.is_null() {
+        return ERR_INVALID_ARGUMENT;
+    }
+    let magic = *header4.cast::<[u8; 4]>();
+    guarded(|| match identify_header(&magic) {
+        Some(rom_type) => {
+            *out_type = rom_type_value(rom_type);
+            OK
+        }
+        None => ERR_UNRECOGNIZED,
+    })
+}
+
+/// Convert `len` bytes at `in_buf` from `src_type` to `dst_type` into `out_buf`.
+///
+/// `len` must be a multiple of 4. `out_buf` may be `in_buf` to convert in place.
+///
+/// # Safety
+///
+/// `in_buf` must point to `len` readable bytes and `out_buf` to `len` writable
+/// bytes. The two buffers are either the same or don't overlap.
+#[no_mangle]
+pub unsafe extern "C" fn n64swap_convert(
+    in_buf: *const u8,
+    len: usize,
+    src_type: c_int,
+    dst_type: c_int,
+    out_buf: *mut u8,
+) -> c_int {
+    let (Some(src_type), Some(dst_type)) = (rom_type(src_type), rom_type(dst_type)) else {
+        return ERR_INVALID_ARGUMENT;
+    };
+    if in_buf.is_null() || out_buf.is_null() {
+        return ERR_INVALID_ARGUMENT;
+    }
+    if !len.is_multiple_of(4) {
+        return ERR_SIZE;
+    }
+    if !std::ptr::eq(in_buf, out_buf) {
+        out_buf.copy_from_nonoverlapping(in_buf, len);
+    }
+    let out = slice::from_raw_parts_mut(out_buf, len);
+    guarded(move || {
+        swapper_simd(out, src_type, dst_type);
+        OK
+    })
+}
+
+/// Convert the rom file at `src_path` into `dst_type`, writing it to `dst_path`.
+///
+/// # Safety
+///
+/// `src_path` and `dst_path` must be NUL-terminated UTF-8 strings.
+#[no_mangle]
+pub unsafe extern "C" fn n64swap_convert_file(
+    src_path: *const c_char,
+    dst_path: *const c_char,
+    dst_type: c_int,
+) -> c_int {
+    let Some(dst_type) = rom_type(dst_type) else {
+        return ERR_INVALID_ARGUMENT;
+    };
+    if src_path.is_null() || dst_path.is_null() {
+        return ERR_INVALID_ARGUMENT;
+    }
+    let (Ok(src), Ok(dst)) = (
+        CStr::from_ptr(src_path).to_str(),
+        CStr::from_ptr(dst_path).to_str(),
+    ) else {
+        return ERR_INVALID_ARGUMENT;
+    };
+    guarded(
+        || match convert_file(Path::new(src), Path::new(dst), dst_type) {
+            Ok(()) => OK,
+            Err(error) => error_code(&error),
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CString;
+    use std::fs;
+    use std::ptr;
+
+    use super::*;
+    use crate::tests::TempFile;
+    use crate::{BIG_ENDIAN, BYTE_SWAP};
+
+    #[test]
+    fn identify() {
+        let mut out_type = -1;
+        unsafe {
+            assert_eq!(n64swap_identify(BYTE_SWAP.as_ptr(), &mut out_type), OK);
+            assert_eq!(out_type, 1);
+            assert_eq!(
+                n64swap_identify([0; 4].as_ptr(), &mut out_type),
+                ERR_UNRECOGNIZED
+            );
+            assert_eq!(
+                n64swap_identify(ptr::null(), &mut out_type),
+                ERR_INVALID_ARGUMENT
+            );
+            assert_eq!(
+                n64swap_identify(BYTE_SWAP.as_ptr(), ptr::null_mut()),
+                ERR_INVALID_ARGUMENT
+            );
+        }
+    }
+
+    #[test]
+    fn convert() {
+        let input = [0x37, 0x80, 0x40, 0x12, 1, 2, 3, 4];
+        let mut out = [0; 8];
+        unsafe {
+            assert_eq!(
+                n64swap_convert(input.as_ptr(), 8, 1, 0, out.as_mut_ptr()),
+                OK
+            );
+            assert_eq!(out, [0x80, 0x37, 0x12, 0x40, 2, 1, 4, 3]);
+
+            // In place
+            assert_eq!(n64swap_convert(out.as_ptr(), 8, 0, 1, out.as_mut_ptr()), OK);
+            assert_eq!(out, input);
+
+            assert_eq!(
+                n64swap_convert(input.as_ptr(), 6, 1, 0, out.as_mut_ptr()),
+                ERR_SIZE
+            );
+            assert_eq!(
+                n64swap_convert(input.as_ptr(), 8, 4, 0, out.as_mut_ptr()),
+                ERR_INVALID_ARGUMENT
+            );
+            assert_eq!(
+                n64swap_convert(ptr::null(), 8, 1, 0, out.as_mut_ptr()),
+                ERR_INVALID_ARGUMENT
+            );
+            assert_eq!(
+                n64swap_convert(input.as_ptr(), 8, 1, 0, ptr::null_mut()),
+                ERR_INVALID_ARGUMENT
+            );
+        }
+        assert_eq!(out, input);
+    }
+
+    #[test]
+    fn convert_file() {
+        let src = TempFile::new("ffi.v64", &[0x37, 0x80, 0x40, 0x12, 1, 2, 3, 4]);
+        let dst = TempFile::new("ffi.z64", &[]);
+        let src_path = CString::new(src.0.to_str().unwrap()).unwrap();
+        let dst_path = CString::new(dst.0.to_str().unwrap()).unwrap();
+        unsafe {
+            assert_eq!(
+                n64swap_convert_file(src_path.as_ptr(), dst_path.as_ptr(), 0),
+                OK
+            );
+            assert_eq!(
+                n64swap_convert_file(ptr::null(), dst_path.as_ptr(), 0),
+                ERR_INVALID_ARGUMENT
+            );
+            assert_eq!(
+                n64swap_convert_file(src_path.as_ptr(), dst_path.as_ptr(), -1),
+                ERR_INVALID_ARGUMENT
+            );
+        }
+        let mut expected = BIG_ENDIAN.to_vec();
+        expected.extend([2, 1, 4, 3]);
+        assert_eq!(fs::read(&dst.0).unwrap(), expected);
+
+        let missing = CString::new(src.0.with_extension("missing").to_str().unwrap()).unwrap();
+        unsafe {
+            assert_eq!(
+                n64swap_convert_file(missing.as_ptr(), dst_path.as_ptr(), 0),
+                ERR_IO
+            );
+        }
+    }
+}
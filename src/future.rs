@@ -0,0 +1,52 @@
+use std::future::Future;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+
+use crate::{convert_file, ConversionError, RomType};
+
+#[derive(Default)]
+struct Shared {
+    result: Option<Result<(), ConversionError>>,
+    waker: Option<Waker>,
+}
+
+/// A `convert_file` running on its own thread, see `convert_file_async`
+pub struct ConvertFuture {
+    shared: Arc<Mutex<Shared>>,
+}
+
+impl Future for ConvertFuture {
+    type Output = Result<(), ConversionError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut shared = self.shared.lock().unwrap_or_else(PoisonError::into_inner);
+        match shared.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                shared.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+/// Convert the rom at `src` into `dst_type` without blocking the calling task.
+///
+/// The conversion starts right away on a separate thread and the future
+/// resolves once it's done, on any executor.
+pub fn convert_file_async(src: PathBuf, dst: PathBuf, dst_type: RomType) -> ConvertFuture {
+    let shared = Arc::new(Mutex::new(Shared::default()));
+    let worker = Arc::clone(&shared);
+    thread::spawn(move || {
+        let result = convert_file(&src, &dst, dst_type);
+        let mut shared = worker.lock().unwrap_or_else(PoisonError::into_inner);
+        shared.result = Some(result);
+        if let Some(waker) = shared.waker.take() {
+            waker.wake();
+        }
+    });
+    ConvertFuture { shared }
+}
@@ -0,0 +1,187 @@
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// Whether an argument contains glob wildcards
+pub fn is_pattern(arg: &str) -> bool {
+    arg.contains(['*', '?', '['])
+}
+
+// Match a `[...]` class against `c`, returning the match and the rest of the pattern
+fn match_class(pattern: &[char], c: char) -> Option<(bool, &[char])> {
+    let (negated, mut rest) = match pattern {
+        ['!' | '^', rest @ ..] => (true, rest),
+        rest => (false, rest),
+    };
+    let mut matched = false;
+    let mut first = true;
+    loop {
+        match rest {
+            // A leading ] is part of the class
+            [']', tail @ ..] if !first => return Some((matched != negated, tail)),
+            [start, '-', end, tail @ ..] if *end != ']' => {
+                matched |= (*start..=*end).contains(&c);
+                rest = tail;
+            }
+            [single, tail @ ..] => {
+                matched |= *single == c;
+                rest = tail;
+            }
+            // Unclosed, not a class after all
+            [] => return None,
+        }
+        first = false;
+    }
+}
+
+// The usual two-pointer wildcard match: every other token takes one character, and
+// on a mismatch the last `*` takes one more instead, at most pattern × name steps
+fn matches(pattern: &[char], name: &[char]) -> bool {
+    let (mut p, mut n) = (0, 0);
+    // The pattern after the last `*`, and where in the name that `*` stops
+    let mut star = None;
+    while n < name.len() {
+        let next = match pattern.get(p) {
+            Some('*') => {
+                p += 1;
+                star = Some((p, n));
+                continue;
+            }
+            Some('?') => Some(p + 1),
+            Some('[') => match match_class(&pattern[p + 1..], name[n]) {
+                Some((matched, rest)) => matched.then_some(pattern.len() - rest.len()),
+                None => (name[n] == '[').then_some(p + 1),
+            },
+            Some(c) => (*c == name[n]).then_some(p + 1),
+            None => None,
+        };
+        match (next, star) {
+            (Some(next), _) => {
+                p = next;
+                n += 1;
+            }
+            (None, Some((after_star, skipped))) => {
+                p = after_star;
+                n = skipped + 1;
+                star = Some((after_star, n));
+            }
+            (None, None) => return false,
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+// Match a single path component, wildcards don't match a leading dot
+fn matches_name(pattern: &str, name: &str) -> bool {
+    if name.starts_with('.') && !pattern.starts_with('.') {
+        return false;
+    }
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    matches(&pattern, &name)
+}
+
+/// Expand a glob pattern into the existing paths matching it, sorted by name
+pub fn expand(pattern: &str) -> Vec<PathBuf> {
+    let mut paths = vec![PathBuf::new()];
+
+    for component in Path::new(pattern).components() {
+        let part = component.as_os_str().to_string_lossy();
+        if !matches!(component, Component::Normal(_)) || !is_pattern(&part) {
+            for path in &mut paths {
+                path.push(component);
+            }
+            continue;
+        }
+
+        let mut expanded = Vec::new();
+        for base in &paths {
+            let dir = if base.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                base.as_path()
+            };
+            let Ok(entries) = fs::read_dir(dir) else {
+                continue;
+            };
+            let mut names: Vec<String> = entries
+                .filter_map(|entry| entry.ok())
+                .filter_map(|entry| entry.file_name().into_string().ok())
+                .filter(|name| matches_name(&part, name))
+                .collect();
+            names.sort();
+            expanded.extend(names.into_iter().map(|name| base.join(name)));
+        }
+        paths = expanded;
+    }
+
+    paths.retain(|path| path.exists());
+    paths
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wildcards() {
+        for (pattern, name, expected) in [
+            ("*.z64", "rom.z64", true),
+            ("*.z64", "rom.v64", false),
+            ("rom_??.z64", "rom_01.z64", true),
+            ("rom_??.z64", "rom_1.z64", false),
+            ("*", "", true),
+            ("a*b*c", "aXbYbZc", true),
+            ("a*b*c", "aXbYbZ", false),
+            ("[ab", "[ab", true),
+        ] {
+            assert_eq!(
+                matches_name(pattern, name),
+                expected,
+                "{} {}",
+                pattern,
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn classes() {
+        for (pattern, name, expected) in [
+            ("rom.[vz]64", "rom.v64", true),
+            ("rom.[vz]64", "rom.n64", false),
+            ("rom_[0-2]", "rom_1", true),
+            ("rom_[0-2]", "rom_3", false),
+            ("rom_[!0-2]", "rom_3", true),
+            ("rom_[^0-2]", "rom_1", false),
+            ("[]a]", "]", true),
+            ("[]a]", "a", true),
+            ("[]a]", "b", false),
+            ("[!]]", "]", false),
+            ("[!]]", "x", true),
+            ("[a-]", "-", true),
+        ] {
+            assert_eq!(
+                matches_name(pattern, name),
+                expected,
+                "{} {}",
+                pattern,
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn hidden_files_need_a_dot() {
+        assert!(!matches_name("*", ".hidden"));
+        assert!(!matches_name("?hidden", ".hidden"));
+        assert!(matches_name(".*", ".hidden"));
+        assert!(matches_name("*hidden", "not.hidden"));
+    }
+
+    #[test]
+    fn many_stars_dont_blow_up() {
+        let name = "a".repeat(200);
+        assert!(!matches_name("*a*a*a*a*a*a*a*a*b", &name));
+        assert!(matches_name("*a*a*a*a*a*a*a*a*", &name));
+    }
+}
@@ -0,0 +1,160 @@
+use std::io::{self, Read, Write};
+
+use clap::ValueEnum;
+#[cfg(feature = "blake3")]
+use n64swap::Blake3;
+use n64swap::{Crc32, Md5, Sha1, Sha256};
+
+#[derive(Debug, PartialEq, Copy, Clone, ValueEnum)]
+pub enum HashAlgorithm {
+    Md5,
+    Sha1,
+    Sha256,
+    Crc32,
+    #[cfg(feature = "blake3")]
+    Blake3,
+}
+
+impl HashAlgorithm {
+    pub fn label(&self) -> &str {
+        match *self {
+            HashAlgorithm::Md5 => "MD5",
+            HashAlgorithm::Sha1 => "SHA1",
+            HashAlgorithm::Sha256 => "SHA256",
+            HashAlgorithm::Crc32 => "CRC32",
+            #[cfg(feature = "blake3")]
+            HashAlgorithm::Blake3 => "BLAKE3",
+        }
+    }
+}
+
+enum Hasher {
+    Md5(Md5),
+    Sha1(Sha1),
+    Sha256(Sha256),
+    Crc32(Crc32),
+    #[cfg(feature = "blake3")]
+    Blake3(Box<Blake3>),
+}
+
+impl Hasher {
+    fn new(algorithm: HashAlgorithm) -> Hasher {
+        match algorithm {
+            HashAlgorithm::Md5 => Hasher::Md5(Md5::new()),
+            HashAlgorithm::Sha1 => Hasher::Sha1(Sha1::new()),
+            HashAlgorithm::Sha256 => Hasher::Sha256(Sha256::new()),
+            HashAlgorithm::Crc32 => Hasher::Crc32(Crc32::new()),
+            #[cfg(feature = "blake3")]
+            HashAlgorithm::Blake3 => Hasher::Blake3(Box::default()),
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Md5(hasher) => hasher.update(data),
+            Hasher::Sha1(hasher) => hasher.update(data),
+            Hasher::Sha256(hasher) => hasher.update(data),
+            Hasher::Crc32(hasher) => hasher.update(data),
+            #[cfg(feature = "blake3")]
+            Hasher::Blake3(hasher) => hasher.update(data),
+        }
+    }
+
+    fn finish(self) -> String {
+        fn hex(digest: &[u8]) -> String {
+            digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+        }
+        match self {
+            Hasher::Md5(hasher) => hex(&hasher.finish()),
+            Hasher::Sha1(hasher) => hex(&hasher.finish()),
+            Hasher::Sha256(hasher) => hex(&hasher.finish()),
+            Hasher::Crc32(hasher) => format!("{:08X}", hasher.finish()),
+            #[cfg(feature = "blake3")]
+            Hasher::Blake3(hasher) => hex(&hasher.finish()),
+        }
+    }
+}
+
+/// Several digests of the same data, fed as a `Write` sink
+pub struct Hashes {
+    hashers: Vec<(HashAlgorithm, Hasher)>,
+}
+
+impl Hashes {
+    pub fn new(algorithms: &[HashAlgorithm]) -> Hashes {
+        let mut hashers: Vec<(HashAlgorithm, Hasher)> = Vec::new();
+        for &algorithm in algorithms {
+            if !hashers.iter().any(|(existing, _)| *existing == algorithm) {
+                hashers.push((algorithm, Hasher::new(algorithm)));
+            }
+        }
+        Hashes { hashers }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hashers.is_empty()
+    }
+
+    /// The hex digests, in the order the algorithms were asked for
+    pub fn finish(self) -> Vec<(HashAlgorithm, String)> {
+        self.hashers
+            .into_iter()
+            .map(|(algorithm, hasher)| (algorithm, hasher.finish()))
+            .collect()
+    }
+}
+
+impl Write for Hashes {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for (_, hasher) in &mut self.hashers {
+            hasher.update(buf);
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Writer adapter hashing everything written through it
+pub struct HashWriter<W: Write> {
+    inner: W,
+    hashes: Hashes,
+}
+
+impl<W: Write> HashWriter<W> {
+    pub fn new(inner: W, hashes: Hashes) -> HashWriter<W> {
+        HashWriter { inner, hashes }
+    }
+
+    pub fn into_parts(self) -> (W, Hashes) {
+        (self.inner, self.hashes)
+    }
+}
+
+impl<W: Write> Write for HashWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hashes.write_all(&buf[..written])?;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Reader adapter hashing everything read through it
+pub struct HashTee<'a, R: Read> {
+    pub inner: R,
+    pub hashes: &'a mut Hashes,
+}
+
+impl<R: Read> Read for HashTee<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.hashes.write_all(&buf[..read])?;
+        Ok(read)
+    }
+}
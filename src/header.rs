@@ -0,0 +1,310 @@
+use core::fmt;
+
+use crate::{identify_header, swapper, CountryCode, RomType};
+
+/// Size of the N64 rom header in bytes
+pub const HEADER_SIZE: usize = 64;
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum HeaderError {
+    /// The header magic doesn't match any known rom type
+    UnrecognizedMagic([u8; 4]),
+}
+
+impl fmt::Display for HeaderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HeaderError::UnrecognizedMagic(bytes) => write!(
+                f,
+                "Header {:02x} {:02x} {:02x} {:02x} not recognized",
+                bytes[0], bytes[1], bytes[2], bytes[3]
+            ),
+        }
+    }
+}
+
+impl std::error::Error for HeaderError {}
+
+/// Media format, from the byte at header offset 0x3B
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum MediaType {
+    /// 'N', regular cartridge
+    Cartridge,
+    /// 'D', 64DD disk
+    Disk,
+    /// 'C', cartridge part of an expandable game
+    Expandable,
+    /// 'E', 64DD expansion disk
+    DiskExpansion,
+    /// 'Z', Aleck64 arcade board
+    Aleck64,
+    Other(u8),
+}
+
+impl MediaType {
+    pub fn from_byte(b: u8) -> MediaType {
+        match b {
+            b'N' => MediaType::Cartridge,
+            b'D' => MediaType::Disk,
+            b'C' => MediaType::Expandable,
+            b'E' => MediaType::DiskExpansion,
+            b'Z' => MediaType::Aleck64,
+            other => MediaType::Other(other),
+        }
+    }
+}
+
+impl fmt::Display for MediaType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MediaType::Cartridge => write!(f, "Cartridge (N)"),
+            MediaType::Disk => write!(f, "64DD disk (D)"),
+            MediaType::Expandable => write!(f, "Expandable cartridge (C)"),
+            MediaType::DiskExpansion => write!(f, "64DD expansion (E)"),
+            MediaType::Aleck64 => write!(f, "Aleck 64 (Z)"),
+            MediaType::Other(b) => write!(f, "Unknown (0x{:02X})", b),
+        }
+    }
+}
+
+/// PI BSD domain 1 timing, the first header word.
+///
+/// Parsed after reordering into big-endian, these bytes double as the magic that
+/// identifies the byte order.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct PiBsdDomain1 {
+    /// Latency
+    pub lat: u8,
+    /// Pulse width
+    pub pwd: u8,
+    /// Page size
+    pub pgs: u8,
+    /// Release time
+    pub rls: u8,
+}
+
+impl fmt::Display for PiBsdDomain1 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "lat 0x{:02X}, pwd 0x{:02X}, pgs 0x{:02X}, rls 0x{:02X}",
+            self.lat, self.pwd, self.pgs, self.rls
+        )
+    }
+}
+
+/// The 64-byte N64 rom header, with all values in big-endian order.
+///
+/// It's decoded field by field rather than cast from the raw bytes, as the rom can
+/// be in any byte order and the enum fields don't accept every bit pattern.
+#[derive(Debug, PartialEq, Clone)]
+pub struct RomHeader {
+    /// On-disk byte order the header was parsed from
+    pub rom_type: RomType,
+    /// PI BSD domain 1 register values (0x00)
+    pub pi_bsd_dom1: PiBsdDomain1,
+    pub clock_rate: u32,
+    pub boot_address: u32,
+    pub release: u32,
+    pub crc1: u32,
+    pub crc2: u32,
+    /// Internal rom name, ASCII or Shift-JIS padded with spaces (0x20)
+    pub name: [u8; 20],
+    pub manufacturer_id: u32,
+    pub media_type: MediaType,
+    pub cart_id: [u8; 2],
+    pub country_code: CountryCode,
+    /// Rom revision (0x3F), 0 for the original release
+    pub version: u8,
+}
+
+fn read_u32(bytes: &[u8; HEADER_SIZE], offset: usize) -> u32 {
+    u32::from_be_bytes([
+        bytes[offset],
+        bytes[offset + 1],
+        bytes[offset + 2],
+        bytes[offset + 3],
+    ])
+}
+
+// Decode the title as ASCII, showing the bytes as hex otherwise
+fn decode_title(name: &[u8]) -> String {
+    let end = name
+        .iter()
+        .rposition(|&byte| byte != b' ' && byte != 0)
+        .map_or(0, |idx| idx + 1);
+    let name = &name[..end];
+
+    if name
+        .iter()
+        .all(|byte| byte.is_ascii() && !byte.is_ascii_control())
+    {
+        String::from_utf8_lossy(name).into_owned()
+    } else {
+        name.iter()
+            .map(|byte| format!("{:02X}", byte))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+/// Get the rom title from a big-endian header slice
+pub fn rom_title(header: &[u8]) -> String {
+    header.get(0x20..0x34).map(decode_title).unwrap_or_default()
+}
+
+/// Get the two-byte cart ID from a big-endian header slice
+pub fn cart_id(header: &[u8]) -> [u8; 2] {
+    match header.get(0x3C..0x3E) {
+        Some(&[first, second]) => [first, second],
+        _ => [0; 2],
+    }
+}
+
+/// Reorder a buffer from `src_type` into big-endian order
+pub fn normalize(bytes: &mut [u8], src_type: RomType) {
+    for chunk in bytes.chunks_exact_mut(4) {
+        if let Ok(chunk) = <&mut [u8; 4]>::try_from(chunk) {
+            swapper(chunk, src_type, RomType::BigEndian);
+        }
+    }
+}
+
+impl RomHeader {
+    /// Parse a raw header in any supported byte order
+    pub fn parse(bytes: &[u8; HEADER_SIZE]) -> Result<RomHeader, HeaderError> {
+        let magic = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        let rom_type = identify_header(&magic).ok_or(HeaderError::UnrecognizedMagic(magic))?;
+
+        let mut bytes = *bytes;
+        normalize(&mut bytes, rom_type);
+
+        let mut name = [0; 20];
+        name.copy_from_slice(&bytes[0x20..0x34]);
+
+        Ok(RomHeader {
+            rom_type,
+            pi_bsd_dom1: PiBsdDomain1 {
+                lat: bytes[0],
+                pwd: bytes[1],
+                pgs: bytes[2],
+                rls: bytes[3],
+            },
+            clock_rate: read_u32(&bytes, 0x04),
+            boot_address: read_u32(&bytes, 0x08),
+            release: read_u32(&bytes, 0x0C),
+            crc1: read_u32(&bytes, 0x10),
+            crc2: read_u32(&bytes, 0x14),
+            name,
+            manufacturer_id: read_u32(&bytes, 0x38),
+            media_type: MediaType::from_byte(bytes[0x3B]),
+            cart_id: cart_id(&bytes),
+            country_code: CountryCode::from_byte(bytes[0x3E]),
+            version: bytes[0x3F],
+        })
+    }
+
+    /// The rom title, as ASCII or hex bytes when it isn't
+    pub fn title(&self) -> String {
+        decode_title(&self.name)
+    }
+
+    /// The cart ID, as ASCII or hex bytes when it isn't
+    pub fn cart_id(&self) -> String {
+        if self.cart_id.iter().all(|byte| byte.is_ascii_graphic()) {
+            String::from_utf8_lossy(&self.cart_id).into_owned()
+        } else {
+            format!("{:02X} {:02X}", self.cart_id[0], self.cart_id[1])
+        }
+    }
+
+    /// The revision as "Rev X", like rom sets name them
+    pub fn revision(&self) -> String {
+        match self.version {
+            0 => "Rev 0 (original)".to_string(),
+            version => format!("Rev {}", version),
+        }
+    }
+}
+
+impl fmt::Display for RomHeader {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "PI BSD Domain 1: {}", self.pi_bsd_dom1)?;
+        writeln!(f, "Clock rate: 0x{:08X}", self.clock_rate)?;
+        writeln!(f, "Boot address: 0x{:08X}", self.boot_address)?;
+        writeln!(f, "Release: 0x{:08X}", self.release)?;
+        writeln!(f, "CRC1: 0x{:08X}", self.crc1)?;
+        writeln!(f, "CRC2: 0x{:08X}", self.crc2)?;
+        writeln!(f, "Title: {}", self.title())?;
+        writeln!(f, "Manufacturer: 0x{:08X}", self.manufacturer_id)?;
+        writeln!(f, "Media type: {}", self.media_type)?;
+        writeln!(f, "Cart ID: {}", self.cart_id())?;
+        match self.country_code {
+            CountryCode::Unknown(b) => writeln!(f, "Region: Unknown (0x{:02X})", b)?,
+            country => writeln!(f, "Region: {}", country.region_string())?,
+        }
+        write!(f, "Revision: {}", self.revision())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header() -> [u8; HEADER_SIZE] {
+        let mut bytes = [0; HEADER_SIZE];
+        bytes[..4].copy_from_slice(&crate::BIG_ENDIAN);
+        bytes[0x04..0x08].copy_from_slice(&0x0000000Fu32.to_be_bytes());
+        bytes[0x08..0x0C].copy_from_slice(&0x80000400u32.to_be_bytes());
+        bytes[0x0C..0x10].copy_from_slice(&0x00001449u32.to_be_bytes());
+        bytes[0x10..0x14].copy_from_slice(&0x635A2BFFu32.to_be_bytes());
+        bytes[0x14..0x18].copy_from_slice(&0x8B022326u32.to_be_bytes());
+        bytes[0x20..0x34].copy_from_slice(b"SUPER MARIO 64      ");
+        bytes[0x3B] = b'N';
+        bytes[0x3C..0x3E].copy_from_slice(b"SM");
+        bytes[0x3E] = b'E';
+        bytes[0x3F] = 1;
+        bytes
+    }
+
+    #[test]
+    fn parses_in_every_byte_order() {
+        let expected = RomHeader::parse(&header()).unwrap();
+        assert_eq!(expected.boot_address, 0x80000400);
+        assert_eq!(expected.crc1, 0x635A2BFF);
+        assert_eq!(expected.crc2, 0x8B022326);
+        assert_eq!(expected.title(), "SUPER MARIO 64");
+        assert_eq!(expected.media_type, MediaType::Cartridge);
+        assert_eq!(expected.cart_id(), "SM");
+        assert_eq!(expected.country_code, CountryCode::Usa);
+        assert_eq!(expected.version, 1);
+
+        for rom_type in [RomType::ByteSwap, RomType::LittleEndian, RomType::WordSwap] {
+            let mut bytes = header();
+            for chunk in bytes.chunks_exact_mut(4) {
+                swapper(chunk.try_into().unwrap(), RomType::BigEndian, rom_type);
+            }
+            let parsed = RomHeader::parse(&bytes).unwrap();
+            assert_eq!(parsed.rom_type, rom_type);
+            assert_eq!(
+                RomHeader {
+                    rom_type: RomType::BigEndian,
+                    ..parsed
+                },
+                expected,
+                "{}",
+                rom_type
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_a_bad_magic() {
+        let mut bytes = header();
+        bytes[3] = 0x41;
+        assert_eq!(
+            RomHeader::parse(&bytes),
+            Err(HeaderError::UnrecognizedMagic([0x80, 0x37, 0x12, 0x41]))
+        );
+    }
+}
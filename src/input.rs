@@ -0,0 +1,142 @@
+use std::fs::File;
+use std::io::{self, BufReader, Seek};
+use std::path::Path;
+
+use n64swap::{
+    check_file_size, file_size_is_valid, guess_path_type, guess_rom_type, guess_type_from_content,
+    identify_header, normalize_path_windows, read_checksum_region, read_rom_type, verify_crc,
+    ChecksumError, ConversionError, RomHeader, RomType, HEADER_SIZE,
+};
+
+use crate::{Args, STDIO};
+
+/// Open a file, naming it in the error
+pub fn open_input(filename: &Path) -> Result<File, ConversionError> {
+    File::open(normalize_path_windows(filename)).map_err(|error| {
+        let message = format!("Unable to open file {}: {}", filename.display(), error);
+        io::Error::new(error.kind(), message).into()
+    })
+}
+
+/// Misaligned roms are an error unless padding was asked for
+pub fn check_size(args: &Args, filename: &Path, size: u64) -> Result<(), ConversionError> {
+    if file_size_is_valid(size) {
+        return Ok(());
+    }
+    if !args.pad {
+        return check_file_size(size);
+    }
+    warning!(
+        "{} is {} bytes, padding it to {} bytes",
+        filename.display(),
+        size,
+        size.next_multiple_of(4)
+    );
+    Ok(())
+}
+
+/// Check the stored CRCs of a rom file, a mismatch is only an error unless forced
+pub fn verify_input_crc(args: &Args, filename: &Path) -> Result<(), ConversionError> {
+    let mut file = BufReader::new(open_input(filename)?);
+    let filetype = read_rom_type(&mut file)?;
+    file.rewind()?;
+    let rom = read_checksum_region(&mut file, filetype)?;
+
+    match verify_crc(&rom) {
+        Ok((crc1, crc2)) => {
+            success!(
+                args,
+                "CRCs of {} match: 0x{:08X} 0x{:08X}",
+                filename.display(),
+                crc1,
+                crc2
+            );
+            Ok(())
+        }
+        Err(error @ ChecksumError::Mismatch { .. }) if !args.force => Err(error.into()),
+        Err(error) => {
+            warning!("{} in {}", error, filename.display());
+            Ok(())
+        }
+    }
+}
+
+/// The byte order of a rom from its header, guessing it with --repair-header when
+/// the magic is damaged. Returns whether the magic in `header` was repaired.
+pub fn source_type(
+    args: &Args,
+    path: &Path,
+    header: &mut [u8],
+) -> Result<(RomType, bool), ConversionError> {
+    if args.strict_ext && path != Path::new(STDIO) && guess_path_type(path).is_none() {
+        return Err(ConversionError::UnknownExtension(path.to_path_buf()));
+    }
+    if let Some(rom_type) = args.from {
+        return Ok((rom_type, false));
+    }
+    let Some(&[a, b, c, d]) = header.get(..4) else {
+        return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+    };
+    let magic = [a, b, c, d];
+    if let Some(rom_type) = identify_header(&magic) {
+        return Ok((rom_type, false));
+    }
+    if !args.repair_header {
+        // Last resort, the magic may still be one in another byte order
+        let rom_type =
+            guess_type_from_content(&magic).ok_or(ConversionError::UnrecognizedHeader(magic))?;
+        warning!(
+            "Header {:02x} {:02x} {:02x} {:02x} of {} not recognized, reading it as {}",
+            a,
+            b,
+            c,
+            d,
+            path.display(),
+            rom_type
+        );
+        return Ok((rom_type, false));
+    }
+
+    let hint = guess_path_type(path);
+    let rom_type = guess_rom_type(header, hint)
+        .or_else(|| guess_type_from_content(&magic))
+        .ok_or(ConversionError::UnrecognizedHeader(magic))?;
+    warning!(
+        "Header {:02x} {:02x} {:02x} {:02x} of {} not recognized, repairing it as {}",
+        a,
+        b,
+        c,
+        d,
+        path.display(),
+        rom_type
+    );
+    header[..4].copy_from_slice(rom_type.get_header_bytes());
+    Ok((rom_type, true))
+}
+
+/// Parse a header read into memory, if it's complete
+pub fn parse_header(header: &[u8]) -> Option<RomHeader> {
+    let header = <[u8; HEADER_SIZE]>::try_from(header).ok()?;
+    RomHeader::parse(&header).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::parse;
+
+    #[test]
+    fn source_type_of_a_short_header() {
+        let args = parse(&["rom.z64"]);
+        let mut header = [0x40, 0x07, 0x27];
+        assert!(matches!(
+            source_type(&args, Path::new("rom.z64"), &mut header),
+            Err(ConversionError::IoError(error)) if error.kind() == io::ErrorKind::UnexpectedEof
+        ));
+        let mut header = [0x40, 0x07, 0x27, 0x80];
+        assert!(matches!(
+            source_type(&args, Path::new("rom.z64"), &mut header),
+            Ok((RomType::LittleEndian, false))
+        ));
+    }
+}
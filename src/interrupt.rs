@@ -0,0 +1,44 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+#[cfg(unix)]
+mod unix {
+    use std::ffi::c_int;
+    use std::sync::atomic::Ordering;
+
+    const SIGINT: c_int = 2;
+    const SIG_DFL: usize = 0;
+
+    extern "C" {
+        fn signal(signum: c_int, handler: usize) -> usize;
+    }
+
+    extern "C" fn on_sigint(_: c_int) {
+        super::INTERRUPTED.store(true, Ordering::SeqCst);
+        // A second Ctrl-C stops right away
+        // SAFETY: signal() is async-signal-safe
+        unsafe {
+            signal(SIGINT, SIG_DFL);
+        }
+    }
+
+    pub fn install() {
+        // SAFETY: the handler only stores to an atomic and resets itself
+        unsafe {
+            signal(SIGINT, on_sigint as extern "C" fn(c_int) as usize);
+        }
+    }
+}
+
+/// Catch Ctrl-C, so the work in progress can finish before exiting. Elsewhere
+/// than on unix Ctrl-C still stops the process at once.
+pub fn install() {
+    #[cfg(unix)]
+    unix::install();
+}
+
+/// Whether Ctrl-C was pressed since `install`
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
@@ -0,0 +1,140 @@
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+use clap::ValueEnum;
+use n64swap::{format_size, normalize_path_windows, ConversionError, RomHeader, HEADER_SIZE};
+
+use crate::csv;
+use crate::json::JsonObject;
+
+/// Columns of the --inventory table, in the order they're printed
+#[derive(Debug, PartialEq, Copy, Clone, ValueEnum)]
+pub enum Column {
+    Filename,
+    Type,
+    Title,
+    Region,
+    Crc1,
+    Crc2,
+    Size,
+}
+
+/// CSV header row, in the order of `Row::fields`
+pub const CSV_COLUMNS: [&str; 7] = [
+    "filename",
+    "rom_type",
+    "title",
+    "region",
+    "crc1",
+    "crc2",
+    "file_size_bytes",
+];
+
+/// What --inventory lists about a rom, only its header is read
+pub struct Row {
+    pub filename: PathBuf,
+    pub header: RomHeader,
+    pub size: u64,
+}
+
+impl Row {
+    pub fn read(path: &Path) -> Result<Row, ConversionError> {
+        let mut file = File::open(normalize_path_windows(path))?;
+        let size = file.metadata()?.len();
+        let mut raw = [0; HEADER_SIZE];
+        file.read_exact(&mut raw)?;
+        Ok(Row {
+            filename: path.to_path_buf(),
+            header: RomHeader::parse(&raw)?,
+            size,
+        })
+    }
+
+    // The values of the text table and the CSV, the size being in bytes
+    fn fields(&self) -> [String; 7] {
+        [
+            self.filename.to_string_lossy().into_owned(),
+            self.header.rom_type.name().to_string(),
+            self.header.title(),
+            self.header.country_code.region_string().to_string(),
+            format!("0x{:08X}", self.header.crc1),
+            format!("0x{:08X}", self.header.crc2),
+            self.size.to_string(),
+        ]
+    }
+
+    pub fn json(&self) -> JsonObject {
+        let [filename, rom_type, title, region, crc1, crc2, _] = self.fields();
+        JsonObject::new()
+            .string("filename", &filename)
+            .string("rom_type", &rom_type)
+            .string("title", &title)
+            .string("region", &region)
+            .string("crc1", &crc1)
+            .string("crc2", &crc2)
+            .number("file_size_bytes", self.size)
+    }
+}
+
+/// Sort the rows by a column, rows that tie keep their order
+pub fn sort(rows: &mut [Row], column: Column) {
+    match column {
+        Column::Filename => rows.sort_by(|a, b| a.filename.cmp(&b.filename)),
+        Column::Type => rows.sort_by_key(|row| row.header.rom_type.name().to_string()),
+        Column::Title => rows.sort_by_key(|row| row.header.title()),
+        Column::Region => {
+            rows.sort_by_key(|row| row.header.country_code.region_string().to_string())
+        }
+        Column::Crc1 => rows.sort_by_key(|row| row.header.crc1),
+        Column::Crc2 => rows.sort_by_key(|row| row.header.crc2),
+        Column::Size => rows.sort_by_key(|row| row.size),
+    }
+}
+
+/// Write the rows as a plain text table, with columns padded to their widest value
+pub fn write_table<W: Write>(writer: &mut W, rows: &[Row]) -> io::Result<()> {
+    let titles = [
+        "Filename", "Type", "Title", "Region", "CRC1", "CRC2", "Size",
+    ];
+    let cells: Vec<[String; 7]> = rows
+        .iter()
+        .map(|row| {
+            let mut fields = row.fields();
+            fields[6] = format_size(row.size);
+            fields
+        })
+        .collect();
+
+    let mut widths = titles.map(|title| title.chars().count());
+    for row in &cells {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    let write_line = |writer: &mut W, line: &[&str]| {
+        let padded: Vec<String> = line
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect();
+        writeln!(writer, "{}", padded.join("  ").trim_end())
+    };
+
+    write_line(writer, &titles)?;
+    let rule = widths.map(|width| "-".repeat(width));
+    write_line(writer, &rule.each_ref().map(String::as_str))?;
+    for row in &cells {
+        write_line(writer, &row.each_ref().map(String::as_str))?;
+    }
+    Ok(())
+}
+
+/// Write the rows as CSV, starting with a header row
+pub fn write_csv<W: Write>(writer: &mut W, rows: &[Row]) -> io::Result<()> {
+    csv::write_row(writer, &CSV_COLUMNS)?;
+    for row in rows {
+        csv::write_row(writer, &row.fields().each_ref().map(String::as_str))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,166 @@
+use core::fmt;
+
+const HEADER: &[u8] = b"PATCH";
+const FOOTER: &[u8] = b"EOF";
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum IpsError {
+    /// The patch doesn't start with "PATCH"
+    MissingHeader,
+    /// The patch ends in the middle of a record, or without "EOF"
+    Truncated,
+    /// A record writes past the end of the rom
+    OutOfBounds { offset: usize, len: usize },
+    /// There's data after "EOF", such as the truncation extension
+    TrailingData,
+}
+
+impl fmt::Display for IpsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            IpsError::MissingHeader => write!(f, "Not an IPS patch, the PATCH header is missing"),
+            IpsError::Truncated => write!(f, "IPS patch is truncated"),
+            IpsError::OutOfBounds { offset, len } => write!(
+                f,
+                "IPS record of {} bytes at 0x{:06X} is past the end of the rom",
+                len, offset
+            ),
+            IpsError::TrailingData => write!(f, "IPS patch has unsupported data after EOF"),
+        }
+    }
+}
+
+impl std::error::Error for IpsError {}
+
+// Take the next `len` bytes of the patch
+fn take<'a>(patch: &mut &'a [u8], len: usize) -> Result<&'a [u8], IpsError> {
+    if patch.len() < len {
+        return Err(IpsError::Truncated);
+    }
+    let (bytes, rest) = patch.split_at(len);
+    *patch = rest;
+    Ok(bytes)
+}
+
+fn read_uint(bytes: &[u8]) -> usize {
+    bytes
+        .iter()
+        .fold(0, |value, &byte| value << 8 | byte as usize)
+}
+
+/// Apply an IPS patch to a rom in memory.
+///
+/// Both plain and RLE records are supported. Records can't grow the rom, one
+/// writing past its end is an error, leaving the rom partially patched.
+pub fn apply_ips(output: &mut [u8], patch: &[u8]) -> Result<(), IpsError> {
+    let mut patch = patch.strip_prefix(HEADER).ok_or(IpsError::MissingHeader)?;
+
+    loop {
+        let offset = take(&mut patch, 3)?;
+        if offset == FOOTER {
+            break;
+        }
+        let offset = read_uint(offset);
+        let len = read_uint(take(&mut patch, 2)?);
+
+        // A zero length marks a run of one repeated byte
+        let (len, data) = if len == 0 {
+            let len = read_uint(take(&mut patch, 2)?);
+            (len, None)
+        } else {
+            (len, Some(take(&mut patch, len)?))
+        };
+        let target = output
+            .get_mut(offset..offset + len)
+            .ok_or(IpsError::OutOfBounds { offset, len })?;
+        match data {
+            Some(data) => target.copy_from_slice(data),
+            None => target.fill(take(&mut patch, 1)?[0]),
+        }
+    }
+
+    if !patch.is_empty() {
+        return Err(IpsError::TrailingData);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A patch of `records` between the header and footer
+    fn patch(records: &[&[u8]]) -> Vec<u8> {
+        let mut patch = HEADER.to_vec();
+        for record in records {
+            patch.extend_from_slice(record);
+        }
+        patch.extend_from_slice(FOOTER);
+        patch
+    }
+
+    #[test]
+    fn applies_a_plain_record() {
+        let mut rom = [0u8; 8];
+        apply_ips(&mut rom, &patch(&[&[0, 0, 2, 0, 3, 0xAA, 0xBB, 0xCC]])).unwrap();
+        assert_eq!(rom, [0, 0, 0xAA, 0xBB, 0xCC, 0, 0, 0]);
+    }
+
+    #[test]
+    fn applies_an_rle_record() {
+        let mut rom = [0u8; 8];
+        let records: [&[u8]; 2] = [&[0, 0, 1, 0, 0, 0, 4, 0x55], &[0, 0, 7, 0, 1, 0x11]];
+        apply_ips(&mut rom, &patch(&records)).unwrap();
+        assert_eq!(rom, [0, 0x55, 0x55, 0x55, 0x55, 0, 0, 0x11]);
+    }
+
+    #[test]
+    fn rejects_records_past_the_end() {
+        let mut rom = [0u8; 8];
+        assert_eq!(
+            apply_ips(&mut rom, &patch(&[&[0, 0, 6, 0, 3, 1, 2, 3]])),
+            Err(IpsError::OutOfBounds { offset: 6, len: 3 })
+        );
+        assert_eq!(
+            apply_ips(&mut rom, &patch(&[&[0, 0, 8, 0, 0, 0, 1, 0xFF]])),
+            Err(IpsError::OutOfBounds { offset: 8, len: 1 })
+        );
+        assert_eq!(rom, [0; 8]);
+    }
+
+    #[test]
+    fn rejects_truncated_patches() {
+        let full = patch(&[
+            &[0, 0, 2, 0, 3, 0xAA, 0xBB, 0xCC],
+            &[0, 0, 1, 0, 0, 0, 4, 0x55],
+        ]);
+        for len in HEADER.len()..full.len() {
+            let mut rom = [0u8; 8];
+            assert_eq!(
+                apply_ips(&mut rom, &full[..len]),
+                Err(IpsError::Truncated),
+                "{} bytes",
+                len
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_a_missing_header() {
+        let mut rom = [0u8; 8];
+        assert_eq!(apply_ips(&mut rom, b"PATC"), Err(IpsError::MissingHeader));
+        assert_eq!(
+            apply_ips(&mut rom, b"BPS1\0\0\0\0EOF"),
+            Err(IpsError::MissingHeader)
+        );
+    }
+
+    #[test]
+    fn rejects_trailing_data() {
+        let mut rom = [0u8; 8];
+        // The truncation extension, a 3-byte size after EOF
+        let mut patch = patch(&[]);
+        patch.extend_from_slice(&[0, 0, 4]);
+        assert_eq!(apply_ips(&mut rom, &patch), Err(IpsError::TrailingData));
+    }
+}
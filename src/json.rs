@@ -0,0 +1,95 @@
+use core::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// Quote and escape a string for JSON
+fn quote(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Minimal builder for a flat, compact JSON object
+#[derive(Default)]
+pub struct JsonObject {
+    fields: Vec<(String, String)>,
+}
+
+impl JsonObject {
+    pub fn new() -> JsonObject {
+        JsonObject::default()
+    }
+
+    pub fn string(mut self, key: &str, value: &str) -> JsonObject {
+        self.fields.push((quote(key), quote(value)));
+        self
+    }
+
+    pub fn number(mut self, key: &str, value: u64) -> JsonObject {
+        self.fields.push((quote(key), value.to_string()));
+        self
+    }
+
+    pub fn optional_number(mut self, key: &str, value: Option<u64>) -> JsonObject {
+        let value = value.map_or_else(|| "null".to_string(), |value| value.to_string());
+        self.fields.push((quote(key), value));
+        self
+    }
+}
+
+impl fmt::Display for JsonObject {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{{")?;
+        for (idx, (key, value)) in self.fields.iter().enumerate() {
+            if idx > 0 {
+                write!(f, ",")?;
+            }
+            write!(f, "{}:{}", key, value)?;
+        }
+        write!(f, "}}")
+    }
+}
+
+/// Write `objects` to `path` as a JSON array, one object per line.
+///
+/// With `append` the objects are added to the array already in the file.
+pub fn write_array(path: &Path, objects: &[JsonObject], append: bool) -> io::Result<()> {
+    let mut entries = Vec::new();
+    if append {
+        match fs::read_to_string(path) {
+            Ok(existing) if !existing.trim().is_empty() => {
+                let inner = existing
+                    .trim()
+                    .strip_prefix('[')
+                    .and_then(|rest| rest.strip_suffix(']'))
+                    .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "not a JSON array"))?
+                    .trim();
+                if !inner.is_empty() {
+                    entries.push(inner.to_string());
+                }
+            }
+            Ok(_) => {}
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {}
+            Err(error) => return Err(error),
+        }
+    }
+    entries.extend(objects.iter().map(|object| object.to_string()));
+
+    let mut contents = String::from("[\n");
+    contents.push_str(&entries.join(",\n"));
+    contents.push_str("\n]\n");
+    fs::write(path, contents)
+}
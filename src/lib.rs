@@ -0,0 +1,1213 @@
+use core::fmt;
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use clap::ValueEnum;
+
+#[cfg(feature = "blake3")]
+mod blake3;
+mod bps;
+mod checksum;
+mod cic;
+mod converter;
+mod country;
+mod crc32;
+mod error;
+#[cfg(feature = "ffi")]
+mod ffi;
+#[cfg(feature = "async")]
+mod future;
+mod header;
+mod ips;
+mod md5;
+#[cfg(all(feature = "mmap", unix, target_pointer_width = "64"))]
+mod mmap;
+mod save;
+mod sha1;
+mod sha256;
+mod simd;
+mod split;
+
+#[cfg(feature = "blake3")]
+pub use blake3::{blake3, Blake3};
+pub use bps::{apply_bps, apply_bps_unverified, BpsError};
+pub use checksum::{
+    calculate_crc, calculate_crc_for, fix_crc, read_checksum_region, verify_crc, ChecksumError,
+    CHECKSUM_LENGTH, CHECKSUM_START,
+};
+pub use cic::{detect_ipl3, CicVariant, IPL3_SIZE, IPL3_START};
+pub use converter::RomConverter;
+pub use country::CountryCode;
+pub use crc32::{crc32, Crc32};
+pub use error::ConversionError;
+#[cfg(feature = "async")]
+pub use future::{convert_file_async, ConvertFuture};
+pub use header::{
+    cart_id, normalize, rom_title, HeaderError, MediaType, PiBsdDomain1, RomHeader, HEADER_SIZE,
+};
+pub use ips::{apply_ips, IpsError};
+pub use md5::{md5, Md5};
+#[cfg(all(feature = "mmap", unix, target_pointer_width = "64"))]
+pub use mmap::convert_mmap;
+pub use save::{detect_save_type, SaveType};
+pub use sha1::{sha1, Sha1};
+pub use sha256::{sha256, Sha256};
+pub use simd::swapper_simd;
+pub use split::{merge_roms, split_paths, split_rom};
+
+// N64 header magic bytes
+pub const BIG_ENDIAN: [u8; 4] = [0x80, 0x37, 0x12, 0x40];
+pub const BYTE_SWAP: [u8; 4] = [0x37, 0x80, 0x40, 0x12];
+pub const LITTLE_ENDIAN: [u8; 4] = [0x40, 0x12, 0x37, 0x80];
+pub const WORD_SWAP: [u8; 4] = [0x12, 0x40, 0x80, 0x37];
+
+// Start of the header word holding the country code
+const COUNTRY_WORD: u64 = 0x3C;
+
+#[derive(Debug, PartialEq, Copy, Clone, ValueEnum)]
+pub enum RomType {
+    /// (commonly .z64)
+    BigEndian,
+    /// (commonly .v64)
+    ByteSwap,
+    /// (commonly .n64)
+    LittleEndian,
+    /// Halfwords of each word swapped (also .n64), only detected by its magic
+    #[value(alias = "halfword-swap")]
+    WordSwap,
+}
+
+impl fmt::Display for RomType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RomType::BigEndian => write!(f, "BigEndian (.z64)"),
+            RomType::ByteSwap => write!(f, "ByteSwap (.v64)"),
+            RomType::LittleEndian => write!(f, "LittleEndian (.n64)"),
+            RomType::WordSwap => write!(f, "WordSwap (.n64)"),
+        }
+    }
+}
+
+impl RomType {
+    /// Plain name of the type, without the extension
+    pub fn name(&self) -> &str {
+        match *self {
+            RomType::BigEndian => "BigEndian",
+            RomType::ByteSwap => "ByteSwap",
+            RomType::LittleEndian => "LittleEndian",
+            RomType::WordSwap => "WordSwap",
+        }
+    }
+
+    pub fn get_file_ext(&self) -> &str {
+        match *self {
+            RomType::BigEndian => ".z64",
+            RomType::ByteSwap => ".v64",
+            RomType::LittleEndian | RomType::WordSwap => ".n64",
+        }
+    }
+
+    pub fn get_header_bytes(&self) -> &[u8; 4] {
+        match *self {
+            RomType::BigEndian => &BIG_ENDIAN,
+            RomType::ByteSwap => &BYTE_SWAP,
+            RomType::LittleEndian => &LITTLE_ENDIAN,
+            RomType::WordSwap => &WORD_SWAP,
+        }
+    }
+}
+
+/// Guess the rom type from the extension of a path
+pub fn guess_path_type(path: &Path) -> Option<RomType> {
+    let ext = path.extension()?.to_str()?;
+    [RomType::BigEndian, RomType::ByteSwap, RomType::LittleEndian]
+        .into_iter()
+        .find(|rom_type| rom_type.get_file_ext()[1..].eq_ignore_ascii_case(ext))
+}
+
+/// Identify the rom type from the first 4 bytes of the file
+pub fn identify_header(bytes: &[u8; 4]) -> Option<RomType> {
+    match *bytes {
+        BIG_ENDIAN => Some(RomType::BigEndian),
+        BYTE_SWAP => Some(RomType::ByteSwap),
+        LITTLE_ENDIAN => Some(RomType::LittleEndian),
+        WORD_SWAP => Some(RomType::WordSwap),
+        _ => None,
+    }
+}
+
+/// Guess the byte order of a header whose magic is damaged, from how plausible the
+/// boot address looks in each order. `hint` (e.g. from the file extension) wins when
+/// several orders, or none, look plausible.
+pub fn guess_rom_type(header: &[u8], hint: Option<RomType>) -> Option<RomType> {
+    let candidates: Vec<RomType> = [RomType::BigEndian, RomType::ByteSwap, RomType::LittleEndian]
+        .into_iter()
+        .filter(|&rom_type| {
+            let Some(word) = header.get(0x08..0x0C) else {
+                return false;
+            };
+            let mut word = [word[0], word[1], word[2], word[3]];
+            swapper(&mut word, rom_type, RomType::BigEndian);
+            // Roms boot into KSEG0
+            word[0] == 0x80
+        })
+        .collect();
+    match (candidates.as_slice(), hint) {
+        ([rom_type], _) => Some(*rom_type),
+        (_, Some(hint)) if candidates.is_empty() || candidates.contains(&hint) => Some(hint),
+        _ => None,
+    }
+}
+
+/// Guess the byte order of a magic `identify_header` doesn't know, e.g. the
+/// `80 27 07 40` of 64DD roms or a prototype's `80 37 12 41`, by trying it in every
+/// order. The first byte of the magic is always 0x80, so the order is only picked
+/// when a single one puts a 0x80 byte first.
+pub fn guess_type_from_content(header: &[u8; 4]) -> Option<RomType> {
+    let mut candidates = [
+        RomType::BigEndian,
+        RomType::ByteSwap,
+        RomType::LittleEndian,
+        RomType::WordSwap,
+    ]
+    .into_iter()
+    .filter(|&rom_type| {
+        let mut word = *header;
+        swapper(&mut word, rom_type, RomType::BigEndian);
+        word[0] == 0x80
+    });
+    match (candidates.next(), candidates.next()) {
+        (Some(rom_type), None) => Some(rom_type),
+        _ => None,
+    }
+}
+
+/// Overwrite the header magic of a rom file, leaving the rest as it is
+pub fn repair_header(file: &mut File, magic: &[u8; 4]) -> io::Result<()> {
+    file.seek(SeekFrom::Start(0))?;
+    file.write_all(magic)?;
+    file.flush()
+}
+
+/// Copy the headerless rom at `src` to `dst` behind the magic of `rom_type`, the
+/// rest of the bytes are written as they are
+pub fn prepend_magic(src: &Path, dst: &Path, rom_type: RomType) -> Result<(), ConversionError> {
+    let mut reader = BufReader::new(File::open(normalize_path_windows(src))?);
+    let mut writer = BufWriter::new(File::create(normalize_path_windows(dst))?);
+    writer.write_all(rom_type.get_header_bytes())?;
+    io::copy(&mut reader, &mut writer)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Overwrite the country code at header offset 0x3E of a rom file, in its byte order
+pub fn set_country_code<F: Read + Write + Seek>(
+    file: &mut F,
+    rom_type: RomType,
+    country: CountryCode,
+) -> io::Result<()> {
+    // The byte can move within its word, so the whole word is reordered
+    let mut word = [0; 4];
+    file.seek(SeekFrom::Start(COUNTRY_WORD))?;
+    file.read_exact(&mut word)?;
+    swapper(&mut word, rom_type, RomType::BigEndian);
+    word[2] = country.to_byte();
+    swapper(&mut word, RomType::BigEndian, rom_type);
+    file.seek(SeekFrom::Start(COUNTRY_WORD))?;
+    file.write_all(&word)?;
+    file.flush()
+}
+
+/// Convert a single 4-byte chunk from one rom type to another
+pub fn swapper(bytes: &mut [u8; 4], src_type: RomType, dst_type: RomType) {
+    match (src_type, dst_type) {
+        (RomType::BigEndian, RomType::ByteSwap)
+        | (RomType::ByteSwap, RomType::BigEndian)
+        | (RomType::LittleEndian, RomType::WordSwap)
+        | (RomType::WordSwap, RomType::LittleEndian) => {
+            bytes.swap(0, 1);
+            bytes.swap(2, 3);
+        }
+        (RomType::BigEndian, RomType::LittleEndian)
+        | (RomType::LittleEndian, RomType::BigEndian)
+        | (RomType::ByteSwap, RomType::WordSwap)
+        | (RomType::WordSwap, RomType::ByteSwap) => {
+            bytes.swap(0, 3);
+            bytes.swap(1, 2);
+        }
+        (RomType::ByteSwap, RomType::LittleEndian)
+        | (RomType::LittleEndian, RomType::ByteSwap)
+        | (RomType::BigEndian, RomType::WordSwap)
+        | (RomType::WordSwap, RomType::BigEndian) => {
+            bytes.swap(0, 2);
+            bytes.swap(1, 3);
+        }
+        _ => {}
+    }
+}
+
+/// Convert a whole rom in memory from one rom type to another, 4 bytes at a time.
+///
+/// Returns `SizeMisaligned` if `data` isn't a whole number of 4-byte words, a
+/// buffer that's already `dst` is left untouched.
+pub fn convert_bytes(src: RomType, dst: RomType, data: &mut [u8]) -> Result<(), ConversionError> {
+    if src == dst {
+        return Ok(());
+    }
+    check_file_size(data.len() as u64)?;
+    swapper_simd(data, src, dst);
+    Ok(())
+}
+
+/// A byte count in MiB, KiB or bytes, e.g. "8.00 MiB"
+pub fn format_size(size: u64) -> String {
+    const KIB: u64 = 1024;
+    const MIB: u64 = 1024 * KIB;
+    if size >= MIB {
+        format!("{:.2} MiB", size as f64 / MIB as f64)
+    } else if size >= KIB {
+        format!("{:.2} KiB", size as f64 / KIB as f64)
+    } else {
+        format!("{} B", size)
+    }
+}
+
+/// Roms are made of 4-byte words, anything else can't be swapped cleanly
+pub fn file_size_is_valid(size: u64) -> bool {
+    size.is_multiple_of(4)
+}
+
+/// The smallest power of 2 size at least as big as `size`, what flash carts expect
+pub fn next_power_of_two_size(size: u64) -> u64 {
+    match size {
+        0 => 0,
+        size => size.checked_next_power_of_two().unwrap_or(size),
+    }
+}
+
+/// Generic extensions some dumps use, the rom type comes from the header alone
+pub const ALIAS_EXTS: [&str; 2] = [".rom", ".bin"];
+
+/// Whether `ext` (including the dot) is one of the `ALIAS_EXTS`
+pub fn is_alias_ext(ext: &str) -> bool {
+    ALIAS_EXTS
+        .iter()
+        .any(|alias| alias.eq_ignore_ascii_case(ext))
+}
+
+// A rom extension, or an alias one on a file with a rom header
+fn has_rom_ext(path: &Path) -> bool {
+    if guess_path_type(path).is_some() {
+        return true;
+    }
+    let Some(ext) = path.extension().and_then(|ext| ext.to_str()) else {
+        return false;
+    };
+    is_alias_ext(&format!(".{}", ext))
+        && File::open(path).is_ok_and(|mut file| read_rom_type(&mut file).is_ok())
+}
+
+/// Longest path Windows opens without the `\\?\` prefix
+pub const MAX_PATH: usize = 260;
+
+/// Give a path longer than `MAX_PATH` the extended-length `\\?\` prefix on Windows,
+/// which it has to be absolute for. Shorter paths, and any path on other platforms,
+/// are returned as they are.
+pub fn normalize_path_windows(path: &Path) -> PathBuf {
+    #[cfg(windows)]
+    if path.as_os_str().len() >= MAX_PATH {
+        if let Some(absolute) = std::path::absolute(path)
+            .ok()
+            .and_then(|absolute| absolute.to_str().map(str::to_string))
+        {
+            if absolute.starts_with(r"\\?\") {
+                return PathBuf::from(absolute);
+            }
+            return match absolute.strip_prefix(r"\\") {
+                // \\server\share\... is \\?\UNC\server\share\...
+                Some(unc) => PathBuf::from(format!(r"\\?\UNC\{}", unc)),
+                None => PathBuf::from(format!(r"\\?\{}", absolute)),
+            };
+        }
+    }
+    path.to_path_buf()
+}
+
+/// `<stem>.<ext>` in `dir`, or the first of `<stem>_2.<ext>`, `<stem>_3.<ext>`...
+/// that doesn't exist yet
+pub fn unique_output_path(dir: &Path, stem: &str, ext: &str) -> PathBuf {
+    unique_output_path_with(dir, stem, ext, |path| path.exists())
+}
+
+/// Like `unique_output_path`, with `taken` telling which paths are in use, e.g.
+/// also the ones claimed by other files of a batch
+pub fn unique_output_path_with<F: FnMut(&Path) -> bool>(
+    dir: &Path,
+    stem: &str,
+    ext: &str,
+    mut taken: F,
+) -> PathBuf {
+    let name = |suffix: String| match ext {
+        "" => dir.join(format!("{}{}", stem, suffix)),
+        _ => dir.join(format!("{}{}.{}", stem, suffix, ext)),
+    };
+    let mut unique = name(String::new());
+    for number in 2.. {
+        if !taken(&unique) {
+            break;
+        }
+        unique = name(format!("_{}", number));
+    }
+    unique
+}
+
+// Refuse to write `dst` when it's the file `src` is read from, creating it would
+// truncate the input before it's read
+pub(crate) fn check_distinct_paths(src: &Path, dst: &Path) -> Result<(), ConversionError> {
+    let (Ok(src), Ok(dst_canonical)) = (src.canonicalize(), dst.canonicalize()) else {
+        return Ok(());
+    };
+    if src == dst_canonical {
+        return Err(ConversionError::InputOutputSameFile(dst.to_path_buf()));
+    }
+    Ok(())
+}
+
+/// Check a rom size, returning `SizeMisaligned` if it isn't a multiple of 4 bytes
+pub fn check_file_size(size: u64) -> Result<(), ConversionError> {
+    if file_size_is_valid(size) {
+        Ok(())
+    } else {
+        Err(ConversionError::SizeMisaligned {
+            actual: size,
+            expected: size.next_multiple_of(4),
+        })
+    }
+}
+
+/// How a directory scan treats symbolic links
+#[derive(Debug, PartialEq, Copy, Clone, Default)]
+pub enum Symlinks {
+    /// Leave out every symlink, reporting it in `RomScan::skipped_symlinks`
+    Skip,
+    /// Follow symlinked files, but not directories
+    #[default]
+    Files,
+    /// Follow symlinked files and directories, each directory only once
+    All,
+}
+
+/// What `scan_roms` looks at
+#[derive(Debug, PartialEq, Copy, Clone, Default)]
+pub struct ScanOptions {
+    /// Also search subdirectories
+    pub recursive: bool,
+    /// How many levels of subdirectories, unlimited with `None`
+    pub max_depth: Option<usize>,
+    /// Don't skip subdirectories and files starting with a dot
+    pub include_hidden: bool,
+    pub symlinks: Symlinks,
+}
+
+/// The roms a directory scan found, sorted by name
+#[derive(Debug, Default)]
+pub struct RomScan {
+    pub roms: Vec<PathBuf>,
+    /// Symlinks left out because of `Symlinks::Skip`
+    pub skipped_symlinks: Vec<PathBuf>,
+}
+
+// Entries never worth descending into or converting
+fn is_skipped(name: &str, include_hidden: bool) -> bool {
+    name == ".git" || name == ".DS_Store" || (!include_hidden && name.starts_with('.'))
+}
+
+fn walk_roms(
+    dir: &Path,
+    depth: usize,
+    options: &ScanOptions,
+    visited: &mut HashSet<PathBuf>,
+    scan: &mut RomScan,
+) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        if options.recursive
+            && is_skipped(&entry.file_name().to_string_lossy(), options.include_hidden)
+        {
+            continue;
+        }
+
+        let file_type = entry.file_type()?;
+        let is_dir = if file_type.is_symlink() {
+            match options.symlinks {
+                Symlinks::Skip => {
+                    if (options.recursive && path.is_dir()) || has_rom_ext(&path) {
+                        scan.skipped_symlinks.push(path);
+                    }
+                    continue;
+                }
+                // Symlinked directories could loop, visited catches that
+                Symlinks::Files => false,
+                Symlinks::All => path.is_dir(),
+            }
+        } else {
+            file_type.is_dir()
+        };
+
+        if is_dir {
+            let descend =
+                options.recursive && options.max_depth.is_none_or(|max_depth| depth < max_depth);
+            if descend && visited.insert(path.canonicalize()?) {
+                walk_roms(&path, depth + 1, options, visited, scan)?;
+            }
+        } else if has_rom_ext(&path) && path.is_file() {
+            scan.roms.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// List the roms in `dir`: files with a recognized rom extension, and `.rom` and
+/// `.bin` files whose header identifies them as a rom.
+///
+/// Recursive scans skip `.git`, `.DS_Store` and, unless `include_hidden` is set,
+/// other entries starting with a dot.
+pub fn scan_roms(dir: &Path, options: &ScanOptions) -> io::Result<RomScan> {
+    let mut scan = RomScan::default();
+    let mut visited = HashSet::new();
+    visited.insert(dir.canonicalize()?);
+    walk_roms(dir, 0, options, &mut visited, &mut scan)?;
+    scan.roms.sort();
+    scan.skipped_symlinks.sort();
+    Ok(scan)
+}
+
+/// List the roms in `dir` like `scan_roms`, sorted by name
+pub fn find_roms(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    Ok(scan_roms(dir, &ScanOptions::default())?.roms)
+}
+
+/// Like `find_roms`, but also searching subdirectories up to `max_depth` levels deep
+/// (unlimited with `None`). `.git`, `.DS_Store` and, unless `include_hidden` is set,
+/// other entries starting with a dot are skipped.
+pub fn find_roms_recursive(
+    dir: &Path,
+    max_depth: Option<usize>,
+    include_hidden: bool,
+) -> io::Result<Vec<PathBuf>> {
+    let options = ScanOptions {
+        recursive: true,
+        max_depth,
+        include_hidden,
+        symlinks: Symlinks::Files,
+    };
+    Ok(scan_roms(dir, &options)?.roms)
+}
+
+/// Bytes read and swapped at a time while converting, unless told otherwise
+pub const DEFAULT_BUFFER_SIZE: usize = 64 * 1024;
+
+// Fill as much of the chunk as possible, returning how many bytes were read
+fn read_chunk<R: Read>(reader: &mut R, bytes: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < bytes.len() {
+        match reader.read(&mut bytes[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+            Err(error) => return Err(error),
+        }
+    }
+    Ok(filled)
+}
+
+/// Read the header magic from a stream and identify the rom type
+pub fn read_rom_type<R: Read>(reader: &mut R) -> Result<RomType, ConversionError> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    identify_header(&bytes).ok_or(ConversionError::UnrecognizedHeader(bytes))
+}
+
+/// Read the IPL3 bootcode that follows the header, reordered into big-endian
+pub fn read_bootcode<R: Read>(reader: &mut R, rom_type: RomType) -> io::Result<Vec<u8>> {
+    let mut bootcode = vec![0; IPL3_SIZE];
+    reader.read_exact(&mut bootcode)?;
+    normalize(&mut bootcode, rom_type);
+    Ok(bootcode)
+}
+
+/// The padding byte a rom ends in, if its last word is all 0x00 or all 0xFF
+pub fn padding_byte<R: Read + Seek>(reader: &mut R) -> io::Result<Option<u8>> {
+    let len = reader.seek(SeekFrom::End(0))?;
+    if len < 4 {
+        return Ok(None);
+    }
+    let mut word = [0; 4];
+    reader.seek(SeekFrom::Start(len - 4))?;
+    reader.read_exact(&mut word)?;
+    Ok(match word {
+        [0x00, 0x00, 0x00, 0x00] => Some(0x00),
+        [0xFF, 0xFF, 0xFF, 0xFF] => Some(0xFF),
+        _ => None,
+    })
+}
+
+/// Like `padding_byte`, for the rom at `file`
+pub fn detect_padding_byte(file: &Path) -> Option<u8> {
+    File::open(file)
+        .and_then(|mut file| padding_byte(&mut file))
+        .ok()
+        .flatten()
+}
+
+/// The length of a rom without its trailing words of `byte` padding.
+///
+/// Only whole 4-byte words are cut, so the byte order of what's left is intact. A
+/// partial word at the end goes with the padding when all of it is `byte`, otherwise
+/// nothing is cut.
+pub fn trimmed_len<R: Read + Seek>(reader: &mut R, byte: u8) -> io::Result<u64> {
+    let len = reader.seek(SeekFrom::End(0))?;
+    let mut end = len / 4 * 4;
+    if end < len {
+        let mut tail = [0; 4];
+        let tail = &mut tail[..(len - end) as usize];
+        reader.seek(SeekFrom::Start(end))?;
+        reader.read_exact(tail)?;
+        if tail.iter().any(|&b| b != byte) {
+            return Ok(len);
+        }
+    }
+    let mut bytes = vec![0; DEFAULT_BUFFER_SIZE];
+
+    while end > 0 {
+        let len = end.min(bytes.len() as u64) as usize;
+        reader.seek(SeekFrom::Start(end - len as u64))?;
+        reader.read_exact(&mut bytes[..len])?;
+        match bytes[..len]
+            .chunks_exact(4)
+            .rposition(|word| word.iter().any(|&b| b != byte))
+        {
+            Some(idx) => return Ok(end - len as u64 + (idx as u64 + 1) * 4),
+            None => end -= len as u64,
+        }
+    }
+    Ok(0)
+}
+
+/// The size of the rom at `path` without its trailing 0x00 or 0xFF padding, the
+/// whole size if it isn't padded
+pub fn detect_content_size(path: &Path) -> io::Result<u64> {
+    let mut file = File::open(normalize_path_windows(path))?;
+    match padding_byte(&mut file)? {
+        Some(byte) => trimmed_len(&mut file, byte),
+        None => file.seek(SeekFrom::End(0)),
+    }
+}
+
+/// Read and parse the full rom header from a stream
+pub fn read_header<R: Read>(reader: &mut R) -> Result<RomHeader, ConversionError> {
+    let mut bytes = [0; HEADER_SIZE];
+    reader.read_exact(&mut bytes)?;
+    Ok(RomHeader::parse(&bytes)?)
+}
+
+/// Convert a whole rom like `convert`, swapping `buffer_size` bytes at a time.
+///
+/// The buffer size must be a non-zero multiple of 4. With `pad` a trailing partial
+/// chunk is zero-padded instead of failing.
+pub fn convert_buffered<R: Read, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    src_type: RomType,
+    dst_type: RomType,
+    pad: bool,
+    buffer_size: usize,
+) -> Result<u64, ConversionError> {
+    convert_chunks(
+        reader,
+        writer,
+        src_type,
+        dst_type,
+        pad,
+        buffer_size,
+        &mut |_| {},
+    )
+}
+
+// The loop of `convert_buffered`, calling `progress` with the bytes written so
+// far after each chunk
+fn convert_chunks<R: Read, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    src_type: RomType,
+    dst_type: RomType,
+    pad: bool,
+    buffer_size: usize,
+    progress: &mut dyn FnMut(u64),
+) -> Result<u64, ConversionError> {
+    if buffer_size == 0 || !file_size_is_valid(buffer_size as u64) {
+        return Err(ConversionError::InvalidBufferSize(buffer_size));
+    }
+    let mut bytes = vec![0; buffer_size];
+    let mut written = 0;
+
+    loop {
+        let filled = read_chunk(reader, &mut bytes)?;
+        if filled == 0 {
+            break;
+        }
+        let mut len = filled;
+        if !file_size_is_valid(filled as u64) {
+            if !pad {
+                check_file_size(written + filled as u64)?;
+            }
+            len = filled.next_multiple_of(4);
+            bytes[filled..len].fill(0);
+        }
+
+        swapper_simd(&mut bytes[..len], src_type, dst_type);
+        writer.write_all(&bytes[..len])?;
+        written += len as u64;
+        progress(written);
+
+        // A short read means the end of the stream
+        if filled < bytes.len() {
+            break;
+        }
+    }
+    writer.flush()?;
+
+    Ok(written)
+}
+
+/// Like `convert_buffered`, writing the rom in several types at once.
+///
+/// The input is read a single time, each chunk is swapped into every output's
+/// type and written to its writer. Returns the bytes written to each.
+pub fn convert_multi<R: Read, W: Write>(
+    reader: &mut R,
+    src_type: RomType,
+    outputs: &mut [(RomType, W)],
+    pad: bool,
+    buffer_size: usize,
+) -> Result<u64, ConversionError> {
+    if buffer_size == 0 || !file_size_is_valid(buffer_size as u64) {
+        return Err(ConversionError::InvalidBufferSize(buffer_size));
+    }
+    let mut bytes = vec![0; buffer_size];
+    let mut swapped = vec![0; buffer_size];
+    let mut written = 0;
+
+    loop {
+        let filled = read_chunk(reader, &mut bytes)?;
+        if filled == 0 {
+            break;
+        }
+        let mut len = filled;
+        if !file_size_is_valid(filled as u64) {
+            if !pad {
+                check_file_size(written + filled as u64)?;
+            }
+            len = filled.next_multiple_of(4);
+            bytes[filled..len].fill(0);
+        }
+
+        for (dst_type, writer) in outputs.iter_mut() {
+            swapped[..len].copy_from_slice(&bytes[..len]);
+            swapper_simd(&mut swapped[..len], src_type, *dst_type);
+            writer.write_all(&swapped[..len])?;
+        }
+        written += len as u64;
+
+        // A short read means the end of the stream
+        if filled < bytes.len() {
+            break;
+        }
+    }
+    for (_, writer) in outputs.iter_mut() {
+        writer.flush()?;
+    }
+
+    Ok(written)
+}
+
+/// Where two roms differ once both are in big-endian order
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct RomDifference {
+    pub offset: u64,
+    /// The differing byte of each rom, `None` past its end
+    pub left: Option<u8>,
+    pub right: Option<u8>,
+}
+
+// Read the next chunk of a rom, reordered into big-endian
+fn read_normalized<R: Read>(
+    reader: &mut R,
+    rom_type: RomType,
+    bytes: &mut [u8],
+) -> io::Result<usize> {
+    let filled = read_chunk(reader, bytes)?;
+    // A trailing partial word is compared as it is
+    let aligned = filled / 4 * 4;
+    swapper_simd(&mut bytes[..aligned], rom_type, RomType::BigEndian);
+    Ok(filled)
+}
+
+/// Find every offset `pattern` occurs at in `data`, overlapping matches included
+pub fn search_pattern<'a>(data: &'a [u8], pattern: &'a [u8]) -> impl Iterator<Item = usize> + 'a {
+    data.windows(pattern.len().max(1))
+        .enumerate()
+        .filter(move |(_, window)| !pattern.is_empty() && *window == pattern)
+        .map(|(idx, _)| idx)
+}
+
+/// Search a rom of any byte order for `pattern`, returning the offsets of every
+/// match in big-endian order. The rom is read a chunk at a time.
+pub fn search_rom<R: Read>(reader: &mut R, pattern: &[u8]) -> Result<Vec<u64>, ConversionError> {
+    let rom_type = read_rom_type(reader)?;
+    // The magic was read already, in big-endian it's always this
+    let mut window = BIG_ENDIAN.to_vec();
+    let mut start = 0;
+    let mut bytes = vec![0; DEFAULT_BUFFER_SIZE];
+    let mut matches = Vec::new();
+
+    loop {
+        let filled = read_normalized(reader, rom_type, &mut bytes)?;
+        window.extend_from_slice(&bytes[..filled]);
+        matches.extend(search_pattern(&window, pattern).map(|idx| start + idx as u64));
+
+        // Keep what could be the start of a match running into the next chunk
+        let keep = pattern.len().saturating_sub(1).min(window.len());
+        let done = window.len() - keep;
+        window.drain(..done);
+        start += done as u64;
+
+        if filled < bytes.len() {
+            return Ok(matches);
+        }
+    }
+}
+
+/// Compare two roms of any byte order, returning the first difference in
+/// big-endian order or `None` when they hold the same data
+pub fn compare_roms<L: Read, R: Read>(
+    left: &mut L,
+    right: &mut R,
+) -> Result<Option<RomDifference>, ConversionError> {
+    let left_type = read_rom_type(left)?;
+    let right_type = read_rom_type(right)?;
+    // The magic is part of the data, in big-endian it's the same for both
+    let mut offset = 4;
+    let mut left_bytes = vec![0; DEFAULT_BUFFER_SIZE];
+    let mut right_bytes = vec![0; DEFAULT_BUFFER_SIZE];
+
+    loop {
+        let left_len = read_normalized(left, left_type, &mut left_bytes)?;
+        let right_len = read_normalized(right, right_type, &mut right_bytes)?;
+        let len = left_len.min(right_len);
+        if let Some(idx) = (0..len).find(|&idx| left_bytes[idx] != right_bytes[idx]) {
+            return Ok(Some(RomDifference {
+                offset: offset + idx as u64,
+                left: Some(left_bytes[idx]),
+                right: Some(right_bytes[idx]),
+            }));
+        }
+        if left_len != right_len {
+            return Ok(Some(RomDifference {
+                offset: offset + len as u64,
+                left: left_bytes[..left_len].get(len).copied(),
+                right: right_bytes[..right_len].get(len).copied(),
+            }));
+        }
+        if left_len < left_bytes.len() {
+            return Ok(None);
+        }
+        offset += len as u64;
+    }
+}
+
+/// Like `compare_roms`, for the roms at `left` and `right`
+pub fn compare_files(left: &Path, right: &Path) -> Result<Option<RomDifference>, ConversionError> {
+    let mut left = BufReader::new(File::open(left)?);
+    let mut right = BufReader::new(File::open(right)?);
+    compare_roms(&mut left, &mut right)
+}
+
+/// Convert a whole rom (header included) from `reader` into `writer`,
+/// returning the number of bytes written
+pub fn convert<R: Read, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    src_type: RomType,
+    dst_type: RomType,
+) -> Result<u64, ConversionError> {
+    convert_buffered(
+        reader,
+        writer,
+        src_type,
+        dst_type,
+        false,
+        DEFAULT_BUFFER_SIZE,
+    )
+}
+
+/// Like `convert`, but zero-pads a trailing partial chunk instead of failing
+pub fn convert_padded<R: Read, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    src_type: RomType,
+    dst_type: RomType,
+) -> Result<u64, ConversionError> {
+    convert_buffered(
+        reader,
+        writer,
+        src_type,
+        dst_type,
+        true,
+        DEFAULT_BUFFER_SIZE,
+    )
+}
+
+/// How far a conversion got, passed to `ConversionOptions::progress_callback`
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct BytesProcessed {
+    pub bytes_done: u64,
+    pub total_bytes: u64,
+}
+
+/// What `convert_file_with_options` does
+pub struct ConversionOptions {
+    pub dst_type: RomType,
+    /// Called as the conversion goes, e.g. to move a progress bar in a GUI
+    pub progress_callback: Option<Box<dyn Fn(BytesProcessed) + Send>>,
+    /// Bytes between two calls of `progress_callback`, it's also called once the
+    /// whole rom is written. Conversions go a chunk at a time, so it can't be called
+    /// more often than every `DEFAULT_BUFFER_SIZE` bytes.
+    pub progress_interval_bytes: u64,
+}
+
+impl Default for ConversionOptions {
+    fn default() -> ConversionOptions {
+        ConversionOptions {
+            dst_type: RomType::BigEndian,
+            progress_callback: None,
+            progress_interval_bytes: 1024 * 1024,
+        }
+    }
+}
+
+/// Convert the rom at `src` into `dst_type`, writing it to `dst`.
+///
+/// Returns `InputOutputSameFile` if `dst` is the file `src` points to.
+pub fn convert_file(src: &Path, dst: &Path, dst_type: RomType) -> Result<(), ConversionError> {
+    let options = ConversionOptions {
+        dst_type,
+        ..ConversionOptions::default()
+    };
+    convert_file_with_options(src, dst, options)
+}
+
+/// Like `convert_file`, with the output type and progress reporting in `options`
+pub fn convert_file_with_options(
+    src: &Path,
+    dst: &Path,
+    options: ConversionOptions,
+) -> Result<(), ConversionError> {
+    check_distinct_paths(src, dst)?;
+    let file = File::open(normalize_path_windows(src))?;
+    let size = file.metadata()?.len();
+    check_file_size(size)?;
+
+    let mut reader = BufReader::new(file);
+    let src_type = read_rom_type(&mut reader)?;
+
+    let mut writer = BufWriter::new(File::create(normalize_path_windows(dst))?);
+    let mut header = io::Cursor::new(*src_type.get_header_bytes()).chain(reader);
+    let mut reported = 0;
+    let mut progress = |bytes_done: u64| {
+        let Some(callback) = &options.progress_callback else {
+            return;
+        };
+        if bytes_done - reported >= options.progress_interval_bytes || bytes_done == size {
+            callback(BytesProcessed {
+                bytes_done,
+                total_bytes: size,
+            });
+            reported = bytes_done;
+        }
+    };
+    convert_chunks(
+        &mut header,
+        &mut writer,
+        src_type,
+        options.dst_type,
+        false,
+        DEFAULT_BUFFER_SIZE,
+        &mut progress,
+    )?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A scratch file in the temp directory, removed when dropped
+    pub(crate) struct TempFile(pub PathBuf);
+
+    impl TempFile {
+        pub fn new(name: &str, contents: &[u8]) -> TempFile {
+            let path =
+                std::env::temp_dir().join(format!("n64swap-{}-{}", std::process::id(), name));
+            fs::write(&path, contents).unwrap();
+            TempFile(path)
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    fn rom() -> Vec<u8> {
+        let mut rom = BYTE_SWAP.to_vec();
+        rom.extend([1, 2, 3, 4, 5, 6, 7, 8]);
+        rom
+    }
+
+    #[test]
+    fn convert_file_refuses_its_own_input() {
+        let src = TempFile::new("same.v64", &rom());
+        let error = convert_file(&src.0, &src.0, RomType::BigEndian).unwrap_err();
+        assert!(matches!(error, ConversionError::InputOutputSameFile(_)));
+        // The input wasn't truncated
+        assert_eq!(fs::read(&src.0).unwrap(), rom());
+    }
+
+    #[test]
+    fn convert_file_swaps_into_dst() {
+        let src = TempFile::new("convert.v64", &rom());
+        let dst = TempFile::new("convert.z64", &[]);
+        convert_file(&src.0, &dst.0, RomType::BigEndian).unwrap();
+        let mut expected = BIG_ENDIAN.to_vec();
+        expected.extend([2, 1, 4, 3, 6, 5, 8, 7]);
+        assert_eq!(fs::read(&dst.0).unwrap(), expected);
+    }
+
+    #[test]
+    fn word_swap_round_trips() {
+        for (rom_type, magic) in [
+            (RomType::BigEndian, BIG_ENDIAN),
+            (RomType::ByteSwap, BYTE_SWAP),
+            (RomType::LittleEndian, LITTLE_ENDIAN),
+        ] {
+            let mut word = WORD_SWAP;
+            swapper(&mut word, RomType::WordSwap, rom_type);
+            assert_eq!(word, magic, "{}", rom_type);
+            swapper(&mut word, rom_type, RomType::WordSwap);
+            assert_eq!(word, WORD_SWAP, "{}", rom_type);
+        }
+    }
+
+    #[test]
+    fn word_swap_magic_and_alias() {
+        assert_eq!(
+            identify_header(&[0x12, 0x40, 0x80, 0x37]),
+            Some(RomType::WordSwap)
+        );
+        assert_eq!(
+            RomType::from_str("halfword-swap", true),
+            Ok(RomType::WordSwap)
+        );
+        assert_eq!(RomType::from_str("word-swap", true), Ok(RomType::WordSwap));
+    }
+
+    const ROM_TYPES: [RomType; 4] = [
+        RomType::BigEndian,
+        RomType::ByteSwap,
+        RomType::LittleEndian,
+        RomType::WordSwap,
+    ];
+
+    #[test]
+    fn convert_bytes_round_trips() {
+        let original: Vec<u8> = (0..100).collect();
+        for src in ROM_TYPES {
+            for dst in ROM_TYPES {
+                let mut data = original.clone();
+                convert_bytes(src, dst, &mut data).unwrap();
+                assert_eq!(data == original, src == dst, "{} -> {}", src, dst);
+                for (word, swapped) in original.chunks(4).zip(data.chunks(4)) {
+                    let mut word = [word[0], word[1], word[2], word[3]];
+                    swapper(&mut word, src, dst);
+                    assert_eq!(word, swapped, "{} -> {}", src, dst);
+                }
+                convert_bytes(dst, src, &mut data).unwrap();
+                assert_eq!(data, original, "{} -> {} -> {}", src, dst, src);
+            }
+        }
+    }
+
+    #[test]
+    fn convert_bytes_rejects_a_partial_word() {
+        let mut data = [1, 2, 3, 4, 5, 6];
+        assert!(matches!(
+            convert_bytes(RomType::ByteSwap, RomType::BigEndian, &mut data),
+            Err(ConversionError::SizeMisaligned {
+                actual: 6,
+                expected: 8
+            })
+        ));
+        assert_eq!(data, [1, 2, 3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn guesses_an_unknown_magic_in_each_order() {
+        // The 64DD magic, in every byte order
+        for rom_type in ROM_TYPES {
+            let mut magic = [0x80, 0x27, 0x07, 0x40];
+            swapper(&mut magic, RomType::BigEndian, rom_type);
+            assert_eq!(
+                guess_type_from_content(&magic),
+                Some(rom_type),
+                "{}",
+                rom_type
+            );
+        }
+        // Two orders put a 0x80 byte first
+        assert_eq!(guess_type_from_content(&[0x80, 0x37, 0x12, 0x80]), None);
+        assert_eq!(guess_type_from_content(&[0, 0, 0, 0]), None);
+    }
+
+    // A big-endian rom spanning a few read chunks, in `rom_type`
+    fn large_rom(rom_type: RomType) -> Vec<u8> {
+        let mut rom = BIG_ENDIAN.to_vec();
+        rom.extend((4..DEFAULT_BUFFER_SIZE * 2 + 64).map(|idx| (idx % 251) as u8));
+        convert_bytes(RomType::BigEndian, rom_type, &mut rom).unwrap();
+        rom
+    }
+
+    #[test]
+    fn compare_matches_across_byte_orders() {
+        let big_endian = large_rom(RomType::BigEndian);
+        for rom_type in ROM_TYPES {
+            let other = large_rom(rom_type);
+            assert_eq!(
+                compare_roms(&mut big_endian.as_slice(), &mut other.as_slice()).unwrap(),
+                None,
+                "{}",
+                rom_type
+            );
+        }
+    }
+
+    #[test]
+    fn compare_finds_the_first_difference() {
+        // Past the first chunk, in big-endian order
+        let offset = DEFAULT_BUFFER_SIZE + 5;
+        let mut left = large_rom(RomType::BigEndian);
+        left[offset] = 0xAA;
+        convert_bytes(RomType::BigEndian, RomType::ByteSwap, &mut left).unwrap();
+        let right = large_rom(RomType::LittleEndian);
+        assert_eq!(
+            compare_roms(&mut left.as_slice(), &mut right.as_slice()).unwrap(),
+            Some(RomDifference {
+                offset: offset as u64,
+                left: Some(0xAA),
+                right: Some((offset % 251) as u8),
+            })
+        );
+
+        // One rom running out first
+        let left = large_rom(RomType::BigEndian);
+        let right = &left[..left.len() - 4];
+        assert_eq!(
+            compare_roms(&mut left.as_slice(), &mut &right[..]).unwrap(),
+            Some(RomDifference {
+                offset: right.len() as u64,
+                left: Some(left[right.len()]),
+                right: None,
+            })
+        );
+    }
+
+    #[test]
+    fn search_across_chunks_and_orders() {
+        // Chunks are read after the magic, so they end at these big-endian offsets
+        let first = 4 + DEFAULT_BUFFER_SIZE;
+        let second = first + DEFAULT_BUFFER_SIZE;
+        let pattern = [0xDE, 0xAD, 0xBE, 0xEF, 0x01];
+        let offsets = [0x10, first - 2, second - 4];
+        let mut rom = large_rom(RomType::BigEndian);
+        for offset in offsets {
+            rom[offset..offset + pattern.len()].copy_from_slice(&pattern);
+        }
+        let expected = offsets.map(|offset| offset as u64);
+
+        for rom_type in ROM_TYPES {
+            let mut converted = rom.clone();
+            convert_bytes(RomType::BigEndian, rom_type, &mut converted).unwrap();
+            assert_eq!(
+                search_rom(&mut converted.as_slice(), &pattern).unwrap(),
+                expected,
+                "{}",
+                rom_type
+            );
+        }
+    }
+
+    #[test]
+    fn trims_whole_words_of_padding() {
+        let mut rom = BIG_ENDIAN.to_vec();
+        rom.extend([1, 2, 3, 0]);
+        rom.extend([0; DEFAULT_BUFFER_SIZE + 8]);
+        assert_eq!(trimmed_len(&mut io::Cursor::new(&rom), 0x00).unwrap(), 8);
+        assert_eq!(
+            trimmed_len(&mut io::Cursor::new(&rom), 0xFF).unwrap(),
+            rom.len() as u64
+        );
+        assert_eq!(trimmed_len(&mut io::Cursor::new([0; 12]), 0x00).unwrap(), 0);
+    }
+
+    #[test]
+    fn trims_a_partial_word() {
+        let mut rom = BIG_ENDIAN.to_vec();
+        rom.extend([0xFF; 6]);
+        assert_eq!(trimmed_len(&mut io::Cursor::new(&rom), 0xFF).unwrap(), 4);
+
+        // Data in the partial word keeps all of it
+        rom.push(1);
+        assert_eq!(trimmed_len(&mut io::Cursor::new(&rom), 0xFF).unwrap(), 11);
+    }
+
+    #[test]
+    fn content_size_of_a_padded_rom() {
+        let mut rom = BIG_ENDIAN.to_vec();
+        rom.extend([1, 2, 3, 4, 0xFF, 0xFF, 0xFF, 0xFF]);
+        let padded = TempFile::new("content-size.z64", &rom);
+        assert_eq!(detect_content_size(&padded.0).unwrap(), 8);
+
+        rom.truncate(8);
+        let unpadded = TempFile::new("content-size-unpadded.z64", &rom);
+        assert_eq!(detect_content_size(&unpadded.0).unwrap(), 8);
+    }
+
+    #[test]
+    fn unique_output_paths_are_numbered() {
+        let dir = Path::new("out");
+        let taken: HashSet<PathBuf> = ["rom.z64", "rom_2.z64", "rom_3.z64", "rom"]
+            .map(|name| dir.join(name))
+            .into();
+        let unique =
+            |stem, ext| unique_output_path_with(dir, stem, ext, |path| taken.contains(path));
+        assert_eq!(unique("rom", "z64"), dir.join("rom_4.z64"));
+        assert_eq!(unique("rom", "v64"), dir.join("rom.v64"));
+        assert_eq!(unique("rom", ""), dir.join("rom_2"));
+        assert_eq!(unique("other", ""), dir.join("other"));
+    }
+}
@@ -0,0 +1,47 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use n64swap::ConversionError;
+
+use crate::time::UtcTime;
+
+/// The --error-log file, appended to with one line per failed rom
+pub struct Logger {
+    file: File,
+    path: PathBuf,
+    logged: usize,
+}
+
+impl Logger {
+    pub fn open(path: &Path) -> io::Result<Logger> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Logger {
+            file,
+            path: path.to_path_buf(),
+            logged: 0,
+        })
+    }
+
+    /// Append `<timestamp> <filename>: <error>`
+    pub fn log_error(&mut self, filename: &Path, error: &ConversionError) -> io::Result<()> {
+        writeln!(
+            self.file,
+            "{} {}: {}",
+            UtcTime::from_system(SystemTime::now()).iso8601(),
+            filename.display(),
+            error
+        )?;
+        self.logged += 1;
+        Ok(())
+    }
+
+    pub fn logged(&self) -> usize {
+        self.logged
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
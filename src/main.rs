@@ -1,204 +1,1100 @@
-use core::fmt;
-use std::fs::File;
-use std::io::{BufReader, BufWriter, Read, Write};
+// The output macros come before the modules, so those can use them too
+
+// Status line on stderr, unless --quiet or --machine was given
+macro_rules! info {
+    ($args:expr, $($arg:tt)*) => {
+        if !$args.quiet && !$args.machine {
+            eprintln!($($arg)*);
+        }
+    };
+}
+
+// Like info!, for a step that went through
+macro_rules! success {
+    ($args:expr, $($arg:tt)*) => {
+        if !$args.quiet && !$args.machine {
+            eprintln!("{}", $crate::color::stderr($crate::color::Color::Green, &format!($($arg)*)));
+        }
+    };
+}
+
+macro_rules! warning {
+    ($($arg:tt)*) => {
+        eprintln!("{}", $crate::color::stderr($crate::color::Color::Yellow, &format!("Warning: {}", format!($($arg)*))))
+    };
+}
+
+macro_rules! error {
+    ($($arg:tt)*) => {
+        eprintln!("{}", $crate::color::stderr($crate::color::Color::Red, &format!($($arg)*)))
+    };
+}
+
+mod batch;
+mod color;
+mod commands;
+mod completions;
+mod config;
+mod csv;
+mod dat;
+mod deflate;
+mod dump;
+mod glob;
+mod hash;
+mod input;
+mod interrupt;
+mod inventory;
+mod json;
+mod logger;
+#[cfg(feature = "man")]
+mod man;
+mod metadata;
+mod output;
+mod progress;
+mod report;
+mod sfv;
+mod single;
+mod stats;
+mod template;
+mod time;
+mod trace;
+mod verify;
+mod watch;
+mod write;
+mod zip;
+
+use std::io::{self, IsTerminal};
+use std::path::{Path, PathBuf};
 use std::process::exit;
+use std::thread;
 
-use clap::{Parser, ValueEnum};
+use clap::error::ErrorKind;
+use clap::parser::ValueSource;
+use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser, ValueEnum};
 
-// N64 header magic bytes
-const BIG_ENDIAN: [u8; 4] = [0x80, 0x37, 0x12, 0x40];
-const BYTE_SWAP: [u8; 4] = [0x37, 0x80, 0x40, 0x12];
-const LITTLE_ENDIAN: [u8; 4] = [0x40, 0x12, 0x37, 0x80];
+use n64swap::{
+    file_size_is_valid, ConversionError, CountryCode, RomType, Symlinks, DEFAULT_BUFFER_SIZE,
+};
+
+use batch::{convert_all_types, convert_batch, convert_directory};
+use commands::{dump_file_header, inject_file_header, merge_files, search_file, split_file};
+use completions::Shell;
+use config::Config;
+use hash::HashAlgorithm;
+use report::{error_event, find_duplicates, print_inventory};
+use template::TargetOs;
+use watch::watch_directory;
+use write::ensure_output_dir;
 
 #[derive(Debug, PartialEq, Copy, Clone, ValueEnum)]
-enum RomType {
-    /// (commonly .z64)
-    BigEndian,
-    /// (commonly .v64)
-    ByteSwap,
-    /// (commonly .n64)
-    LittleEndian,
+enum OutputFormat {
+    /// Human readable lines
+    Text,
+    /// One compact JSON object per rom
+    Json,
+    /// A header row and a row per rom, only for --inventory
+    Csv,
 }
 
-impl fmt::Display for RomType {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self {
-            RomType::BigEndian => write!(f, "BigEndian (.z64)"),
-            RomType::ByteSwap => write!(f, "ByteSwap (.v64)"),
-            RomType::LittleEndian => write!(f, "LittleEndian (.n64)"),
-        }
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// Input Filename(s) ("-" for stdin)
+    #[cfg_attr(
+        not(feature = "man"),
+        arg(required_unless_present_any = ["directory", "watch", "generate_completions"])
+    )]
+    #[cfg_attr(
+        feature = "man",
+        arg(required_unless_present_any = ["directory", "watch", "generate_completions", "generate_man"])
+    )]
+    filenames: Vec<PathBuf>,
+
+    /// Output filename ("-" for stdout), only for a single input file. A second
+    /// filename that doesn't exist yet is taken as the output too, without batch flags
+    #[arg(short = 'o', long = "output", conflicts_with = "in_place")]
+    destination_filename: Option<PathBuf>,
+
+    /// Output type, N64SWAP_ROMTYPE when not given
+    #[arg(short, long, visible_alias = "to")]
+    romtype: Option<RomType>,
+
+    /// Input type, used instead of the one the header magic says
+    #[arg(long, value_name = "TYPE", conflicts_with = "repair_header")]
+    from: Option<RomType>,
+
+    /// Write the rom as big-endian, byte-swap and little-endian at once, reading it once
+    #[arg(long, default_value_t = false, conflicts_with_all = [
+        "destination_filename", "romtype", "in_place", "directory", "compare", "identify",
+        "rename_to_title", "output_template", "patch_ips", "patch_bps", "trim", "pad_pow2",
+        "crc_fix", "verify", "gen_sfv", "gen_md5", "region_override",
+    ])]
+    all_types: bool,
+
+    /// Identify rom type (and exit)
+    #[arg(short, long, default_value_t = false)]
+    identify: bool,
+
+    /// Only accept inputs with a .z64, .v64 or .n64 extension, not .rom, .bin or others
+    #[arg(long, default_value_t = false)]
+    strict_ext: bool,
+
+    /// Fix a damaged header magic, guessing the byte order from the extension and
+    /// boot address
+    #[arg(long, default_value_t = false)]
+    repair_header: bool,
+
+    /// Put the magic of this type in front of a rom that's missing its first 4 bytes,
+    /// the rest isn't byte-swapped (and exit)
+    #[arg(long, value_name = "TYPE", conflicts_with_all = [
+        "romtype", "from", "in_place", "all_types", "repair_header", "identify", "compare",
+        "directory", "watch", "search", "dump_header", "zip_input", "zip_output",
+    ])]
+    inject_header: Option<RomType>,
+
+    /// Split a rom into files of this many bytes, e.g. 8m, in --output-dir or next
+    /// to it, the last one padded with 0x00 (and exit)
+    #[arg(long, value_name = "CHUNK_SIZE", value_parser = parse_size, conflicts_with_all = [
+        "destination_filename", "romtype", "in_place", "all_types", "identify", "compare",
+        "directory", "watch", "search", "dump_header", "inject_header", "zip_input", "zip_output",
+    ])]
+    split: Option<usize>,
+
+    /// Join the chunks of a split rom, given in order, into the --output file (and exit),
+    /// the padding --split added to the last chunk is kept
+    #[arg(long, default_value_t = false, requires = "destination_filename", conflicts_with_all = [
+        "romtype", "in_place", "all_types", "identify", "compare", "directory", "watch",
+        "search", "dump_header", "inject_header", "split", "zip_input", "zip_output",
+    ])]
+    merge: bool,
+
+    /// Print the big-endian offsets of a hex byte pattern, e.g. "8010A000 0C001234" (and exit)
+    #[arg(long, value_name = "HEX_PATTERN", value_parser = parse_hex, conflicts_with_all = ["destination_filename", "in_place", "identify", "compare", "directory", "dump_header"])]
+    search: Option<HexPattern>,
+
+    /// Print the header as annotated hex (and exit)
+    #[arg(long, default_value_t = false, conflicts_with_all = ["destination_filename", "in_place", "identify", "compare", "directory"])]
+    dump_header: bool,
+
+    /// Output format of --identify and --inventory
+    #[arg(long, visible_alias = "format", value_enum, default_value_t = OutputFormat::Text)]
+    output_format: OutputFormat,
+
+    /// Force overwrite output file, also set by N64SWAP_FORCE=true
+    #[arg(short, long, default_value_t = false)]
+    force: bool,
+
+    /// Convert the input file in place, replacing it
+    #[arg(long, default_value_t = false)]
+    in_place: bool,
+
+    /// Keep a file that's replaced (by --in-place or --force) as <filename>.bak
+    #[arg(long, default_value_t = false)]
+    backup: bool,
+
+    /// Suffix of --backup files, implies --backup
+    #[arg(long, value_name = "SUFFIX")]
+    backup_suffix: Option<String>,
+
+    /// Recalculate the header CRCs after converting
+    #[arg(long, default_value_t = false)]
+    crc_fix: bool,
+
+    /// Set the country code byte (0x3E) of the output header, as hex (e.g. 0x45) or a
+    /// region ("USA", "Japan", "Europe"...). Only the header changes, the game itself
+    /// isn't localized
+    #[arg(long, value_name = "CODE", value_parser = parse_country)]
+    region_override: Option<CountryCode>,
+
+    /// Check the header CRCs before converting
+    #[arg(long, default_value_t = false)]
+    verify_crc: bool,
+
+    /// Only print errors (and the --identify output), also set by N64SWAP_QUIET=true
+    #[arg(short, long, default_value_t = false)]
+    quiet: bool,
+
+    /// Don't color the output (NO_COLOR is respected too)
+    #[arg(long, default_value_t = false)]
+    no_color: bool,
+
+    /// Print conversion details to stderr
+    #[arg(short, long, default_value_t = false, conflicts_with = "quiet")]
+    verbose: bool,
+
+    /// Print the offset of every 4-byte chunk and its bytes before and after
+    /// swapping to stderr, converting a chunk at a time (implies --buffer-size 4)
+    #[arg(long, default_value_t = false, conflicts_with_all = ["quiet", "buffer_size"])]
+    debug: bool,
+
+    /// Stop the --debug output after this many bytes, the conversion goes on
+    #[arg(long, value_name = "BYTES", value_parser = parse_size, requires = "debug")]
+    debug_limit: Option<usize>,
+
+    /// Print JSON Lines events on stdout instead of status messages, for scripts
+    #[arg(long, default_value_t = false, conflicts_with_all = [
+        "quiet", "verbose", "identify", "all_types", "dry_run", "inventory", "find_duplicates",
+        "search", "dump_header",
+    ])]
+    machine: bool,
+
+    /// Roms converted at the same time in a batch, defaults to the number of CPUs
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u16).range(1..))]
+    threads: Option<u16>,
+
+    /// Bytes converted at a time, e.g. 65536 or 64k, N64SWAP_BUFFER_SIZE when not given
+    #[arg(long, value_parser = parse_size, default_value_t = DEFAULT_BUFFER_SIZE)]
+    buffer_size: usize,
+
+    /// Print digests of the output (of the input with --identify), e.g. md5,sha1
+    #[arg(long, value_enum, value_delimiter = ',')]
+    hash: Vec<HashAlgorithm>,
+
+    /// Print the SHA-1 of the output, same as --hash sha1
+    #[arg(long, default_value_t = false)]
+    sha1: bool,
+
+    /// Print the CRC32 of the output (of the whole file with --identify), same as
+    /// --hash crc32
+    #[arg(long, default_value_t = false)]
+    crc32: bool,
+
+    /// Check roms against this .sfv or .md5 file first, skipping the ones that fail
+    #[arg(long, value_name = "CHECKSUM_FILE")]
+    validate: Option<PathBuf>,
+
+    /// Look each rom up by CRC32 in this No-Intro (Logiqx XML) DAT file and report
+    /// it as known-good, known-bad or not in the database
+    #[arg(long, value_name = "FILE")]
+    dat: Option<PathBuf>,
+
+    /// Print a table of the roms given or in --directory, with their type, title,
+    /// region, CRCs and size (and exit)
+    #[arg(long, default_value_t = false, conflicts_with_all = [
+        "destination_filename", "in_place", "identify", "all_types", "compare", "watch",
+        "dump_header", "search", "inject_header", "zip_input", "zip_output", "find_duplicates",
+    ])]
+    inventory: bool,
+
+    /// Column to sort the --inventory table by
+    #[arg(long, value_enum, value_name = "COLUMN", requires = "inventory")]
+    sort_by: Option<inventory::Column>,
+
+    /// List the roms of a batch holding the same data, whatever their byte order
+    /// (and exit)
+    #[arg(long, default_value_t = false, conflicts_with_all = [
+        "destination_filename", "in_place", "identify", "all_types", "compare", "watch",
+        "dump_header", "search", "inject_header", "zip_input", "zip_output",
+    ])]
+    find_duplicates: bool,
+
+    /// Name the output of each rom in a batch after its --dat entry
+    #[arg(long, default_value_t = false, requires = "dat", conflicts_with_all = [
+        "destination_filename", "in_place", "output_template", "rename_to_title",
+    ])]
+    rename_from_dat: bool,
+
+    /// Read the rom from a zip archive, its first entry with a rom extension
+    #[arg(long, default_value_t = false, conflicts_with_all = [
+        "in_place", "dump_header", "search", "all_types", "verify_crc", "export_csv",
+        "export_json", "directory", "watch", "dat",
+    ])]
+    zip_input: bool,
+
+    /// Write the output into a new zip archive, the entry is named like the output
+    /// file would be
+    #[arg(long, value_name = "PATH", conflicts_with_all = [
+        "destination_filename", "in_place", "all_types", "gen_sfv", "gen_md5", "directory",
+        "watch",
+    ])]
+    zip_output: Option<PathBuf>,
+
+    /// Append a row of metadata about each rom to this CSV file
+    #[arg(long, value_name = "PATH")]
+    export_csv: Option<PathBuf>,
+
+    /// Write metadata and the raw header of each rom to this file as a JSON array
+    #[arg(long, value_name = "PATH")]
+    export_json: Option<PathBuf>,
+
+    /// Add to the --export-json array instead of replacing the file
+    #[arg(long, default_value_t = false, requires = "export_json")]
+    append: bool,
+
+    /// Give the output the permission bits of the input (unix only)
+    #[arg(long, default_value_t = false)]
+    preserve_permissions: bool,
+
+    /// Give the output the modification and access times of the input
+    #[arg(long, default_value_t = false)]
+    preserve_timestamps: bool,
+
+    /// Also append the errors of a batch to this file, one timestamped line per rom
+    #[arg(long, value_name = "PATH")]
+    error_log: Option<PathBuf>,
+
+    /// Write a <name>.sfv CRC32 file next to each converted rom
+    #[arg(long, default_value_t = false)]
+    gen_sfv: bool,
+
+    /// Collect the --gen-sfv entries into this single file instead
+    #[arg(long, requires = "gen_sfv")]
+    sfv_output: Option<PathBuf>,
+
+    /// Write a <name>.md5 file (as md5sum does) next to each converted rom
+    #[arg(long, default_value_t = false)]
+    gen_md5: bool,
+
+    /// Collect the --gen-md5 entries into this single file instead, e.g. roms.md5
+    #[arg(long, value_name = "PATH", requires = "gen_md5")]
+    md5_output: Option<PathBuf>,
+
+    /// Read the output back after converting and check it against the input
+    #[arg(long, default_value_t = false)]
+    verify: bool,
+
+    /// Only print what would be converted, without writing anything
+    #[arg(long, default_value_t = false)]
+    dry_run: bool,
+
+    /// Apply an IPS patch to the output after converting
+    #[arg(long, value_name = "FILE")]
+    patch_ips: Option<PathBuf>,
+
+    /// Apply a BPS patch to the output after converting, --force skips the check
+    /// that it's made for this rom
+    #[arg(long, value_name = "FILE", conflicts_with = "patch_ips")]
+    patch_bps: Option<PathBuf>,
+
+    /// Cut trailing 0x00 or 0xFF padding off the output (before --pad-pow2)
+    #[arg(long, default_value_t = false)]
+    trim: bool,
+
+    /// Zero-pad roms whose size isn't a multiple of 4 bytes
+    #[arg(long, default_value_t = false)]
+    pad: bool,
+
+    /// Zero-pad the output to the next power of 2 size (e.g. 8 MiB)
+    #[arg(long, default_value_t = false)]
+    pad_pow2: bool,
+
+    /// Check whether the input holds the same data as this rom, in any byte order
+    #[arg(long, value_name = "FILE2", conflicts_with_all = ["destination_filename", "in_place", "identify"])]
+    compare: Option<PathBuf>,
+
+    /// Convert every rom in a directory
+    #[arg(short, long, conflicts_with_all = ["filenames", "in_place", "compare"])]
+    directory: Option<PathBuf>,
+
+    /// Keep converting the roms that show up in a directory, until Ctrl-C
+    #[arg(long, value_name = "DIRECTORY", conflicts_with_all = ["filenames", "directory", "in_place", "compare", "all_types"])]
+    watch: Option<PathBuf>,
+
+    /// Also convert roms in subdirectories of --directory
+    #[arg(short = 'R', long, default_value_t = false, requires = "directory")]
+    recursive: bool,
+
+    /// How many levels of subdirectories to search, implies --recursive
+    #[arg(long, requires = "directory")]
+    depth: Option<usize>,
+
+    /// Don't skip files and directories starting with a dot
+    #[arg(long, default_value_t = false, requires = "directory")]
+    include_hidden: bool,
+
+    /// Also follow symlinked directories with --recursive, each directory only once
+    #[arg(long, default_value_t = false, conflicts_with = "no_follow_symlinks")]
+    follow_symlinks: bool,
+
+    /// Skip symlinked files and directories in a batch, with a warning for each
+    #[arg(long, default_value_t = false)]
+    no_follow_symlinks: bool,
+
+    /// Skip files of a batch smaller than this, e.g. 1m
+    #[arg(long, value_name = "BYTES", value_parser = parse_size)]
+    min_size: Option<usize>,
+
+    /// Skip files of a batch larger than this, e.g. 64m
+    #[arg(long, value_name = "BYTES", value_parser = parse_size)]
+    max_size: Option<usize>,
+
+    /// Directory to write converted files to, N64SWAP_OUTPUT_DIR when not given
+    #[arg(long, conflicts_with_all = ["destination_filename", "in_place"])]
+    output_dir: Option<PathBuf>,
+
+    /// Output filename of each rom in a batch, e.g. "{title} ({region}).{ext}",
+    /// placeholders are {stem}, {ext}, {type}, {region}, {title}, {crc} (same as
+    /// {crc1}), {crc2} and {rev}
+    #[arg(long, visible_alias = "name-template", conflicts_with = "in_place")]
+    output_template: Option<String>,
+
+    /// Name the output after the rom title and region, e.g. "SUPER MARIO 64 (USA).z64"
+    #[arg(long, default_value_t = false, conflicts_with_all = ["destination_filename", "output_template"])]
+    rename_to_title: bool,
+
+    /// Fail when two files of a batch would have the same output, instead of
+    /// numbering the later ones like "name_2.z64"
+    #[arg(long, default_value_t = false)]
+    no_suffix: bool,
+
+    /// Keep the titles --rename-to-title and --output-template put in filenames valid
+    /// on this OS, the default is both
+    #[arg(long, value_enum, default_value_t = TargetOs::Any)]
+    target_os: TargetOs,
+
+    /// Print a completion script for this shell and exit
+    #[arg(long, value_name = "SHELL", exclusive = true)]
+    generate_completions: Option<Shell>,
+
+    /// Print a man page and exit
+    #[cfg(feature = "man")]
+    #[arg(long, default_value_t = false, exclusive = true)]
+    generate_man: bool,
+}
+
+// Filename standing in for stdin / stdout
+const STDIO: &str = "-";
+
+// Parse a byte count with an optional k or m suffix
+fn parse_size(value: &str) -> Result<usize, String> {
+    let lower = value.to_ascii_lowercase();
+    let (number, multiplier) = if let Some(number) = lower.strip_suffix('k') {
+        (number, 1024)
+    } else if let Some(number) = lower.strip_suffix('m') {
+        (number, 1024 * 1024)
+    } else {
+        (lower.as_str(), 1)
+    };
+    number
+        .parse::<usize>()
+        .ok()
+        .and_then(|number| number.checked_mul(multiplier))
+        .ok_or_else(|| format!("invalid size '{}'", value))
+}
+
+// Parse a --region-override country code, a hex byte or a region name
+fn parse_country(value: &str) -> Result<CountryCode, String> {
+    if let Some(hex) = value.strip_prefix("0x").or(value.strip_prefix("0X")) {
+        return u8::from_str_radix(hex, 16)
+            .map(CountryCode::from_byte)
+            .map_err(|_| format!("invalid hex byte '{}'", value));
     }
+    CountryCode::from_name(value).ok_or_else(|| format!("unknown region '{}'", value))
 }
 
-impl RomType {
-    fn get_file_ext(&self) -> &str {
-        match *self {
-            RomType::BigEndian => ".z64",
-            RomType::ByteSwap => ".v64",
-            RomType::LittleEndian => ".n64",
+// The bytes --search looks for
+#[derive(Debug, Clone)]
+struct HexPattern(Vec<u8>);
+
+// Parse a --search pattern like "8010A000 0C001234", spaces being ignored
+fn parse_hex(value: &str) -> Result<HexPattern, String> {
+    let digits: Vec<char> = value.chars().filter(|c| !c.is_whitespace()).collect();
+    if digits.is_empty() || !digits.len().is_multiple_of(2) {
+        return Err(format!("'{}' isn't a whole number of hex bytes", value));
+    }
+    digits
+        .chunks(2)
+        .map(|pair| {
+            let pair: String = pair.iter().collect();
+            u8::from_str_radix(&pair, 16).map_err(|_| format!("invalid hex byte '{}'", pair))
+        })
+        .collect::<Result<_, _>>()
+        .map(HexPattern)
+}
+
+impl Args {
+    fn symlinks(&self) -> Symlinks {
+        if self.no_follow_symlinks {
+            Symlinks::Skip
+        } else if self.follow_symlinks {
+            Symlinks::All
+        } else {
+            Symlinks::Files
         }
     }
 
-    fn get_header_bytes(&self) -> &[u8; 4] {
-        match *self {
-            RomType::BigEndian => &BIG_ENDIAN,
-            RomType::ByteSwap => &BYTE_SWAP,
-            RomType::LittleEndian => &LITTLE_ENDIAN,
+    fn threads(&self) -> usize {
+        match self.threads {
+            Some(threads) => threads.into(),
+            None => thread::available_parallelism().map_or(1, usize::from),
         }
     }
-}
 
-fn guess_type(ext: &str) -> Option<RomType> {
-    match ext.to_lowercase().as_str() {
-        ".z64" => Some(RomType::BigEndian),
-        ".v64" => Some(RomType::ByteSwap),
-        ".n64" => Some(RomType::LittleEndian),
-        _ => None,
+    // The input file of a single file conversion
+    fn filename(&self) -> &Path {
+        self.filenames
+            .first()
+            .map_or(Path::new(""), PathBuf::as_path)
     }
-}
 
-fn identify_header(bytes: &[u8; 4]) -> Option<RomType> {
-    match *bytes {
-        BIG_ENDIAN => Some(RomType::BigEndian),
-        BYTE_SWAP => Some(RomType::ByteSwap),
-        LITTLE_ENDIAN => Some(RomType::LittleEndian),
-        _ => None,
+    // --hash plus the shorthand flags
+    fn hash_algorithms(&self) -> Vec<HashAlgorithm> {
+        let mut algorithms = self.hash.clone();
+        if self.sha1 {
+            algorithms.push(HashAlgorithm::Sha1);
+        }
+        if self.crc32 {
+            algorithms.push(HashAlgorithm::Crc32);
+        }
+        algorithms
     }
-}
 
-fn detect_ext(filename: &str) -> Option<&str> {
-    if let Some(idx) = filename.rfind('.') {
-        filename.get(idx..)
-    } else {
-        None
+    // The printed hashes plus the CRC32 --gen-sfv, the MD5 --gen-md5 and the
+    // SHA-256 --machine need
+    fn computed_hashes(&self) -> Vec<HashAlgorithm> {
+        let mut algorithms = self.hash_algorithms();
+        if self.gen_sfv {
+            algorithms.push(HashAlgorithm::Crc32);
+        }
+        if self.gen_md5 {
+            algorithms.push(HashAlgorithm::Md5);
+        }
+        if self.machine && !algorithms.contains(&HashAlgorithm::Sha256) {
+            algorithms.push(HashAlgorithm::Sha256);
+        }
+        algorithms
+    }
+
+    // The suffix to back up replaced files with, if they should be
+    fn backup_suffix(&self) -> Option<&str> {
+        match &self.backup_suffix {
+            Some(suffix) => Some(suffix),
+            None => self.backup.then_some(".bak"),
+        }
+    }
+
+    // Whether a converted rom is written at all
+    fn writes_rom(&self) -> bool {
+        !self.identify && !self.dump_header && self.compare.is_none() && self.search.is_none()
+    }
+
+    fn reads_stdin(&self) -> bool {
+        self.filenames.iter().any(|name| name == Path::new(STDIO))
+    }
+
+    // Piping from stdin defaults to piping to stdout
+    fn writes_stdout(&self) -> bool {
+        if self.zip_output.is_some() {
+            return false;
+        }
+        match self.destination_filename.as_deref() {
+            Some(name) => name == Path::new(STDIO),
+            None => self.reads_stdin(),
+        }
     }
-}
 
-fn swapper(bytes: &mut [u8; 4], src_type: RomType, dst_type: RomType) {
-    match (src_type, dst_type) {
-        (RomType::BigEndian, RomType::ByteSwap) | (RomType::ByteSwap, RomType::BigEndian) => {
-            bytes.swap(0, 1);
-            bytes.swap(2, 3);
+    // Fill in what the command line left unset from the environment and config file
+    fn apply_config(&mut self, config: Config, matches: &ArgMatches) {
+        if self.romtype.is_none() {
+            self.romtype = config.default_romtype;
+        }
+        // An explicit output or not writing one leaves no place for a default directory
+        let named_output = self.destination_filename.is_some()
+            || self.in_place
+            || self.identify
+            || !self.writes_rom()
+            || self.reads_stdin();
+        if self.output_dir.is_none() && !named_output {
+            self.output_dir = config.output_dir.map(PathBuf::from);
+        }
+        if !self.verbose {
+            self.quiet |= config.quiet.unwrap_or(false);
         }
-        (RomType::BigEndian, RomType::LittleEndian) | (RomType::LittleEndian, RomType::BigEndian) => {
-            bytes.swap(0, 3);
-            bytes.swap(1, 2);
+        self.force |= config.force.unwrap_or(false);
+        if self.hash.is_empty() && !self.sha1 && !self.crc32 {
+            self.hash = config.hash_algorithm;
         }
-        (RomType::ByteSwap, RomType::LittleEndian) | (RomType::LittleEndian, RomType::ByteSwap) => {
-            bytes.swap(0, 2);
-            bytes.swap(1, 3);
+        if let Some(size) = config.buffer_size {
+            if matches.value_source("buffer_size") == Some(ValueSource::DefaultValue) {
+                self.buffer_size = size;
+            }
         }
-        _ => {}
     }
 }
 
-#[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
-struct Args {
-    /// Input Filename
-    filename: String,
+// Reject combinations clap can't express on its own
+fn validate(args: &Args) -> Result<(), clap::Error> {
+    let mut cmd = Args::command();
+    if args.buffer_size == 0 || !file_size_is_valid(args.buffer_size as u64) {
+        return Err(cmd.error(
+            ErrorKind::InvalidValue,
+            "--buffer-size must be a non-zero multiple of 4",
+        ));
+    }
+    if let Some(template) = &args.output_template {
+        if let Err(error) = template::validate(template) {
+            return Err(cmd.error(
+                ErrorKind::InvalidValue,
+                format!("Invalid --output-template: {}", error),
+            ));
+        }
+        if args.directory.is_none() && args.filenames.len() < 2 {
+            return Err(cmd.error(
+                ErrorKind::ArgumentConflict,
+                "--output-template requires several input files or --directory",
+            ));
+        }
+    }
+    if args.machine && args.writes_stdout() {
+        return Err(cmd.error(
+            ErrorKind::ArgumentConflict,
+            "--machine prints its events on stdout, so the output can't go there",
+        ));
+    }
+    if let (Some(min), Some(max)) = (args.min_size, args.max_size) {
+        if min > max {
+            return Err(cmd.error(
+                ErrorKind::ArgumentConflict,
+                "--min-size can't be larger than --max-size",
+            ));
+        }
+    }
+    if args.rename_from_dat && args.directory.is_none() && args.filenames.len() < 2 {
+        return Err(cmd.error(
+            ErrorKind::ArgumentConflict,
+            "--rename-from-dat requires several input files or --directory",
+        ));
+    }
+    if args.output_format == OutputFormat::Csv && !args.inventory {
+        return Err(cmd.error(
+            ErrorKind::ArgumentConflict,
+            "--format csv can only be used with --inventory",
+        ));
+    }
+    if args.directory.is_some() || args.watch.is_some() {
+        return Ok(());
+    }
 
-    /// Output filename
-    destination_filename: Option<String>,
+    if args.filenames.len() > 1 {
+        if args.destination_filename.is_some() && !args.merge {
+            return Err(cmd.error(
+                ErrorKind::ArgumentConflict,
+                "--output can only be used with a single input file",
+            ));
+        }
+        if args.in_place {
+            return Err(cmd.error(
+                ErrorKind::ArgumentConflict,
+                "--in-place can only be used with a single input file",
+            ));
+        }
+        if args.zip_input || args.zip_output.is_some() {
+            return Err(cmd.error(
+                ErrorKind::ArgumentConflict,
+                "--zip-input and --zip-output can only be used with a single input file",
+            ));
+        }
+        if args.reads_stdin() {
+            return Err(cmd.error(
+                ErrorKind::ArgumentConflict,
+                "stdin can only be used as a single input file",
+            ));
+        }
+        if args.compare.is_some() {
+            return Err(cmd.error(
+                ErrorKind::ArgumentConflict,
+                "--compare can only be used with a single input file",
+            ));
+        }
+        return Ok(());
+    }
 
-    /// Output type
-    #[arg(short, long)]
-    romtype: Option<RomType>,
+    if args.reads_stdin() {
+        if args.romtype.is_none() && args.writes_rom() {
+            return Err(cmd.error(
+                ErrorKind::MissingRequiredArgument,
+                "Reading from stdin requires --romtype",
+            ));
+        }
+        if args.in_place {
+            return Err(cmd.error(
+                ErrorKind::ArgumentConflict,
+                "Unable to convert stdin in place",
+            ));
+        }
+        if args.verify_crc {
+            return Err(cmd.error(
+                ErrorKind::ArgumentConflict,
+                "--verify-crc requires an input file",
+            ));
+        }
+        if args.output_dir.is_some() {
+            return Err(cmd.error(
+                ErrorKind::ArgumentConflict,
+                "--output-dir requires an input file",
+            ));
+        }
+        if args.rename_to_title {
+            return Err(cmd.error(
+                ErrorKind::ArgumentConflict,
+                "--rename-to-title requires an input file",
+            ));
+        }
+        if args.export_csv.is_some() {
+            return Err(cmd.error(
+                ErrorKind::ArgumentConflict,
+                "--export-csv requires an input file",
+            ));
+        }
+        if args.export_json.is_some() {
+            return Err(cmd.error(
+                ErrorKind::ArgumentConflict,
+                "--export-json requires an input file",
+            ));
+        }
+        if args.all_types {
+            return Err(cmd.error(
+                ErrorKind::ArgumentConflict,
+                "--all-types requires an input file",
+            ));
+        }
+        if args.dat.is_some() {
+            return Err(cmd.error(ErrorKind::ArgumentConflict, "--dat requires an input file"));
+        }
+        if args.inject_header.is_some() {
+            return Err(cmd.error(
+                ErrorKind::ArgumentConflict,
+                "--inject-header requires an input file",
+            ));
+        }
+        if args.split.is_some() {
+            return Err(cmd.error(
+                ErrorKind::ArgumentConflict,
+                "--split requires an input file",
+            ));
+        }
+    }
 
-    /// Identify rom type (and exit)
-    #[arg(short, long, default_value_t = false)]
-    identify: bool,
+    if args.writes_stdout() && args.writes_rom() {
+        if args.crc_fix {
+            return Err(cmd.error(
+                ErrorKind::ArgumentConflict,
+                "--crc-fix requires an output file",
+            ));
+        }
+        if args.verify {
+            return Err(cmd.error(
+                ErrorKind::ArgumentConflict,
+                "--verify requires an output file",
+            ));
+        }
+        if args.gen_sfv {
+            return Err(cmd.error(
+                ErrorKind::ArgumentConflict,
+                "--gen-sfv requires an output file",
+            ));
+        }
+        if args.gen_md5 {
+            return Err(cmd.error(
+                ErrorKind::ArgumentConflict,
+                "--gen-md5 requires an output file",
+            ));
+        }
+        if args.trim {
+            return Err(cmd.error(
+                ErrorKind::ArgumentConflict,
+                "--trim requires an output file",
+            ));
+        }
+        if args.patch_ips.is_some() || args.patch_bps.is_some() {
+            return Err(cmd.error(
+                ErrorKind::ArgumentConflict,
+                "Patching requires an output file",
+            ));
+        }
+        if args.region_override.is_some() {
+            return Err(cmd.error(
+                ErrorKind::ArgumentConflict,
+                "--region-override requires an output file",
+            ));
+        }
+        if io::stdout().is_terminal() && !args.force && !args.dry_run {
+            return Err(cmd.error(
+                ErrorKind::InvalidValue,
+                "Refusing to write binary data to a terminal, use --force to override",
+            ));
+        }
+    }
 
-    /// Force overwrite output file
-    #[arg(short, long, default_value_t = false)]
-    force: bool,
+    Ok(())
 }
 
-fn main() {
-    let args = Args::parse();
+fn run(args: Args) -> Result<(), ConversionError> {
+    #[cfg(not(unix))]
+    if args.preserve_permissions {
+        warning!("--preserve-permissions has no effect on this platform");
+    }
+    ensure_output_dir(&args)?;
+    if let Some(directory) = &args.directory {
+        return convert_directory(&args, directory);
+    }
+    if let Some(directory) = &args.watch {
+        return watch_directory(&args, directory);
+    }
+    if args.dump_header {
+        for filename in &args.filenames {
+            dump_file_header(&args, filename)?;
+        }
+        return Ok(());
+    }
+    if let Some(rom_type) = args.inject_header {
+        for filename in &args.filenames {
+            inject_file_header(&args, filename, rom_type)?;
+        }
+        return Ok(());
+    }
+    if args.merge {
+        return merge_files(&args);
+    }
+    if let Some(chunk_size) = args.split {
+        for filename in &args.filenames {
+            split_file(&args, filename, chunk_size as u64)?;
+        }
+        return Ok(());
+    }
+    if let Some(pattern) = &args.search {
+        for filename in &args.filenames {
+            search_file(&args, filename, &pattern.0)?;
+        }
+        return Ok(());
+    }
+    if args.all_types {
+        if let [filename] = args.filenames.as_slice() {
+            return convert_all_types(&args, filename);
+        }
+        let mut errors = 0;
+        for filename in &args.filenames {
+            if let Err(error) = convert_all_types(&args, filename) {
+                error!("Error converting {}: {}", filename.display(), error);
+                errors += 1;
+            }
+        }
+        if errors > 0 {
+            return Err(ConversionError::BatchFailed { errors });
+        }
+        return Ok(());
+    }
+    if args.find_duplicates {
+        return find_duplicates(&args, &args.filenames);
+    }
+    if args.inventory {
+        return print_inventory(&args, &args.filenames);
+    }
+    if args.filenames.len() > 1 {
+        return convert_batch(&args, &args.filenames);
+    }
+    single::convert_single(&args)
+}
 
-    // Input file
-    let Ok(file) = File::open(&args.filename) else {
-        println!("Unable to open file: {}", &args.filename);
-        exit(1)
-    };
-    let mut buf = BufReader::new(file);
-    let mut bytes = [0; 4];
+// Flags that make two filenames two inputs, as they're about batches
+const BATCH_FLAGS: [&str; 8] = [
+    "output_dir",
+    "output_template",
+    "rename_from_dat",
+    "no_suffix",
+    "threads",
+    "min_size",
+    "max_size",
+    "merge",
+];
 
-    // Let's read the header
-    let Ok(_) = buf.read_exact(&mut bytes) else {
-        println!("Error reading file: {}", &args.filename);
-        exit(1);
+// The `n64swap in.v64 out.z64` form. A second filename that doesn't exist yet is
+// the output, unless a flag asks for a batch or rules out --output. An existing
+// file is always an input, so a glob matching two roms converts both and never
+// overwrites one with the other.
+fn positional_output(args: &mut Args, matches: &ArgMatches) {
+    let [_, output] = args.filenames.as_slice() else {
+        return;
     };
-
-    let Some(filetype) = identify_header(&bytes) else {
-        println!("File {} not recognized!", &args.filename);
-        exit(1);
+    let cmd = Args::command();
+    let given = |id: &str| matches.value_source(id) == Some(ValueSource::CommandLine);
+    let excludes_output = |arg: &clap::Arg| {
+        let id = arg.get_id().as_str();
+        given(id)
+            && (BATCH_FLAGS.contains(&id)
+                || cmd
+                    .get_arg_conflicts_with(arg)
+                    .iter()
+                    .any(|other| other.get_id() == "destination_filename"))
     };
-
-    if args.identify {
-        println!("File {} is {}", &args.filename, filetype);
-        exit(0);
+    let output_arg = cmd
+        .get_arguments()
+        .find(|arg| arg.get_id() == "destination_filename");
+    let excluded = cmd.get_arguments().any(excludes_output)
+        || output_arg.is_some_and(|arg| {
+            cmd.get_arg_conflicts_with(arg)
+                .iter()
+                .any(|other| given(other.get_id().as_str()))
+        });
+    if excluded || args.destination_filename.is_some() || !args.writes_rom() {
+        return;
     }
+    if output == Path::new(STDIO) || !output.exists() {
+        args.destination_filename = args.filenames.pop();
+    }
+}
 
-    // Output file
-    let outfiletype = args.romtype.unwrap_or_else(|| { // If specified, use that
-        args.destination_filename
-            .as_deref() // Otherwise borrow the destination filename
-            .and_then(detect_ext) // Detect the extension
-            .and_then(guess_type) // Identify the type based on extension
-            .unwrap_or(RomType::BigEndian) // Or default to BigEndian
-    });
+// Expand wildcards the shell left alone, as cmd.exe does
+fn expand_globs(filenames: Vec<PathBuf>) -> Vec<PathBuf> {
+    let mut expanded = Vec::new();
+    for filename in filenames {
+        if filename == Path::new(STDIO)
+            || !filename.to_str().is_some_and(glob::is_pattern)
+            || filename.exists()
+        {
+            expanded.push(filename);
+            continue;
+        }
+        let matched = glob::expand(&filename.to_string_lossy());
+        if matched.is_empty() {
+            warning!("no files match {}", filename.display());
+        }
+        expanded.extend(matched);
+    }
+    expanded
+}
 
-    if filetype == outfiletype {
-        println!("File is already {}!", outfiletype);
-        exit(0);
+fn main() {
+    let matches = Args::command().get_matches();
+    let mut args = Args::from_arg_matches(&matches).unwrap_or_else(|error| error.exit());
+    color::init(args.no_color);
+    if let Some(shell) = args.generate_completions {
+        let mut cmd = Args::command();
+        print!("{}", completions::generate(shell, &mut cmd));
+        eprintln!("{}", shell.instructions(cmd.get_name()));
+        return;
+    }
+    #[cfg(feature = "man")]
+    if args.generate_man {
+        print!("{}", man::generate(&mut Args::command()));
+        return;
+    }
+    positional_output(&mut args, &matches);
+    match config::from_env().and_then(|env| Ok(env.or(config::load()?))) {
+        Ok(config) => args.apply_config(config, &matches),
+        Err(error) => {
+            error!("{}", error);
+            exit(1);
+        }
+    }
+    if !args.filenames.is_empty() {
+        args.filenames = expand_globs(args.filenames);
+        if args.filenames.is_empty() {
+            error!("No input files");
+            exit(1);
+        }
+    }
+    if let Err(error) = validate(&args) {
+        error.exit();
+    }
+    // A chunk at a time, whatever the config file says
+    if args.debug {
+        args.buffer_size = 4;
     }
 
-    let outfilename = args.destination_filename.unwrap_or_else(|| { // If specified, use that
-        let mut name = args.filename.clone(); // Otherwise, copy the input filename
-        let len = name.len(); // Get the filename length
-        if name.chars().nth(len - 4) == Some('.') { // Check if there's a 3-letter extension
-            name.truncate(len - 4); // Lop off the extension
+    let quiet = args.quiet;
+    let machine = args.machine;
+    match run(args) {
+        Ok(()) => {}
+        Err(error) if machine => {
+            println!("{}", error_event(&error));
+            match error {
+                // Nothing to do isn't a failure
+                ConversionError::SameType(_) => {}
+                ConversionError::VerifyFailed { .. } => exit(2),
+                _ => exit(1),
+            }
+        }
+        // Nothing to do isn't a failure
+        Err(error @ ConversionError::SameType(_)) => {
+            if !quiet {
+                eprintln!("{}", error);
+            }
+        }
+        Err(error @ ConversionError::VerifyFailed { .. }) => {
+            error!("{}", error);
+            exit(2);
         }
-        name.push_str(outfiletype.get_file_ext()); // Add the standard extension for the output type
-        name
-    });
+        Err(error) => {
+            error!("{}", error);
+            exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::Write;
+
+    use super::*;
+    use hash::Hashes;
 
-    if args.filename == outfilename {
-        println!(
-            "Input and Output filenames are identical {}, consider renaming input file",
-            &outfilename
+    pub(crate) fn parse(args: &[&str]) -> Args {
+        Args::try_parse_from(["n64swap"].iter().chain(args)).unwrap()
+    }
+
+    #[test]
+    fn sha1_flag_is_hash_sha1() {
+        assert_eq!(
+            parse(&["--sha1", "rom.z64"]).hash_algorithms(),
+            [HashAlgorithm::Sha1]
+        );
+        // Asking twice still prints one digest
+        let args = parse(&["--sha1", "--hash", "sha1", "rom.z64"]);
+        let mut hashes = Hashes::new(&args.computed_hashes());
+        hashes.write_all(b"abc").unwrap();
+        assert_eq!(
+            hashes.finish(),
+            [(
+                HashAlgorithm::Sha1,
+                "a9993e364706816aba3e25717850c26c9cd0d89d".to_string()
+            )]
         );
-        exit(1);
     }
 
-    let outfile = match File::options()
-        .write(true)
-        .create_new(!args.force)
-        .open(&outfilename)
-    {
-        Ok(file) => file,
-        Err(error) => {
-            println!(
-                "Unable to open file {} for output. Error {}",
-                &outfilename, error
-            );
-            exit(1);
+    // Parse like main does, with the second filename maybe taken as the output
+    fn parse_positional(args: &[&str]) -> Args {
+        let matches = Args::command()
+            .try_get_matches_from(["n64swap"].iter().chain(args))
+            .unwrap();
+        let mut args = Args::from_arg_matches(&matches).unwrap();
+        positional_output(&mut args, &matches);
+        args
+    }
+
+    #[test]
+    fn second_filename_is_the_output_if_new() {
+        let dir = std::env::temp_dir();
+        let roms = ["a.v64", "b.v64"]
+            .map(|name| dir.join(format!("n64swap-{}-{}", std::process::id(), name)));
+        for rom in &roms {
+            fs::write(rom, [0; 4]).unwrap();
         }
-    };
-    let mut outbuf = BufWriter::new(outfile);
-    let Ok(_) = outbuf.write_all(outfiletype.get_header_bytes() ) else {
-        println!("Unable to write to output file!");
-        exit(1);
-    };
+        let [a, b] = roms.each_ref().map(|rom| rom.to_str().unwrap());
 
-    while buf.read_exact(&mut bytes).is_ok() {
-        swapper(&mut bytes, filetype, outfiletype);
+        for output in ["new.z64", "new", "-"] {
+            let args = parse_positional(&[a, output]);
+            assert_eq!(args.filenames, [Path::new(a)]);
+            assert_eq!(
+                args.destination_filename.as_deref(),
+                Some(Path::new(output))
+            );
+        }
+        // `n64swap *.v64` matching two roms converts both, even with --force
+        for flags in [&[][..], &["-f"], &["--output-dir", "out"], &["-i"]] {
+            let args = parse_positional(&[flags, &[a, b]].concat());
+            assert_eq!(args.filenames, [Path::new(a), Path::new(b)], "{:?}", flags);
+            assert!(args.destination_filename.is_none());
+        }
+        // A batch flag makes a new name an input too
+        let args = parse_positional(&["--output-dir", "out", a, "new.z64"]);
+        assert_eq!(args.filenames.len(), 2);
+        let args = parse_positional(&["a.v64", "b.v64", "c.v64"]);
+        assert_eq!(args.filenames.len(), 3);
 
-        let Ok(_) = outbuf.write_all(&bytes) else {
-            println!("Error during output!");
-            exit(1);
-        };
+        for rom in &roms {
+            fs::remove_file(rom).unwrap();
+        }
     }
 }
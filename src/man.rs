@@ -0,0 +1,131 @@
+use clap::{Arg, Command};
+
+// Escape text for roff, keeping it from being read as a request
+fn escape(text: &str) -> String {
+    let escaped = text.replace('\\', "\\e").replace('-', "\\-");
+    if escaped.starts_with(['.', '\'']) {
+        format!("\\&{}", escaped)
+    } else {
+        escaped
+    }
+}
+
+fn option(arg: &Arg) -> String {
+    let mut names = Vec::new();
+    if let Some(short) = arg.get_short() {
+        names.push(format!("\\fB\\-{}\\fR", short));
+    }
+    if let Some(long) = arg.get_long() {
+        names.push(format!("\\fB\\-\\-{}\\fR", escape(long)));
+    }
+    let mut line = names.join(", ");
+    if arg.get_action().takes_values() {
+        let value = match arg.get_value_names() {
+            Some(names) => names.iter().map(|name| name.to_string()).collect(),
+            None => arg.get_id().as_str().to_uppercase(),
+        };
+        line.push_str(&format!(" \\fI{}\\fR", escape(&value)));
+    }
+
+    let mut help = arg
+        .get_long_help()
+        .or(arg.get_help())
+        .map(|help| help.to_string())
+        .unwrap_or_default();
+    let values: Vec<String> = arg
+        .get_possible_values()
+        .iter()
+        .filter(|value| !value.is_hide_set())
+        .map(|value| value.get_name().to_string())
+        .collect();
+    if !values.is_empty() {
+        help.push_str(&format!(" [possible values: {}]", values.join(", ")));
+    }
+    format!(".TP\n{}\n{}\n", line, escape(&help))
+}
+
+const EXAMPLES: [(&str, &str); 5] = [
+    (
+        "n64swap file.v64",
+        "Convert file.v64 into a big-endian file.z64",
+    ),
+    (
+        "n64swap file.z64 -r byte-swap -o out.v64",
+        "Convert into a byte-swapped rom named out.v64",
+    ),
+    (
+        "n64swap *.n64 --output-dir converted",
+        "Convert several roms into a directory",
+    ),
+    (
+        "n64swap -i file.z64",
+        "Show the rom type and header of file.z64",
+    ),
+    (
+        "n64swap -d roms -R --dry-run",
+        "List what converting every rom below roms would do",
+    ),
+];
+
+const EXIT_STATUS: [(&str, &str); 3] = [
+    (
+        "0",
+        "Success, also when the rom already was the requested type",
+    ),
+    ("1", "A rom couldn't be read, converted or written"),
+    ("2", "Invalid arguments, or the output didn't verify"),
+];
+
+/// A roff man page for `cmd`, in section 1
+pub fn generate(cmd: &mut Command) -> String {
+    cmd.build();
+    let name = cmd.get_name().to_string();
+    let about = cmd
+        .get_about()
+        .map(|about| about.to_string())
+        .unwrap_or_default();
+    let usage = cmd.render_usage().to_string();
+    let usage = usage.trim().trim_start_matches("Usage:").trim();
+
+    let mut page = format!(
+        ".TH {} 1 \"\" \"{} {}\"\n",
+        name.to_uppercase(),
+        name,
+        env!("CARGO_PKG_VERSION")
+    );
+    page.push_str(&format!(".SH NAME\n{} \\- {}\n", name, escape(&about)));
+    page.push_str(&format!(".SH SYNOPSIS\n\\fB{}\\fR\n", escape(usage)));
+    page.push_str(&format!(
+        ".SH DESCRIPTION\n{}\n",
+        escape(
+            "Converts Nintendo 64 roms between the big-endian (.z64), byte-swapped (.v64) \
+             and little-endian (.n64) byte orders. The input type is detected from the \
+             header, the output is big-endian unless --romtype says otherwise."
+        )
+    ));
+    page.push_str(".SH OPTIONS\n");
+    for arg in cmd.get_arguments() {
+        if arg.is_positional() || arg.is_hide_set() {
+            continue;
+        }
+        page.push_str(&option(arg));
+    }
+    page.push_str(".SH EXAMPLES\n");
+    for (example, description) in EXAMPLES {
+        page.push_str(&format!(
+            ".TP\n\\fB{}\\fR\n{}\n",
+            escape(example),
+            escape(description)
+        ));
+    }
+    page.push_str(".SH EXIT STATUS\n");
+    for (status, description) in EXIT_STATUS {
+        page.push_str(&format!(
+            ".TP\n\\fB{}\\fR\n{}\n",
+            status,
+            escape(description)
+        ));
+    }
+    page.push_str(".SH SEE ALSO\n\\fBucon64\\fR(1), \\fBdd\\fR(1)\n");
+    page
+}
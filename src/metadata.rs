@@ -0,0 +1,114 @@
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek};
+use std::path::Path;
+
+use n64swap::{ConversionError, RomHeader, HEADER_SIZE};
+
+use crate::hash::{HashAlgorithm, Hashes};
+use crate::json::JsonObject;
+
+/// What --export-csv and --export-json record about a rom
+pub struct RomMetadata {
+    pub filename: String,
+    pub rom_type: String,
+    pub title: String,
+    pub cart_id: String,
+    pub country_code: String,
+    pub region: String,
+    pub crc1: String,
+    pub crc2: String,
+    pub revision: u8,
+    pub file_size_bytes: u64,
+    pub file_md5: String,
+    /// The raw first 64 bytes, in the rom's own byte order
+    pub header: [u8; HEADER_SIZE],
+}
+
+/// CSV header row, in the order of `RomMetadata::csv_row`
+pub const CSV_COLUMNS: [&str; 11] = [
+    "filename",
+    "rom_type",
+    "title",
+    "cart_id",
+    "country_code",
+    "region",
+    "crc1",
+    "crc2",
+    "revision",
+    "file_size_bytes",
+    "file_md5",
+];
+
+impl RomMetadata {
+    /// Read the header and MD5 of the rom at `path`
+    pub fn read(path: &Path) -> Result<RomMetadata, ConversionError> {
+        let file = File::open(path)?;
+        let file_size_bytes = file.metadata()?.len();
+        let mut reader = BufReader::new(file);
+        let mut raw = [0; HEADER_SIZE];
+        reader.read_exact(&mut raw)?;
+        let header = RomHeader::parse(&raw)?;
+
+        let mut hashes = Hashes::new(&[HashAlgorithm::Md5]);
+        reader.rewind()?;
+        io::copy(&mut reader, &mut hashes)?;
+        let file_md5 = hashes
+            .finish()
+            .into_iter()
+            .map(|(_, digest)| digest)
+            .next()
+            .unwrap_or_default();
+
+        Ok(RomMetadata {
+            filename: path.to_string_lossy().into_owned(),
+            rom_type: header.rom_type.name().to_string(),
+            title: header.title(),
+            cart_id: header.cart_id(),
+            country_code: format!("0x{:02X}", header.country_code.to_byte()),
+            region: header.country_code.region_string().to_string(),
+            crc1: format!("0x{:08X}", header.crc1),
+            crc2: format!("0x{:08X}", header.crc2),
+            revision: header.version,
+            file_size_bytes,
+            file_md5,
+            header: raw,
+        })
+    }
+
+    pub fn json(&self) -> JsonObject {
+        let header: String = self
+            .header
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        JsonObject::new()
+            .string("filename", &self.filename)
+            .string("rom_type", &self.rom_type)
+            .string("title", &self.title)
+            .string("cart_id", &self.cart_id)
+            .string("country_code", &self.country_code)
+            .string("region", &self.region)
+            .string("crc1", &self.crc1)
+            .string("crc2", &self.crc2)
+            .number("revision", self.revision.into())
+            .number("file_size_bytes", self.file_size_bytes)
+            .string("file_md5", &self.file_md5)
+            .string("header", &header)
+    }
+
+    pub fn csv_row(&self) -> Vec<String> {
+        vec![
+            self.filename.clone(),
+            self.rom_type.clone(),
+            self.title.clone(),
+            self.cart_id.clone(),
+            self.country_code.clone(),
+            self.region.clone(),
+            self.crc1.clone(),
+            self.crc2.clone(),
+            self.revision.to_string(),
+            self.file_size_bytes.to_string(),
+            self.file_md5.clone(),
+        ]
+    }
+}
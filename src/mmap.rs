@@ -0,0 +1,127 @@
+use std::ffi::c_void;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::os::fd::AsRawFd;
+use std::os::raw::c_int;
+use std::path::Path;
+use std::ptr;
+
+use crate::{
+    check_distinct_paths, check_file_size, identify_header, swapper_simd, ConversionError, RomType,
+};
+
+// <sys/mman.h>, identical on Linux and the BSDs
+const PROT_READ: c_int = 1;
+const PROT_WRITE: c_int = 2;
+const MAP_SHARED: c_int = 1;
+
+extern "C" {
+    fn mmap(
+        addr: *mut c_void,
+        len: usize,
+        prot: c_int,
+        flags: c_int,
+        fd: c_int,
+        offset: i64,
+    ) -> *mut c_void;
+    fn munmap(addr: *mut c_void, len: usize) -> c_int;
+}
+
+// A shared mapping of a whole file, unmapped on drop
+struct Mapping {
+    ptr: *mut u8,
+    len: usize,
+}
+
+impl Mapping {
+    fn new(file: &File, len: usize, prot: c_int) -> io::Result<Mapping> {
+        // SAFETY: a fresh mapping chosen by the kernel, it doesn't alias any Rust memory
+        let ptr = unsafe { mmap(ptr::null_mut(), len, prot, MAP_SHARED, file.as_raw_fd(), 0) };
+        // MAP_FAILED
+        if ptr as isize == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Mapping {
+            ptr: ptr.cast(),
+            len,
+        })
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        // SAFETY: the mapping is `len` bytes long and lives as long as `self`
+        unsafe { std::slice::from_raw_parts(self.ptr, self.len) }
+    }
+
+    // Only valid for mappings created with PROT_WRITE
+    fn as_mut_slice(&mut self) -> &mut [u8] {
+        // SAFETY: as above, and `&mut self` makes the borrow unique
+        unsafe { std::slice::from_raw_parts_mut(self.ptr, self.len) }
+    }
+}
+
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        // SAFETY: `ptr` and `len` come from a successful mmap call
+        unsafe {
+            munmap(self.ptr.cast(), self.len);
+        }
+    }
+}
+
+/// Convert the rom at `src` into `dst_type` through memory-mapped files.
+///
+/// Both files are mapped whole and swapped in a single pass. The input must not be
+/// truncated by another process while converting, and `dst` can't be `src`
+/// (`InputOutputSameFile`).
+pub fn convert_mmap(src: &Path, dst: &Path, dst_type: RomType) -> Result<(), ConversionError> {
+    // Truncating the destination would cut the mapped input short
+    check_distinct_paths(src, dst)?;
+    let input = File::open(src)?;
+    let size = input.metadata()?.len();
+    check_file_size(size)?;
+    if size == 0 {
+        return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+    }
+    let len = usize::try_from(size).map_err(|_| io::Error::from(io::ErrorKind::OutOfMemory))?;
+
+    let source = Mapping::new(&input, len, PROT_READ)?;
+    let magic = [
+        source.as_slice()[0],
+        source.as_slice()[1],
+        source.as_slice()[2],
+        source.as_slice()[3],
+    ];
+    let src_type = identify_header(&magic).ok_or(ConversionError::UnrecognizedHeader(magic))?;
+
+    let output = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(dst)?;
+    output.set_len(size)?;
+    let mut target = Mapping::new(&output, len, PROT_READ | PROT_WRITE)?;
+
+    let target_bytes = target.as_mut_slice();
+    target_bytes.copy_from_slice(source.as_slice());
+    swapper_simd(target_bytes, src_type, dst_type);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::TempFile;
+    use crate::BYTE_SWAP;
+
+    #[test]
+    fn convert_mmap_refuses_its_own_input() {
+        let mut rom = BYTE_SWAP.to_vec();
+        rom.extend([1, 2, 3, 4]);
+        let src = TempFile::new("mmap-same.v64", &rom);
+        let error = convert_mmap(&src.0, &src.0, RomType::BigEndian).unwrap_err();
+        assert!(matches!(error, ConversionError::InputOutputSameFile(_)));
+        assert_eq!(std::fs::read(&src.0).unwrap(), rom);
+    }
+}
@@ -0,0 +1,169 @@
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use n64swap::{normalize_path_windows, ConversionError};
+
+/// An output file written under a temporary name next to its destination.
+///
+/// Nothing shows up at the destination until `persist` moves the finished file
+/// there, an output that's dropped before that is removed again.
+pub struct TempOutput {
+    file: File,
+    temp: PathBuf,
+    dest: PathBuf,
+    // As given, for errors
+    name: PathBuf,
+    force: bool,
+    persisted: bool,
+}
+
+impl TempOutput {
+    /// Create the temporary file, refusing an existing destination unless forced
+    pub fn create(dest: &Path, force: bool) -> Result<TempOutput, ConversionError> {
+        // A long path has to be extended-length on Windows
+        let path = normalize_path_windows(dest);
+        if !force && path.exists() {
+            return Err(ConversionError::OutputExists(dest.to_path_buf()));
+        }
+
+        let name = dest.file_name().unwrap_or_default().to_string_lossy();
+        for attempt in 0.. {
+            let temp = normalize_path_windows(&path.with_file_name(format!(
+                ".{}.{}.{}.n64swap.tmp",
+                name,
+                process::id(),
+                attempt
+            )));
+            match File::options()
+                .read(true)
+                .write(true)
+                .create_new(true)
+                .open(&temp)
+            {
+                Ok(file) => {
+                    return Ok(TempOutput {
+                        file,
+                        temp,
+                        dest: path,
+                        name: dest.to_path_buf(),
+                        force,
+                        persisted: false,
+                    })
+                }
+                // Left behind by an earlier run that was killed
+                Err(error) if error.kind() == io::ErrorKind::AlreadyExists => {}
+                Err(error) => return Err(error.into()),
+            }
+        }
+        unreachable!()
+    }
+
+    pub fn file(&mut self) -> &mut File {
+        &mut self.file
+    }
+
+    /// Sync the written data to disk and move it to the destination, which it
+    /// only replaces when forced. The temporary file is removed if that fails.
+    pub fn persist(mut self) -> Result<(), ConversionError> {
+        self.file.sync_all()?;
+        if self.force {
+            fs::rename(&self.temp, &self.dest)?;
+        } else {
+            self.link()?;
+        }
+        self.persisted = true;
+        Ok(())
+    }
+
+    // Move the file to a destination that has to still be free, a hard link fails
+    // on one created since `create` checked, where a rename would replace it
+    fn link(&self) -> Result<(), ConversionError> {
+        match fs::hard_link(&self.temp, &self.dest) {
+            Ok(()) => {
+                fs::remove_file(&self.temp)?;
+                Ok(())
+            }
+            Err(error) if error.kind() == io::ErrorKind::AlreadyExists => {
+                Err(ConversionError::OutputExists(self.name.clone()))
+            }
+            // No hard links on this file system, such as FAT
+            Err(_) if self.dest.exists() => Err(ConversionError::OutputExists(self.name.clone())),
+            Err(_) => {
+                fs::rename(&self.temp, &self.dest)?;
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Drop for TempOutput {
+    fn drop(&mut self) {
+        if !self.persisted {
+            let _ = fs::remove_file(&self.temp);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    // An empty directory of its own for each test
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("n64swap-{}-{}", process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir(&dir).unwrap();
+        dir
+    }
+
+    fn entries(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn persist_keeps_a_destination_created_meanwhile() {
+        let dir = test_dir("no-clobber");
+        let dest = dir.join("out.z64");
+        let mut output = TempOutput::create(&dest, false).unwrap();
+        output.file().write_all(b"new").unwrap();
+        fs::write(&dest, b"old").unwrap();
+        assert!(matches!(
+            output.persist(),
+            Err(ConversionError::OutputExists(path)) if path == dest
+        ));
+        assert_eq!(fs::read(&dest).unwrap(), b"old");
+        assert_eq!(entries(&dir), ["out.z64"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn persist_moves_the_file_into_place() {
+        let dir = test_dir("persist");
+        let dest = dir.join("out.z64");
+        let mut output = TempOutput::create(&dest, false).unwrap();
+        output.file().write_all(b"new").unwrap();
+        output.persist().unwrap();
+        assert_eq!(fs::read(&dest).unwrap(), b"new");
+        assert_eq!(entries(&dir), ["out.z64"]);
+
+        assert!(matches!(
+            TempOutput::create(&dest, false),
+            Err(ConversionError::OutputExists(_))
+        ));
+        let mut output = TempOutput::create(&dest, true).unwrap();
+        output.file().write_all(b"forced").unwrap();
+        output.persist().unwrap();
+        assert_eq!(fs::read(&dest).unwrap(), b"forced");
+        assert_eq!(entries(&dir), ["out.z64"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
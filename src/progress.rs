@@ -0,0 +1,150 @@
+use std::io::{self, Write};
+use std::time::Instant;
+
+use crate::json::JsonObject;
+
+// Redraw the bar at most once per this many bytes
+const BAR_INTERVAL: u64 = 64 * 1024;
+// Verbose mode logs a line, and --machine an event, per this many bytes
+const LOG_INTERVAL: u64 = 1024 * 1024;
+const BAR_WIDTH: usize = 30;
+const MIB: f64 = 1024.0 * 1024.0;
+
+/// How progress is reported, on stderr unless it's events
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum ProgressStyle {
+    Hidden,
+    /// A single redrawn progress bar
+    Bar,
+    /// One line per MiB, for --verbose
+    Log,
+    /// One JSON progress event per MiB on stdout, for --machine
+    Events,
+}
+
+struct State {
+    style: ProgressStyle,
+    total: Option<u64>,
+    last_draw: u64,
+    start: Instant,
+    drawn: bool,
+}
+
+/// Writer adapter reporting progress on stderr as bytes go through it
+pub struct Progress<W: Write> {
+    inner: W,
+    done: u64,
+    state: Option<State>,
+}
+
+impl<W: Write> Progress<W> {
+    pub fn new(inner: W, total: Option<u64>, style: ProgressStyle) -> Progress<W> {
+        let state = (style != ProgressStyle::Hidden).then(|| State {
+            style,
+            total,
+            last_draw: 0,
+            start: Instant::now(),
+            drawn: false,
+        });
+        Progress {
+            inner,
+            done: 0,
+            state,
+        }
+    }
+
+    fn draw(&mut self) {
+        let Some(state) = &mut self.state else {
+            return;
+        };
+        state.last_draw = self.done;
+
+        match state.style {
+            ProgressStyle::Log => {
+                eprintln!("Offset 0x{:08X}: {} bytes processed", self.done, self.done);
+                return;
+            }
+            ProgressStyle::Events => {
+                let event = JsonObject::new()
+                    .string("event", "progress")
+                    .number("bytes", self.done)
+                    .optional_number("total", state.total);
+                println!("{}", event);
+                return;
+            }
+            _ => {}
+        }
+        state.drawn = true;
+
+        let elapsed = state.start.elapsed().as_secs_f64();
+        let speed = if elapsed > 0.0 {
+            self.done as f64 / MIB / elapsed
+        } else {
+            0.0
+        };
+        let done = self.done as f64 / MIB;
+
+        match state.total {
+            Some(total) if total > 0 => {
+                let filled = (self.done.min(total) as usize * BAR_WIDTH) / total as usize;
+                eprint!(
+                    "\r[{}{}] {:.1}/{:.1} MiB, {:.1} MiB/s",
+                    "#".repeat(filled),
+                    "-".repeat(BAR_WIDTH - filled),
+                    done,
+                    total as f64 / MIB,
+                    speed
+                );
+            }
+            _ => eprint!("\r{:.1} MiB, {:.1} MiB/s", done, speed),
+        }
+    }
+
+    /// Draw the final state and end the progress line
+    pub fn finish(&mut self) {
+        if let Some(state) = &self.state {
+            // An event for the last bytes, unless one went out after them
+            let last = state.style == ProgressStyle::Events && state.last_draw != self.done;
+            if state.style == ProgressStyle::Bar || last {
+                self.draw();
+            }
+            self.state = None;
+        }
+    }
+
+    /// Stop reporting and get the wrapped writer back
+    pub fn into_inner(mut self) -> W {
+        self.finish();
+        self.inner
+    }
+}
+
+impl<W: Write> Write for Progress<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.done += written as u64;
+        if let Some(state) = &self.state {
+            let interval = match state.style {
+                ProgressStyle::Log | ProgressStyle::Events => LOG_INTERVAL,
+                _ => BAR_INTERVAL,
+            };
+            if self.done - state.last_draw >= interval {
+                self.draw();
+            }
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl Drop for State {
+    fn drop(&mut self) {
+        // End the progress line, also when bailing out halfway
+        if self.drawn {
+            eprintln!();
+        }
+    }
+}
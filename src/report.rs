@@ -0,0 +1,551 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read, Seek, Write};
+use std::path::{Path, PathBuf};
+
+use n64swap::{
+    convert_buffered, crc32, detect_content_size, detect_ipl3, detect_padding_byte,
+    detect_save_type, format_size, normalize, read_bootcode, read_header, read_rom_type,
+    ConversionError, RomHeader, RomType, SaveType, CHECKSUM_LENGTH,
+};
+
+use crate::color::{self, Color};
+use crate::csv;
+use crate::dat::{Dat, DatStatus};
+use crate::hash::{HashAlgorithm, HashTee, Hashes};
+use crate::input::open_input;
+use crate::inventory;
+use crate::json::{self, JsonObject};
+use crate::metadata::{RomMetadata, CSV_COLUMNS};
+use crate::sfv;
+use crate::{Args, OutputFormat};
+
+/// What --identify reports about a rom
+pub struct Identity {
+    header: RomHeader,
+    // None when the rom is too small to hold one
+    bootcode: Option<Vec<u8>>,
+    size: Option<u64>,
+    // The padding byte and the size before it, when the rom file is padded
+    pub padding: Option<(u8, u64)>,
+    save_type: Option<SaveType>,
+    // Digests of the whole input, for --hash
+    digests: Vec<(HashAlgorithm, String)>,
+}
+
+pub fn read_identity<R: Read>(
+    args: &Args,
+    reader: &mut R,
+    size: Option<u64>,
+) -> Result<Identity, ConversionError> {
+    let mut hashes = Hashes::new(&args.hash_algorithms());
+    let mut reader = HashTee {
+        inner: reader,
+        hashes: &mut hashes,
+    };
+
+    let header = read_header(&mut reader)?;
+    let bootcode = match read_bootcode(&mut reader, header.rom_type) {
+        Ok(bootcode) => Some(bootcode),
+        Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => None,
+        Err(error) => return Err(error.into()),
+    };
+    // The save type is looked for in the code the CRCs cover, the rest is only
+    // read for the digests
+    let mut body = Vec::new();
+    reader
+        .by_ref()
+        .take(CHECKSUM_LENGTH as u64)
+        .read_to_end(&mut body)?;
+    normalize(&mut body, header.rom_type);
+    let save_type = detect_save_type(&body);
+    if !reader.hashes.is_empty() {
+        io::copy(&mut reader, &mut io::sink())?;
+    }
+
+    Ok(Identity {
+        header,
+        bootcode,
+        size,
+        padding: None,
+        save_type,
+        digests: hashes.finish(),
+    })
+}
+
+/// The padding byte and content size of a padded rom file, for --identify
+pub fn read_padding(rom: &Path) -> Option<(u8, u64)> {
+    let byte = detect_padding_byte(rom)?;
+    detect_content_size(rom).ok().map(|size| (byte, size))
+}
+
+impl Identity {
+    fn cic(&self) -> String {
+        let Some(bootcode) = &self.bootcode else {
+            return "Unknown (no bootcode)".to_string();
+        };
+        match detect_ipl3(bootcode) {
+            Some(cic) => format!("{} ({})", cic, cic.chips()),
+            None => format!("Unknown (CRC: 0x{:08X})", crc32(bootcode)),
+        }
+    }
+
+    fn save_type(&self) -> String {
+        self.save_type
+            .map_or_else(|| "Unknown".to_string(), |save_type| save_type.to_string())
+    }
+}
+
+pub fn print_identify(args: &Args, filename: &Path, identity: &Identity) {
+    let header = &identity.header;
+    match args.output_format {
+        // validate() only lets csv through for --inventory
+        OutputFormat::Text | OutputFormat::Csv => {
+            let rom_type = color::stdout(Color::Cyan, &header.rom_type.to_string());
+            println!("File {} is {}", filename.display(), rom_type);
+            println!("{}", header);
+            if let Some(size) = identity.size {
+                let note = if size.is_power_of_two() {
+                    ""
+                } else {
+                    " (not a standard cartridge size)"
+                };
+                println!("Size: {} ({} bytes){}", format_size(size), size, note);
+                if let Some((byte, content)) = identity.padding {
+                    println!(
+                        "Content size: {} ({} trailing 0x{:02X} padding)",
+                        format_size(content),
+                        format_size(size - content),
+                        byte
+                    );
+                }
+            }
+            println!("CIC: {}", identity.cic());
+            println!("Save type: {}", identity.save_type());
+            print_digests(args, &identity.digests);
+        }
+        OutputFormat::Json => {
+            let mut json = JsonObject::new()
+                .string("filename", &filename.to_string_lossy())
+                .string("rom_type", header.rom_type.name())
+                .string("rom_title", &header.title())
+                .string("clock_rate", &format!("0x{:08X}", header.clock_rate))
+                .string("boot_address", &format!("0x{:08X}", header.boot_address))
+                .string("cart_id", &header.cart_id())
+                .string("crc1", &format!("0x{:08X}", header.crc1))
+                .string("crc2", &format!("0x{:08X}", header.crc2))
+                .string(
+                    "country_code",
+                    &format!("0x{:02X}", header.country_code.to_byte()),
+                )
+                .string("region", header.country_code.region_string())
+                .number("revision", header.version.into())
+                .string("cic", &identity.cic())
+                .string("save_type", &identity.save_type())
+                .optional_number("file_size_bytes", identity.size)
+                .optional_number(
+                    "content_size_bytes",
+                    identity
+                        .padding
+                        .map(|(_, content)| content)
+                        .or(identity.size),
+                );
+            for (algorithm, digest) in &identity.digests {
+                json = json.string(&algorithm.label().to_lowercase(), digest);
+            }
+            println!("{}", json);
+        }
+    }
+}
+
+/// Print the digests that were asked for, leaving the CRC32 only kept for --gen-sfv
+pub fn print_digests(args: &Args, digests: &[(HashAlgorithm, String)]) {
+    // The complete event has them
+    if args.machine {
+        return;
+    }
+    let requested = args.hash_algorithms();
+    for (algorithm, digest) in digests {
+        if requested.contains(algorithm) {
+            println!("{}: {}", algorithm.label(), digest);
+        }
+    }
+}
+
+/// The ConversionError variant, as the code of a --machine error event
+pub fn error_code(error: &ConversionError) -> &'static str {
+    match error {
+        ConversionError::IoError(_) => "IoError",
+        ConversionError::UnrecognizedHeader(_) => "UnrecognizedHeader",
+        ConversionError::SizeMisaligned { .. } => "SizeMisaligned",
+        ConversionError::SameType(_) => "SameType",
+        ConversionError::OutputExists(_) => "OutputExists",
+        ConversionError::BackupExists(_) => "BackupExists",
+        ConversionError::UnknownExtension(_) => "UnknownExtension",
+        ConversionError::InputOutputSameFile(_) => "InputOutputSameFile",
+        ConversionError::OutputCollision(_) => "OutputCollision",
+        ConversionError::Checksum(_) => "Checksum",
+        ConversionError::BatchFailed { .. } => "BatchFailed",
+        ConversionError::InvalidBufferSize(_) => "InvalidBufferSize",
+        ConversionError::InvalidChunkSize(_) => "InvalidChunkSize",
+        ConversionError::ChunkTypeMismatch { .. } => "ChunkTypeMismatch",
+        ConversionError::ValidationFailed(_) => "ValidationFailed",
+        ConversionError::VerifyFailed { .. } => "VerifyFailed",
+        ConversionError::RomsDiffer(_) => "RomsDiffer",
+        ConversionError::Ips(_) => "Ips",
+        ConversionError::Bps(_) => "Bps",
+    }
+}
+
+pub fn error_event(error: &ConversionError) -> JsonObject {
+    JsonObject::new()
+        .string("event", "error")
+        .string("code", error_code(error))
+        .string("detail", &error.to_string())
+}
+
+/// Report a rom of a batch that failed to convert
+pub fn conversion_failed(args: &Args, rom: &Path, error: &ConversionError) {
+    if args.machine {
+        println!(
+            "{}",
+            error_event(error).string("src", &rom.to_string_lossy())
+        );
+    } else {
+        error!("Error converting {}: {}", rom.display(), error);
+    }
+}
+
+/// The --gen-sfv entry of a converted rom, a requested --hash crc32 alone doesn't count
+pub fn sfv_entry(
+    args: &Args,
+    outpath: &Path,
+    digests: &[(HashAlgorithm, String)],
+) -> Option<(PathBuf, String)> {
+    if !args.gen_sfv {
+        return None;
+    }
+    digests
+        .iter()
+        .find(|(algorithm, _)| *algorithm == HashAlgorithm::Crc32)
+        .map(|(_, crc)| (outpath.to_path_buf(), crc.clone()))
+}
+
+/// The --gen-md5 entry of a converted rom
+pub fn md5_entry(
+    args: &Args,
+    outpath: &Path,
+    digests: &[(HashAlgorithm, String)],
+) -> Option<(PathBuf, String)> {
+    if !args.gen_md5 {
+        return None;
+    }
+    digests
+        .iter()
+        .find(|(algorithm, _)| *algorithm == HashAlgorithm::Md5)
+        .map(|(_, md5)| (outpath.to_path_buf(), md5.clone()))
+}
+
+/// Write the SFV entries to --sfv-output, or each to its own sidecar file
+pub fn write_sfv_entries(
+    args: &Args,
+    entries: &[(PathBuf, String)],
+) -> Result<(), ConversionError> {
+    let written = match &args.sfv_output {
+        Some(path) => sfv::write_sfv(path, entries).map(|()| vec![path.clone()]),
+        None => entries
+            .iter()
+            .map(|entry| {
+                let path = sfv::sidecar_path(&entry.0);
+                sfv::write_sfv(&path, std::slice::from_ref(entry)).map(|()| path)
+            })
+            .collect(),
+    };
+    for path in written? {
+        info!(args, "Wrote {}", path.display());
+    }
+    Ok(())
+}
+
+/// Write the MD5 entries to --md5-output, or each to its own sidecar file
+pub fn write_md5_entries(
+    args: &Args,
+    entries: &[(PathBuf, String)],
+) -> Result<(), ConversionError> {
+    let written = match &args.md5_output {
+        Some(path) => sfv::write_md5(path, entries).map(|()| vec![path.clone()]),
+        None => entries
+            .iter()
+            .map(|entry| {
+                let path = sfv::md5_sidecar_path(&entry.0);
+                sfv::write_md5(&path, std::slice::from_ref(entry)).map(|()| path)
+            })
+            .collect(),
+    };
+    for path in written? {
+        info!(args, "Wrote {}", path.display());
+    }
+    Ok(())
+}
+
+/// Read what --export-csv and --export-json record, before the rom gets converted
+pub fn read_export(args: &Args, rom: &Path) -> Result<Option<RomMetadata>, ConversionError> {
+    if args.export_csv.is_none() && args.export_json.is_none() {
+        return Ok(None);
+    }
+    RomMetadata::read(rom).map(Some)
+}
+
+/// Add a row about the rom to the --export-csv file, and collect its
+/// --export-json entry for write_json_export
+pub fn export_rom(
+    args: &Args,
+    rom: &Path,
+    metadata: &RomMetadata,
+    json_entries: &mut Vec<JsonObject>,
+) -> Result<(), ConversionError> {
+    if let Some(path) = &args.export_json {
+        if args.dry_run {
+            println!("Would add {} to {}", rom.display(), path.display());
+        }
+        json_entries.push(metadata.json());
+    }
+    let Some(path) = &args.export_csv else {
+        return Ok(());
+    };
+    if args.dry_run {
+        println!("Would add {} to {}", rom.display(), path.display());
+        return Ok(());
+    }
+    csv::append_row(path, &CSV_COLUMNS, &metadata.csv_row()).map_err(|error| {
+        let message = format!("Unable to write {}: {}", path.display(), error);
+        io::Error::new(error.kind(), message)
+    })?;
+    Ok(())
+}
+
+pub fn write_json_export(args: &Args, json_entries: &[JsonObject]) -> Result<(), ConversionError> {
+    let Some(path) = &args.export_json else {
+        return Ok(());
+    };
+    if args.dry_run || json_entries.is_empty() {
+        return Ok(());
+    }
+    json::write_array(path, json_entries, args.append).map_err(|error| {
+        let message = format!("Unable to write {}: {}", path.display(), error);
+        io::Error::new(error.kind(), message)
+    })?;
+    Ok(())
+}
+
+/// Check the files listed in the --validate checksum file, returning the ones
+/// that need skipping
+pub fn validate_checksums(args: &Args) -> Result<HashSet<PathBuf>, ConversionError> {
+    let mut rejected = HashSet::new();
+    let Some(checksum_file) = args.validate.as_deref() else {
+        return Ok(rejected);
+    };
+    let content = fs::read_to_string(checksum_file).map_err(|error| {
+        let message = format!("Unable to read {}: {}", checksum_file.display(), error);
+        io::Error::new(error.kind(), message)
+    })?;
+    let (algorithm, entries): (_, Vec<(String, String)>) = if checksum_file
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("md5"))
+    {
+        let entries = sfv::parse_md5(&content).into_iter().map(|(name, digest)| {
+            let digest = digest.iter().map(|byte| format!("{:02x}", byte)).collect();
+            (name, digest)
+        });
+        (HashAlgorithm::Md5, entries.collect())
+    } else {
+        let entries = sfv::parse_sfv(&content)
+            .into_iter()
+            .map(|(name, crc)| (name, format!("{:08X}", crc)));
+        (HashAlgorithm::Crc32, entries.collect())
+    };
+
+    let base = checksum_file.parent().unwrap_or(Path::new(""));
+    let (mut passed, mut failed) = (0, 0);
+    for (name, expected) in entries {
+        let path = base.join(&name);
+        let digest = File::open(&path).and_then(|file| {
+            let mut hashes = Hashes::new(&[algorithm]);
+            io::copy(&mut BufReader::new(file), &mut hashes)?;
+            Ok(hashes.finish().into_iter().map(|(_, digest)| digest).next())
+        });
+        match digest {
+            Ok(Some(digest)) if digest == expected => {
+                success!(args, "OK {}", name);
+                passed += 1;
+                continue;
+            }
+            Ok(_) => error!("FAILED {}", name),
+            Err(error) => error!("FAILED {}: {}", name, error),
+        }
+        failed += 1;
+        if !args.force {
+            rejected.insert(path.canonicalize().unwrap_or(path));
+        }
+    }
+    info!(args, "{} passed, {} failed validation", passed, failed);
+    Ok(rejected)
+}
+
+pub fn is_rejected(rejected: &HashSet<PathBuf>, rom: &Path) -> bool {
+    !rejected.is_empty() && rom.canonicalize().is_ok_and(|rom| rejected.contains(&rom))
+}
+
+// The size and CRC32 of a rom in big-endian order, the one No-Intro lists dumps in
+fn big_endian_crc(args: &Args, rom: &Path) -> Result<(u64, u32), ConversionError> {
+    let file = open_input(rom)?;
+    let size = file.metadata()?.len();
+    let mut reader = BufReader::new(file);
+    let filetype = match args.from {
+        Some(filetype) => filetype,
+        None => read_rom_type(&mut reader)?,
+    };
+    reader.rewind()?;
+    let mut hashes = Hashes::new(&[HashAlgorithm::Crc32]);
+    convert_buffered(
+        &mut reader,
+        &mut hashes,
+        filetype,
+        RomType::BigEndian,
+        args.pad,
+        args.buffer_size,
+    )?;
+    let crc = hashes
+        .finish()
+        .into_iter()
+        .find_map(|(_, digest)| u32::from_str_radix(&digest, 16).ok())
+        .unwrap_or_default();
+    Ok((size, crc))
+}
+
+/// Report how the roms compare with the --dat file, returning the DAT names of
+/// the known-good ones for --rename-from-dat
+pub fn check_dat(
+    args: &Args,
+    roms: &[PathBuf],
+) -> Result<HashMap<PathBuf, String>, ConversionError> {
+    let mut names = HashMap::new();
+    let Some(dat_file) = &args.dat else {
+        return Ok(names);
+    };
+    let dat = Dat::load(dat_file).map_err(|error| {
+        let message = format!("Unable to read DAT file {}: {}", dat_file.display(), error);
+        io::Error::new(error.kind(), message)
+    })?;
+    if dat.is_empty() {
+        warning!("No roms with a CRC32 found in {}", dat_file.display());
+    }
+
+    for rom in roms {
+        let (size, crc) = match big_endian_crc(args, rom) {
+            Ok(checked) => checked,
+            Err(error) => {
+                error!(
+                    "Unable to check {} against the DAT: {}",
+                    rom.display(),
+                    error
+                );
+                continue;
+            }
+        };
+        match dat.lookup(rom, size, crc) {
+            DatStatus::KnownGood(entry) => {
+                success!(args, "{}: known-good, {}", rom.display(), entry.game);
+                names.insert(rom.clone(), entry.name.clone());
+            }
+            DatStatus::KnownBad(entry) => error!(
+                "{}: known-bad, CRC32 {:08X} instead of {:08X}",
+                rom.display(),
+                crc,
+                entry.crc
+            ),
+            DatStatus::NotInDatabase => info!(args, "{}: not in database", rom.display()),
+        }
+    }
+    Ok(names)
+}
+
+/// Print the --inventory table of the roms, exporting them too with --export-csv
+/// or --export-json
+pub fn print_inventory(args: &Args, roms: &[PathBuf]) -> Result<(), ConversionError> {
+    let mut rows = Vec::with_capacity(roms.len());
+    let mut json_entries = Vec::new();
+    let mut errors = 0;
+    for rom in roms {
+        let exported = read_export(args, rom).and_then(|metadata| match metadata {
+            Some(metadata) => export_rom(args, rom, &metadata, &mut json_entries),
+            None => Ok(()),
+        });
+        if let Err(error) = exported {
+            error!("Unable to export {}: {}", rom.display(), error);
+        }
+        match inventory::Row::read(rom) {
+            Ok(row) => rows.push(row),
+            Err(error) => {
+                error!("Error reading {}: {}", rom.display(), error);
+                errors += 1;
+            }
+        }
+    }
+    write_json_export(args, &json_entries)?;
+
+    if let Some(column) = args.sort_by {
+        inventory::sort(&mut rows, column);
+    }
+    let mut stdout = io::stdout().lock();
+    match args.output_format {
+        OutputFormat::Text => inventory::write_table(&mut stdout, &rows)?,
+        OutputFormat::Json => {
+            for row in &rows {
+                writeln!(stdout, "{}", row.json())?;
+            }
+        }
+        OutputFormat::Csv => inventory::write_csv(&mut stdout, &rows)?,
+    }
+    if errors > 0 {
+        return Err(ConversionError::BatchFailed { errors });
+    }
+    Ok(())
+}
+
+/// Print the groups of roms with the same big-endian CRC32, for --find-duplicates
+pub fn find_duplicates(args: &Args, roms: &[PathBuf]) -> Result<(), ConversionError> {
+    let mut by_crc: HashMap<u32, Vec<PathBuf>> = HashMap::new();
+    let mut errors = 0;
+    for rom in roms {
+        match big_endian_crc(args, rom) {
+            Ok((_, crc)) => by_crc.entry(crc).or_default().push(rom.clone()),
+            Err(error) => {
+                error!("Error reading {}: {}", rom.display(), error);
+                errors += 1;
+            }
+        }
+    }
+
+    // In the order the first rom of each group was found
+    let mut groups: Vec<_> = by_crc
+        .into_iter()
+        .filter(|(_, roms)| roms.len() > 1)
+        .collect();
+    groups.sort_by_key(|(_, group)| roms.iter().position(|rom| rom == &group[0]));
+    for (crc, group) in &groups {
+        println!("CRC32 {:08X}:", crc);
+        for rom in group {
+            println!("  {}", rom.display());
+        }
+    }
+    if groups.is_empty() {
+        info!(args, "No duplicates among {} roms", roms.len());
+    } else {
+        info!(args, "{} groups of duplicates", groups.len());
+    }
+    if errors > 0 {
+        return Err(ConversionError::BatchFailed { errors });
+    }
+    Ok(())
+}
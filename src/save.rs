@@ -0,0 +1,54 @@
+use core::fmt;
+
+/// Cartridge save types, as flash cart menus ask for them
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum SaveType {
+    Eeprom4K,
+    Eeprom16K,
+    Sram32K,
+    FlashRam,
+}
+
+impl fmt::Display for SaveType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SaveType::Eeprom4K => write!(f, "EEPROM 4K"),
+            SaveType::Eeprom16K => write!(f, "EEPROM 16K"),
+            SaveType::Sram32K => write!(f, "SRAM 32K"),
+            SaveType::FlashRam => write!(f, "FlashRAM"),
+        }
+    }
+}
+
+// Words the save routines of each type leave in a rom, most specific first. A
+// FlashRAM game also addresses the SRAM domain, so that one comes last.
+const SIGNATURES: [(u32, SaveType); 5] = [
+    // FlashRAM silicon ID
+    (0x1111_8001, SaveType::FlashRam),
+    // FlashRAM command register, in KSEG1
+    (0xA801_0000, SaveType::FlashRam),
+    (0x19AE_3100, SaveType::Eeprom16K),
+    (0x11AE_3100, SaveType::Eeprom4K),
+    // Start of the SRAM domain, in KSEG1
+    (0xA800_0000, SaveType::Sram32K),
+];
+
+/// Guess the save type from the words of a (big-endian) rom body.
+///
+/// This is a heuristic, `None` doesn't mean the game can't save.
+pub fn detect_save_type(data: &[u8]) -> Option<SaveType> {
+    let mut found = [false; SIGNATURES.len()];
+    for word in data.chunks_exact(4) {
+        let word = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        if let Some(idx) = SIGNATURES
+            .iter()
+            .position(|(signature, _)| *signature == word)
+        {
+            found[idx] = true;
+        }
+    }
+    SIGNATURES
+        .iter()
+        .zip(found)
+        .find_map(|((_, save_type), found)| found.then_some(*save_type))
+}
@@ -0,0 +1,154 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Sidecar checksum file next to a rom, `<stem>.sfv`
+pub fn sidecar_path(rom: &Path) -> PathBuf {
+    rom.with_extension("sfv")
+}
+
+/// Write `entries` (rom path and CRC32 hex) as an SFV file at `path`.
+///
+/// Names are relative to the SFV file's directory where possible.
+pub fn write_sfv(path: &Path, entries: &[(PathBuf, String)]) -> io::Result<()> {
+    let base = path.parent().unwrap_or(Path::new(""));
+    let mut file = BufWriter::new(File::create(path)?);
+    writeln!(file, "; Generated by n64swap {}", env!("CARGO_PKG_VERSION"))?;
+    for (rom, crc) in entries {
+        let name = match rom.strip_prefix(base) {
+            Ok(relative) if !base.as_os_str().is_empty() => relative,
+            _ => Path::new(rom.file_name().unwrap_or_default()),
+        };
+        writeln!(file, "{} {}", name.display(), crc)?;
+    }
+    file.flush()
+}
+
+/// Sidecar md5sum file next to a rom, `<stem>.md5`
+pub fn md5_sidecar_path(rom: &Path) -> PathBuf {
+    rom.with_extension("md5")
+}
+
+/// Write `entries` (rom path and MD5 hex) at `path` as `md5sum` does, for `md5sum -c`.
+///
+/// Names are relative to the file's directory where possible.
+pub fn write_md5(path: &Path, entries: &[(PathBuf, String)]) -> io::Result<()> {
+    let base = path.parent().unwrap_or(Path::new(""));
+    let mut file = BufWriter::new(File::create(path)?);
+    for (rom, md5) in entries {
+        let name = match rom.strip_prefix(base) {
+            Ok(relative) if !base.as_os_str().is_empty() => relative,
+            _ => Path::new(rom.file_name().unwrap_or_default()),
+        };
+        writeln!(file, "{}  {}", md5, name.display())?;
+    }
+    file.flush()
+}
+
+/// Parse the `name CRC32` entries of an SFV file, skipping comments
+pub fn parse_sfv(content: &str) -> Vec<(String, u32)> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with(';'))
+        .filter_map(|line| {
+            let (name, crc) = line.rsplit_once(|c: char| c.is_ascii_whitespace())?;
+            if crc.len() != 8 {
+                return None;
+            }
+            let crc = u32::from_str_radix(crc, 16).ok()?;
+            Some((name.trim_end().to_string(), crc))
+        })
+        .collect()
+}
+
+/// Parse the `digest  name` entries of an md5sum file, in text or binary (`*name`) mode
+pub fn parse_md5(content: &str) -> Vec<(String, [u8; 16])> {
+    content
+        .lines()
+        .map(str::trim_end)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| {
+            let (digest, name) = line.split_once(|c: char| c.is_ascii_whitespace())?;
+            if digest.len() != 32 {
+                return None;
+            }
+            let mut bytes = [0; 16];
+            for (byte, pair) in bytes.iter_mut().zip(digest.as_bytes().chunks_exact(2)) {
+                *byte = u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok()?;
+            }
+            let name = name.trim_start();
+            let name = name.strip_prefix('*').unwrap_or(name);
+            Some((name.to_string(), bytes))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_sfv() {
+        let content = "; Generated by n64swap\r\n\
+            ;rom.z64 00000000\r\n\
+            \r\n\
+            Super Game (USA).z64 1FA056E0\r\n\
+            other.v64\t\ta1b2c3d4\r\n\
+            short.z64 1234\r\n\
+            not hex.z64 ZZZZZZZZ\n\
+            last.n64 0BADF00D";
+        assert_eq!(
+            parse_sfv(content),
+            [
+                ("Super Game (USA).z64".to_string(), 0x1FA056E0),
+                ("other.v64".to_string(), 0xA1B2C3D4),
+                ("last.n64".to_string(), 0x0BADF00D),
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_md5() {
+        let digest = "0123456789abcdef0123456789ABCDEF";
+        let bytes = [
+            0x01, 0x23, 0x45, 0x67, 0x89, 0xAB, 0xCD, 0xEF, 0x01, 0x23, 0x45, 0x67, 0x89, 0xAB,
+            0xCD, 0xEF,
+        ];
+        let content = format!(
+            "# comment\r\n{d}  Super Game (USA).z64\r\n{d} *binary rom.v64\r\nshort  rom.z64\n",
+            d = digest
+        );
+        assert_eq!(
+            parse_md5(&content),
+            [
+                ("Super Game (USA).z64".to_string(), bytes),
+                ("binary rom.v64".to_string(), bytes),
+            ]
+        );
+    }
+
+    #[test]
+    fn written_sfv_parses_back() {
+        let dir = std::env::temp_dir().join(format!("n64swap-{}-sfv", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir(&dir).unwrap();
+        let path = dir.join("roms.sfv");
+        write_sfv(
+            &path,
+            &[
+                (dir.join("Super Game (USA).z64"), "1FA056E0".to_string()),
+                (PathBuf::from("elsewhere/other.v64"), "A1B2C3D4".to_string()),
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            parse_sfv(&std::fs::read_to_string(&path).unwrap()),
+            [
+                ("Super Game (USA).z64".to_string(), 0x1FA056E0),
+                ("other.v64".to_string(), 0xA1B2C3D4),
+            ]
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,175 @@
+use crate::{swapper, RomType};
+
+// Where each output byte of a 4-byte word comes from
+fn word_order(src_type: RomType, dst_type: RomType) -> Option<[u8; 4]> {
+    match (src_type, dst_type) {
+        (RomType::BigEndian, RomType::ByteSwap)
+        | (RomType::ByteSwap, RomType::BigEndian)
+        | (RomType::LittleEndian, RomType::WordSwap)
+        | (RomType::WordSwap, RomType::LittleEndian) => Some([1, 0, 3, 2]),
+        (RomType::BigEndian, RomType::LittleEndian)
+        | (RomType::LittleEndian, RomType::BigEndian)
+        | (RomType::ByteSwap, RomType::WordSwap)
+        | (RomType::WordSwap, RomType::ByteSwap) => Some([3, 2, 1, 0]),
+        (RomType::ByteSwap, RomType::LittleEndian)
+        | (RomType::LittleEndian, RomType::ByteSwap)
+        | (RomType::BigEndian, RomType::WordSwap)
+        | (RomType::WordSwap, RomType::BigEndian) => Some([2, 3, 0, 1]),
+        _ => None,
+    }
+}
+
+// The word order repeated over a 32-byte shuffle mask
+fn shuffle_mask(order: [u8; 4]) -> [u8; 32] {
+    let mut mask = [0; 32];
+    for (idx, byte) in mask.iter_mut().enumerate() {
+        // Both AVX2 lanes index into their own 16 bytes
+        *byte = (idx % 16 / 4 * 4) as u8 + order[idx % 4];
+    }
+    mask
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn swap_avx2(buf: &mut [u8], mask: &[u8; 32]) -> usize {
+    use std::arch::x86_64::{_mm256_loadu_si256, _mm256_shuffle_epi8, _mm256_storeu_si256};
+
+    let mask = _mm256_loadu_si256(mask.as_ptr().cast());
+    let mut done = 0;
+    for chunk in buf.chunks_exact_mut(32) {
+        let bytes = _mm256_loadu_si256(chunk.as_ptr().cast());
+        _mm256_storeu_si256(chunk.as_mut_ptr().cast(), _mm256_shuffle_epi8(bytes, mask));
+        done += 32;
+    }
+    done
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "ssse3")]
+unsafe fn swap_ssse3(buf: &mut [u8], mask: &[u8; 32]) -> usize {
+    use std::arch::x86_64::{_mm_loadu_si128, _mm_shuffle_epi8, _mm_storeu_si128};
+
+    let mask = _mm_loadu_si128(mask.as_ptr().cast());
+    let mut done = 0;
+    for chunk in buf.chunks_exact_mut(16) {
+        let bytes = _mm_loadu_si128(chunk.as_ptr().cast());
+        _mm_storeu_si128(chunk.as_mut_ptr().cast(), _mm_shuffle_epi8(bytes, mask));
+        done += 16;
+    }
+    done
+}
+
+/// Convert a buffer of 4-byte words from one rom type to another.
+///
+/// Uses AVX2 or SSSE3 when the CPU has them, the scalar `swapper` otherwise and for
+/// the remainder. A trailing partial word is left untouched.
+pub fn swapper_simd(buf: &mut [u8], src_type: RomType, dst_type: RomType) {
+    let Some(order) = word_order(src_type, dst_type) else {
+        return;
+    };
+
+    #[allow(unused_mut)]
+    let mut done = 0;
+    #[cfg(target_arch = "x86_64")]
+    {
+        let mask = shuffle_mask(order);
+        if is_x86_feature_detected!("avx2") {
+            // SAFETY: the CPU supports AVX2, checked above
+            done = unsafe { swap_avx2(buf, &mask) };
+        } else if is_x86_feature_detected!("ssse3") {
+            // SAFETY: the CPU supports SSSE3, checked above
+            done = unsafe { swap_ssse3(buf, &mask) };
+        }
+    }
+
+    for chunk in buf[done..].chunks_exact_mut(4) {
+        if let Ok(chunk) = <&mut [u8; 4]>::try_from(chunk) {
+            swapper(chunk, src_type, dst_type);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TYPES: [RomType; 4] = [
+        RomType::BigEndian,
+        RomType::ByteSwap,
+        RomType::LittleEndian,
+        RomType::WordSwap,
+    ];
+    // Around the 16 and 32 byte vectors, with trailing partial words
+    const LENGTHS: [usize; 15] = [0, 3, 4, 12, 15, 16, 20, 31, 32, 36, 47, 48, 64, 100, 1030];
+
+    fn data(len: usize) -> Vec<u8> {
+        (0..len).map(|idx| (idx * 7 + 3) as u8).collect()
+    }
+
+    // What the scalar `swapper` makes of `buf`, a trailing partial word left as is
+    fn scalar(mut buf: Vec<u8>, src_type: RomType, dst_type: RomType) -> Vec<u8> {
+        for chunk in buf.chunks_exact_mut(4) {
+            swapper(chunk.try_into().unwrap(), src_type, dst_type);
+        }
+        buf
+    }
+
+    #[test]
+    fn matches_the_scalar_swapper() {
+        for src_type in TYPES {
+            for dst_type in TYPES {
+                for len in LENGTHS {
+                    let mut buf = data(len);
+                    swapper_simd(&mut buf, src_type, dst_type);
+                    assert_eq!(
+                        buf,
+                        scalar(data(len), src_type, dst_type),
+                        "{:?} -> {:?}, {} bytes",
+                        src_type,
+                        dst_type,
+                        len
+                    );
+                }
+            }
+        }
+    }
+
+    // swapper_simd only takes the best path the CPU has, so try each one it has
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn every_x86_path_matches_the_scalar_swapper() {
+        type Swap = unsafe fn(&mut [u8], &[u8; 32]) -> usize;
+        let mut paths: Vec<(Swap, usize)> = Vec::new();
+        if is_x86_feature_detected!("avx2") {
+            paths.push((swap_avx2, 32));
+        }
+        if is_x86_feature_detected!("ssse3") {
+            paths.push((swap_ssse3, 16));
+        }
+        for (swap, width) in paths {
+            for src_type in TYPES {
+                for dst_type in TYPES {
+                    let Some(order) = word_order(src_type, dst_type) else {
+                        continue;
+                    };
+                    for len in LENGTHS {
+                        let mut buf = data(len);
+                        // SAFETY: the CPU supports the instructions, checked above
+                        let done = unsafe { swap(&mut buf, &shuffle_mask(order)) };
+                        assert_eq!(done, len / width * width);
+                        assert_eq!(
+                            buf[..done],
+                            scalar(data(len), src_type, dst_type)[..done],
+                            "{:?} -> {:?}, {} bytes, {} byte vectors",
+                            src_type,
+                            dst_type,
+                            len,
+                            width
+                        );
+                        assert_eq!(buf[done..], data(len)[done..]);
+                    }
+                }
+            }
+        }
+    }
+}
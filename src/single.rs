@@ -0,0 +1,244 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, BufReader, BufWriter, Cursor, Read};
+use std::path::{Path, PathBuf};
+
+use n64swap::{compare_roms, guess_path_type, ConversionError, RomType, HEADER_SIZE};
+
+use crate::hash::{HashWriter, Hashes};
+use crate::input::{check_size, open_input, parse_header, source_type, verify_input_crc};
+use crate::output::TempOutput;
+use crate::report::{
+    check_dat, export_rom, is_rejected, md5_entry, print_digests, print_identify, read_export,
+    read_identity, read_padding, sfv_entry, validate_checksums, write_json_export,
+    write_md5_entries, write_sfv_entries,
+};
+use crate::template;
+use crate::write::{
+    archive_output, backup_file, convert_with_progress, plan_output, preserve_attributes,
+    replaced_files, unique_path, write_output, Job,
+};
+use crate::zip;
+use crate::{Args, STDIO};
+
+/// Convert a single rom, or compare or identify it
+pub fn convert_single(args: &Args) -> Result<(), ConversionError> {
+    let filename = args.filename().to_path_buf();
+    let rejected = validate_checksums(args)?;
+    if is_rejected(&rejected, &filename) {
+        return Err(ConversionError::ValidationFailed(filename));
+    }
+    check_dat(args, std::slice::from_ref(&filename))?;
+    if !args.reads_stdin() {
+        if let Some(metadata) = read_export(args, &filename)? {
+            let mut json_entries = Vec::new();
+            export_rom(args, &filename, &metadata, &mut json_entries)?;
+            write_json_export(args, &json_entries)?;
+        }
+    }
+
+    // Input file
+    let mut input_metadata = None;
+    let mut buf: Box<dyn Read> = if args.reads_stdin() {
+        Box::new(io::stdin().lock())
+    } else {
+        let file = open_input(&filename)?;
+        input_metadata = Some(file.metadata()?);
+        Box::new(BufReader::new(file))
+    };
+    let mut size = input_metadata.as_ref().map(fs::Metadata::len);
+    // What's read, an entry of a --zip-input archive is named after it
+    let mut source = filename.clone();
+    if args.zip_input {
+        let mut archive = Vec::new();
+        buf.read_to_end(&mut archive)?;
+        let (name, rom) = zip::read_rom(&archive).map_err(|error| {
+            io::Error::new(error.kind(), format!("{}: {}", filename.display(), error))
+        })?;
+        source = filename.join(name);
+        size = Some(rom.len() as u64);
+        buf = Box::new(Cursor::new(rom));
+    }
+    if let Some(other) = &args.compare {
+        let mut other_file = BufReader::new(open_input(other)?);
+        if let Some(difference) = compare_roms(&mut buf, &mut other_file)? {
+            return Err(ConversionError::RomsDiffer(difference));
+        }
+        success!(
+            args,
+            "{} and {} hold the same rom",
+            filename.display(),
+            other.display()
+        );
+        return Ok(());
+    }
+    // Let's read the header
+    let mut header = Vec::with_capacity(HEADER_SIZE);
+    buf.by_ref()
+        .take(HEADER_SIZE as u64)
+        .read_to_end(&mut header)?;
+    let (filetype, repaired) = source_type(args, &source, &mut header)?;
+
+    if args.identify {
+        let mut identity = read_identity(args, &mut Cursor::new(header).chain(buf), size)?;
+        if input_metadata.is_some() && !args.zip_input {
+            identity.padding = read_padding(&filename);
+        }
+        print_identify(args, &source, &identity);
+        return Ok(());
+    }
+
+    // Output file
+    let outfiletype = args.romtype.unwrap_or_else(|| { // If specified, use that
+        args.destination_filename
+            .as_deref() // Otherwise borrow the destination filename
+            .and_then(guess_path_type) // Identify the type based on extension
+            .unwrap_or(RomType::BigEndian) // Or default to BigEndian
+    });
+
+    if let Some(size) = size {
+        check_size(args, &filename, size)?;
+    }
+    if args.verify_crc {
+        verify_input_crc(args, &filename)?;
+    }
+
+    let mut inbuf = Cursor::new(header).chain(buf);
+    if args.writes_stdout() {
+        // A rom that's already the right type is passed through untouched
+        let job = Job {
+            filename: &filename,
+            outfilename: Path::new(STDIO),
+            filetype,
+            outfiletype,
+            size,
+            repaired,
+            progress: true,
+        };
+        if args.dry_run {
+            eprintln!(
+                "Would convert {} ({}) -> stdout ({})",
+                filename.display(),
+                filetype.name(),
+                outfiletype.name()
+            );
+            return Ok(());
+        }
+        let outbuf = BufWriter::with_capacity(args.buffer_size, io::stdout().lock());
+        let outbuf = HashWriter::new(outbuf, Hashes::new(&args.computed_hashes()));
+        let (_, hashes) = convert_with_progress(args, &job, &mut inbuf, outbuf)?.into_parts();
+        // stdout is taken by the rom itself
+        for (algorithm, digest) in hashes.finish() {
+            eprintln!("{}: {}", algorithm.label(), digest);
+        }
+        return Ok(());
+    }
+
+    if filetype == outfiletype && !repaired {
+        return Err(ConversionError::SameType(outfiletype));
+    }
+
+    let outfilename = args.destination_filename.clone().unwrap_or_else(|| { // If specified, use that
+        let ext = &outfiletype.get_file_ext()[1..]; // Otherwise, the standard extension for the output type
+        let name = match filename.extension() {
+            // Swap out a 3-letter extension
+            Some(old) if old.to_string_lossy().chars().count() == 3 => filename.with_extension(ext),
+            // Or add one
+            _ => {
+                let mut name = filename.clone().into_os_string();
+                name.push(".");
+                name.push(ext);
+                PathBuf::from(name)
+            }
+        };
+        match &args.output_dir {
+            // Move it to the output directory if there is one
+            Some(dir) => dir.join(name.file_name().unwrap_or_default()),
+            None => name,
+        }
+    });
+    // Only the directory of the usual name is kept
+    let outfilename = if args.rename_to_title {
+        let header = parse_header(inbuf.get_ref().0.get_ref());
+        let fields = template::Fields {
+            stem: &filename.file_stem().unwrap_or_default().to_string_lossy(),
+            outfiletype,
+            header: header.as_ref(),
+            os: args.target_os,
+        };
+        for placeholder in template::unresolved(template::TITLE_TEMPLATE, &fields) {
+            warning!(
+                "{{{}}} is unknown for {}, it's left empty",
+                placeholder,
+                filename.display()
+            );
+        }
+        let name = template::render(template::TITLE_TEMPLATE, &fields);
+        let path = Path::new(&outfilename).with_file_name(name);
+        unique_path(args, path, &filename, &mut HashSet::new())
+    } else {
+        outfilename
+    };
+    // --zip-output holds the file under its usual name
+    let entry_name = outfilename
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .into_owned();
+    let outfilename = args.zip_output.clone().unwrap_or(outfilename);
+
+    if args.in_place && filename != outfilename && !args.force && outfilename.exists() {
+        return Err(ConversionError::OutputExists(outfilename));
+    }
+    if !args.in_place && filename == outfilename {
+        return Err(ConversionError::InputOutputSameFile(outfilename));
+    }
+
+    let job = Job {
+        filename: &filename,
+        outfilename: &outfilename,
+        filetype,
+        outfiletype,
+        size,
+        repaired,
+        progress: true,
+    };
+    // In-place conversions replace the input on purpose
+    let overwrite = args.force || args.in_place;
+    if args.dry_run {
+        return plan_output(args, &job, &outfilename, overwrite);
+    }
+
+    // Back up before writing anything, a failed conversion leaves the backup intact
+    let mut kept = false;
+    if overwrite {
+        for replaced in replaced_files(args, &job, &outfilename) {
+            kept |= backup_file(args, &replaced)? && replaced == filename;
+        }
+    }
+
+    // Written next to the output, then renamed over it once complete
+    let mut output = TempOutput::create(&outfilename, overwrite)?;
+    let digests = write_output(args, &job, output.file(), &mut inbuf)?;
+    if args.zip_output.is_some() {
+        archive_output(output.file(), &entry_name)?;
+    }
+    if let Some(metadata) = &input_metadata {
+        preserve_attributes(args, metadata, output.file())?;
+    }
+    output.persist()?;
+
+    // The extension changed, so the original is still around
+    if args.in_place && filename != outfilename && !kept {
+        fs::remove_file(&filename)?;
+    }
+    print_digests(args, &digests);
+    if let Some(entry) = sfv_entry(args, &outfilename, &digests) {
+        write_sfv_entries(args, &[entry])?;
+    }
+    if let Some(entry) = md5_entry(args, &outfilename, &digests) {
+        write_md5_entries(args, &[entry])?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,208 @@
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use crate::{file_size_is_valid, identify_header, normalize_path_windows, ConversionError};
+
+// Reject a chunk size that would cut words in half
+fn check_chunk_size(chunk_size: u64) -> Result<(), ConversionError> {
+    if chunk_size == 0 || !file_size_is_valid(chunk_size) {
+        return Err(ConversionError::InvalidChunkSize(chunk_size));
+    }
+    Ok(())
+}
+
+/// The chunk files `split_rom` writes for `src`, `<stem>_00.<ext>`, `<stem>_01.<ext>`...
+/// in `output_dir`. The numbers get more digits past 100 chunks.
+pub fn split_paths(
+    src: &Path,
+    chunk_size: u64,
+    output_dir: &Path,
+) -> Result<Vec<PathBuf>, ConversionError> {
+    check_chunk_size(chunk_size)?;
+    let size = normalize_path_windows(src).metadata()?.len();
+    let count = size.div_ceil(chunk_size);
+    let digits = count.saturating_sub(1).to_string().len().max(2);
+    let stem = src.file_stem().unwrap_or_default().to_string_lossy();
+    let ext = src
+        .extension()
+        .map(|ext| format!(".{}", ext.to_string_lossy()))
+        .unwrap_or_default();
+    Ok((0..count)
+        .map(|idx| output_dir.join(format!("{}_{:0digits$}{}", stem, idx, ext, digits = digits)))
+        .collect())
+}
+
+/// Split the rom at `src` into files of `chunk_size` bytes in `output_dir`, named
+/// like `split_paths` says, and return their paths. The bytes are copied as they
+/// are, in the rom's own byte order, and the last chunk is padded with 0x00.
+///
+/// Returns `InvalidChunkSize` unless `chunk_size` is a non-zero multiple of 4.
+pub fn split_rom(
+    src: &Path,
+    chunk_size: u64,
+    output_dir: &Path,
+) -> Result<Vec<PathBuf>, ConversionError> {
+    let paths = split_paths(src, chunk_size, output_dir)?;
+    let mut reader = BufReader::new(File::open(normalize_path_windows(src))?);
+    for path in &paths {
+        let mut writer = BufWriter::new(File::create(normalize_path_windows(path))?);
+        let copied = io::copy(&mut reader.by_ref().take(chunk_size), &mut writer)?;
+        io::copy(&mut io::repeat(0).take(chunk_size - copied), &mut writer)?;
+        writer.flush()?;
+    }
+    Ok(paths)
+}
+
+/// Join the chunk files of a rom, in order, into `dst`, the reverse of `split_rom`.
+///
+/// The first chunk has to start with a rom's magic. The others are copied as they
+/// are, but one that starts with the magic of another byte order fails with
+/// `ChunkTypeMismatch`, as it can't be part of the same rom. Padding `split_rom`
+/// added to the last chunk stays.
+pub fn merge_roms(chunks: &[PathBuf], dst: &Path) -> Result<(), ConversionError> {
+    if chunks.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "no chunks to merge").into());
+    }
+    let mut rom_type = None;
+    for path in chunks {
+        let mut start = Vec::with_capacity(4);
+        File::open(normalize_path_windows(path))?
+            .take(4)
+            .read_to_end(&mut start)?;
+        let mut magic = [0; 4];
+        magic[..start.len()].copy_from_slice(&start);
+        let found = (start.len() == magic.len())
+            .then(|| identify_header(&magic))
+            .flatten();
+        match (rom_type, found) {
+            (None, Some(found)) => rom_type = Some(found),
+            (None, None) => return Err(ConversionError::UnrecognizedHeader(magic)),
+            (Some(expected), Some(found)) if found != expected => {
+                return Err(ConversionError::ChunkTypeMismatch {
+                    path: path.clone(),
+                    expected,
+                    found,
+                })
+            }
+            _ => {}
+        }
+    }
+
+    let mut writer = BufWriter::new(File::create(normalize_path_windows(dst))?);
+    for path in chunks {
+        let mut reader = File::open(normalize_path_windows(path))?;
+        io::copy(&mut reader, &mut writer)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    // An empty directory of its own for each test
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("n64swap-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir(&dir).unwrap();
+        dir
+    }
+
+    fn rom(len: usize) -> Vec<u8> {
+        let mut rom = crate::BIG_ENDIAN.to_vec();
+        rom.extend((4..len).map(|idx| idx as u8));
+        rom
+    }
+
+    #[test]
+    fn rejects_bad_chunk_sizes() {
+        let dir = test_dir("split-sizes");
+        let src = dir.join("rom.z64");
+        fs::write(&src, rom(16)).unwrap();
+        for chunk_size in [0, 6] {
+            assert!(matches!(
+                split_rom(&src, chunk_size, &dir),
+                Err(ConversionError::InvalidChunkSize(size)) if size == chunk_size
+            ));
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn pads_the_last_chunk() {
+        let dir = test_dir("split-pad");
+        let src = dir.join("rom.z64");
+        fs::write(&src, rom(20)).unwrap();
+        let paths = split_rom(&src, 8, &dir).unwrap();
+        assert_eq!(
+            paths,
+            ["rom_00.z64", "rom_01.z64", "rom_02.z64"].map(|name| dir.join(name))
+        );
+        assert_eq!(fs::read(&paths[0]).unwrap(), rom(20)[..8]);
+        assert_eq!(fs::read(&paths[1]).unwrap(), rom(20)[8..16]);
+        assert_eq!(fs::read(&paths[2]).unwrap(), [16, 17, 18, 19, 0, 0, 0, 0]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn names_get_wider_past_100_chunks() {
+        let dir = test_dir("split-names");
+        let src = dir.join("rom");
+        fs::write(&src, rom(100 * 4)).unwrap();
+        let paths = split_paths(&src, 4, &dir).unwrap();
+        assert_eq!(paths.len(), 100);
+        assert_eq!(paths[99], dir.join("rom_99"));
+
+        fs::write(&src, rom(101 * 4)).unwrap();
+        let paths = split_paths(&src, 4, &dir).unwrap();
+        assert_eq!(paths[0], dir.join("rom_000"));
+        assert_eq!(paths[100], dir.join("rom_100"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn merge_undoes_split() {
+        let dir = test_dir("merge");
+        let src = dir.join("rom.z64");
+        fs::write(&src, rom(24)).unwrap();
+        let paths = split_rom(&src, 8, &dir).unwrap();
+        let dst = dir.join("merged.z64");
+        merge_roms(&paths, &dst).unwrap();
+        assert_eq!(fs::read(&dst).unwrap(), rom(24));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn merge_rejects_bad_chunks() {
+        let dir = test_dir("merge-bad");
+        let big_endian = dir.join("rom_00.z64");
+        let byte_swap = dir.join("rom_01.z64");
+        let headerless = dir.join("rom_02.z64");
+        fs::write(&big_endian, rom(8)).unwrap();
+        fs::write(&byte_swap, crate::BYTE_SWAP).unwrap();
+        fs::write(&headerless, [1, 2, 3, 4]).unwrap();
+        let dst = dir.join("merged.z64");
+
+        assert!(matches!(
+            merge_roms(&[big_endian.clone(), byte_swap.clone()], &dst),
+            Err(ConversionError::ChunkTypeMismatch { path, expected, found })
+                if path == byte_swap
+                    && expected == crate::RomType::BigEndian
+                    && found == crate::RomType::ByteSwap
+        ));
+        assert!(matches!(
+            merge_roms(&[headerless.clone(), big_endian], &dst),
+            Err(ConversionError::UnrecognizedHeader([1, 2, 3, 4]))
+        ));
+        assert!(matches!(
+            merge_roms(&[], &dst),
+            Err(ConversionError::IoError(error)) if error.kind() == io::ErrorKind::InvalidInput
+        ));
+        assert!(!dst.exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
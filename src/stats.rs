@@ -0,0 +1,86 @@
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::Instant;
+
+use n64swap::format_size;
+
+const MIB: f64 = 1024.0 * 1024.0;
+
+/// Counters of a batch run, shared by the worker threads
+pub struct BatchStats {
+    scanned: usize,
+    converted: AtomicUsize,
+    skipped: AtomicUsize,
+    errors: AtomicUsize,
+    bytes_read: AtomicU64,
+    bytes_written: AtomicU64,
+    start: Instant,
+}
+
+impl BatchStats {
+    pub fn new(scanned: usize) -> BatchStats {
+        BatchStats {
+            scanned,
+            converted: AtomicUsize::new(0),
+            skipped: AtomicUsize::new(0),
+            errors: AtomicUsize::new(0),
+            bytes_read: AtomicU64::new(0),
+            bytes_written: AtomicU64::new(0),
+            start: Instant::now(),
+        }
+    }
+
+    pub fn converted(&self) {
+        self.converted.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn skipped(&self) {
+        self.skipped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn failed(&self) {
+        self.errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Count the bytes of one conversion
+    pub fn transferred(&self, read: u64, written: u64) {
+        self.bytes_read.fetch_add(read, Ordering::Relaxed);
+        self.bytes_written.fetch_add(written, Ordering::Relaxed);
+    }
+
+    pub fn converted_count(&self) -> usize {
+        self.converted.load(Ordering::Relaxed)
+    }
+
+    pub fn skipped_count(&self) -> usize {
+        self.skipped.load(Ordering::Relaxed)
+    }
+
+    pub fn error_count(&self) -> usize {
+        self.errors.load(Ordering::Relaxed)
+    }
+
+    /// Print the summary table on stderr
+    pub fn print_summary(&self) {
+        let read = self.bytes_read.load(Ordering::Relaxed);
+        let written = self.bytes_written.load(Ordering::Relaxed);
+        let elapsed = self.start.elapsed().as_secs_f64();
+        let throughput = if elapsed > 0.0 {
+            read as f64 / MIB / elapsed
+        } else {
+            0.0
+        };
+        let rows = [
+            ("Files scanned", self.scanned.to_string()),
+            ("Converted", self.converted_count().to_string()),
+            ("Skipped", self.skipped_count().to_string()),
+            ("Errors", self.error_count().to_string()),
+            ("Bytes read", format_size(read)),
+            ("Bytes written", format_size(written)),
+            ("Elapsed", format!("{:.2} s", elapsed)),
+            ("Throughput", format!("{:.2} MiB/s", throughput)),
+        ];
+        for (label, value) in rows {
+            eprintln!("{:<14} {:>12}", format!("{}:", label), value);
+        }
+    }
+}
@@ -0,0 +1,277 @@
+use clap::ValueEnum;
+use n64swap::{CountryCode, RomHeader, RomType};
+
+/// The output name `--rename-to-title` uses
+pub const TITLE_TEMPLATE: &str = "{title} ({region}).{ext}";
+
+/// Longest title `sanitize_filename` leaves, in bytes, well under the 255 most
+/// filesystems allow for the whole name
+const MAX_TITLE_LEN: usize = 240;
+
+/// Filesystems a title is made safe for
+#[derive(Debug, PartialEq, Copy, Clone, ValueEnum)]
+pub enum TargetOs {
+    /// No \ / : * ? " < > |
+    Windows,
+    /// No /
+    Unix,
+    /// Safe on both
+    Any,
+}
+
+/// Placeholders `--output-template` understands
+const PLACEHOLDERS: [&str; 9] = [
+    "stem", "ext", "type", "region", "title", "crc", "crc1", "crc2", "rev",
+];
+
+/// What a rom's output name can be built from
+pub struct Fields<'a> {
+    /// Input filename without the extension
+    pub stem: &'a str,
+    pub outfiletype: RomType,
+    /// `None` when the header couldn't be read
+    pub header: Option<&'a RomHeader>,
+    /// Where the title has to be a valid filename
+    pub os: TargetOs,
+}
+
+enum Part<'a> {
+    Text(&'a str),
+    Placeholder(&'a str),
+}
+
+// Split a template into text and placeholders, {{ and }} being literal braces
+fn parts(template: &str) -> Result<Vec<Part<'_>>, String> {
+    let mut parts = Vec::new();
+    let mut rest = template;
+    while let Some(idx) = rest.find(['{', '}']) {
+        if idx > 0 {
+            parts.push(Part::Text(&rest[..idx]));
+        }
+        rest = &rest[idx..];
+        if rest.starts_with("{{") || rest.starts_with("}}") {
+            parts.push(Part::Text(&rest[..1]));
+            rest = &rest[2..];
+        } else if rest.starts_with('}') {
+            return Err("unmatched '}'".to_string());
+        } else {
+            let end = rest.find('}').ok_or("unterminated placeholder")?;
+            let name = &rest[1..end];
+            if !PLACEHOLDERS.contains(&name) {
+                return Err(format!(
+                    "unknown placeholder {{{}}}, expected one of {{{}}}",
+                    name,
+                    PLACEHOLDERS.join("}, {")
+                ));
+            }
+            parts.push(Part::Placeholder(name));
+            rest = &rest[end + 1..];
+        }
+    }
+    if !rest.is_empty() {
+        parts.push(Part::Text(rest));
+    }
+    Ok(parts)
+}
+
+/// Check a template up front, so a bad one fails before converting anything
+pub fn validate(template: &str) -> Result<(), String> {
+    parts(template).map(|_| ())
+}
+
+/// Make a title usable as a filename on `os`: characters it rejects become `_`,
+/// runs of spaces become one, leading and trailing spaces and periods are dropped
+/// and it's cut to `MAX_TITLE_LEN` bytes
+pub fn sanitize_filename(title: &str, os: TargetOs) -> String {
+    let mut name = String::with_capacity(title.len());
+    for c in title.chars() {
+        let rejected = match c {
+            '/' | '\0' => true,
+            '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => os != TargetOs::Unix,
+            c => c.is_control() && os != TargetOs::Unix,
+        };
+        if rejected {
+            name.push('_');
+        } else if c != ' ' || !name.ends_with(' ') {
+            name.push(c);
+        }
+    }
+
+    let mut name = name.trim_matches([' ', '.']).to_string();
+    if name.len() > MAX_TITLE_LEN {
+        let mut end = MAX_TITLE_LEN;
+        while !name.is_char_boundary(end) {
+            end -= 1;
+        }
+        name.truncate(end);
+        name.truncate(name.trim_end_matches([' ', '.']).len());
+    }
+    name
+}
+
+// The region name, `None` without a header or for a country code it doesn't know
+fn region<'a>(fields: &Fields<'a>) -> Option<&'a str> {
+    match &fields.header?.country_code {
+        CountryCode::Unknown(_) => None,
+        country => Some(country.name()),
+    }
+}
+
+fn resolve(name: &str, fields: &Fields) -> String {
+    let header = fields.header;
+    match name {
+        // Only from the header, left empty without one
+        "crc1" => header.map_or_else(String::new, |header| format!("{:08X}", header.crc1)),
+        "crc2" => header.map_or_else(String::new, |header| format!("{:08X}", header.crc2)),
+        "rev" => header.map_or_else(String::new, |header| header.version.to_string()),
+        "ext" | "type" => fields.outfiletype.get_file_ext()[1..].to_string(),
+        "region" => region(fields).unwrap_or_default().to_string(),
+        "crc" => header.map_or_else(
+            || "00000000".to_string(),
+            |header| format!("{:08X}", header.crc1),
+        ),
+        "title" => match header.map(|header| sanitize_filename(&header.title(), fields.os)) {
+            Some(title) if !title.is_empty() => title,
+            _ => fields.stem.to_string(),
+        },
+        _ => fields.stem.to_string(),
+    }
+}
+
+/// The placeholders of a template that `render` leaves empty for this rom
+pub fn unresolved<'a>(template: &'a str, fields: &Fields) -> Vec<&'a str> {
+    parts(template)
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|part| match part {
+            Part::Placeholder(name @ ("crc1" | "crc2" | "rev")) if fields.header.is_none() => {
+                Some(name)
+            }
+            Part::Placeholder(name @ "region") if region(fields).is_none() => Some(name),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Fill in a template, `validate` should have accepted it.
+///
+/// Values that aren't available fall back to the stem (for the title) or a
+/// placeholder value, `{crc1}`, `{crc2}`, `{rev}` and an unknown `{region}` are
+/// left empty.
+pub fn render(template: &str, fields: &Fields) -> String {
+    let mut name = String::new();
+    for part in parts(template).unwrap_or_default() {
+        match part {
+            Part::Text(text) => name.push_str(text),
+            Part::Placeholder(placeholder) => name.push_str(&resolve(placeholder, fields)),
+        }
+    }
+    name
+}
+
+#[cfg(test)]
+mod tests {
+    use n64swap::HEADER_SIZE;
+
+    use super::*;
+
+    fn header(country: u8) -> RomHeader {
+        let mut bytes = [0; HEADER_SIZE];
+        bytes[..4].copy_from_slice(&[0x80, 0x37, 0x12, 0x40]);
+        bytes[0x20..0x2A].copy_from_slice(b"SUPER GAME");
+        bytes[0x3E] = country;
+        RomHeader::parse(&bytes).unwrap()
+    }
+
+    fn fields(header: Option<&RomHeader>) -> Fields<'_> {
+        Fields {
+            stem: "game",
+            outfiletype: RomType::ByteSwap,
+            header,
+            os: TargetOs::Any,
+        }
+    }
+
+    #[test]
+    fn type_is_the_extension() {
+        assert_eq!(render("{stem}.{type}", &fields(None)), "game.v64");
+        assert_eq!(
+            render("{type}", &fields(None)),
+            render("{ext}", &fields(None))
+        );
+    }
+
+    #[test]
+    fn unknown_region_is_empty() {
+        let usa = header(b'E');
+        assert_eq!(
+            render(TITLE_TEMPLATE, &fields(Some(&usa))),
+            "SUPER GAME (USA).v64"
+        );
+        assert!(unresolved(TITLE_TEMPLATE, &fields(Some(&usa))).is_empty());
+
+        let unknown = header(b'@');
+        assert_eq!(
+            render("{title} [{region}]", &fields(Some(&unknown))),
+            "SUPER GAME []"
+        );
+        assert_eq!(
+            unresolved(TITLE_TEMPLATE, &fields(Some(&unknown))),
+            ["region"]
+        );
+
+        assert_eq!(
+            unresolved("{region} {crc1} {rev} {crc}", &fields(None)),
+            ["region", "crc1", "rev"]
+        );
+    }
+
+    #[test]
+    fn sanitize_rejects_per_os() {
+        let title = "a\\b:c*d?e\"f<g>h|i/j\u{1}k";
+        assert_eq!(
+            sanitize_filename(title, TargetOs::Windows),
+            "a_b_c_d_e_f_g_h_i_j_k"
+        );
+        assert_eq!(
+            sanitize_filename(title, TargetOs::Any),
+            "a_b_c_d_e_f_g_h_i_j_k"
+        );
+        assert_eq!(
+            sanitize_filename(title, TargetOs::Unix),
+            "a\\b:c*d?e\"f<g>h|i_j\u{1}k"
+        );
+    }
+
+    #[test]
+    fn sanitize_collapses_and_trims() {
+        assert_eq!(
+            sanitize_filename(" . SUPER   GAME  64 .. ", TargetOs::Any),
+            "SUPER GAME 64"
+        );
+        assert_eq!(sanitize_filename("...", TargetOs::Any), "");
+
+        // A title left empty falls back to the stem
+        let mut bytes = [0; HEADER_SIZE];
+        bytes[..4].copy_from_slice(&[0x80, 0x37, 0x12, 0x40]);
+        bytes[0x20..0x23].copy_from_slice(b"...");
+        let dots = RomHeader::parse(&bytes).unwrap();
+        assert_eq!(render("{title}", &fields(Some(&dots))), "game");
+    }
+
+    #[test]
+    fn sanitize_cuts_on_a_char_boundary() {
+        // 241 bytes, the cut at 240 falls inside the last 3-byte character
+        let title = format!("a{}", "\u{3042}".repeat(80));
+        let name = sanitize_filename(&title, TargetOs::Any);
+        assert_eq!(name, format!("a{}", "\u{3042}".repeat(79)));
+        assert_eq!(name.len(), MAX_TITLE_LEN - 2);
+
+        // A cut that leaves a trailing space drops it too
+        let title = format!("{} b", "a".repeat(MAX_TITLE_LEN - 1));
+        assert_eq!(
+            sanitize_filename(&title, TargetOs::Any),
+            "a".repeat(MAX_TITLE_LEN - 1)
+        );
+    }
+}
@@ -0,0 +1,67 @@
+use std::io::{self, Read};
+
+use n64swap::{swapper, RomType};
+
+/// Reader adapter printing every 4-byte chunk read through it on stderr, before
+/// and after swapping, for --debug
+pub struct TraceReader<R: Read> {
+    inner: R,
+    src_type: RomType,
+    dst_type: RomType,
+    offset: u64,
+    // Bytes of a chunk split over several reads
+    pending: [u8; 4],
+    pending_len: usize,
+    // Bytes still to print, 0 when tracing is off
+    remaining: u64,
+}
+
+impl<R: Read> TraceReader<R> {
+    pub fn new(inner: R, src_type: RomType, dst_type: RomType, limit: u64) -> TraceReader<R> {
+        TraceReader {
+            inner,
+            src_type,
+            dst_type,
+            offset: 0,
+            pending: [0; 4],
+            pending_len: 0,
+            remaining: limit,
+        }
+    }
+
+    fn print_chunk(&mut self) {
+        let chunk = self.pending;
+        let mut swapped = chunk;
+        swapper(&mut swapped, self.src_type, self.dst_type);
+        let hex = |bytes: [u8; 4]| bytes.map(|byte| format!("{:02x}", byte)).join(" ");
+        eprintln!("[0x{:08X}] {} -> {}", self.offset, hex(chunk), hex(swapped));
+        self.offset += 4;
+        self.remaining = self.remaining.saturating_sub(4);
+        self.pending_len = 0;
+    }
+}
+
+impl<R: Read> Read for TraceReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        if self.remaining == 0 {
+            return Ok(read);
+        }
+        if read == 0 && self.pending_len > 0 {
+            // The last partial chunk, --pad fills it with zeros
+            self.pending[self.pending_len..].fill(0);
+            self.print_chunk();
+        }
+        for &byte in &buf[..read] {
+            if self.remaining == 0 {
+                break;
+            }
+            self.pending[self.pending_len] = byte;
+            self.pending_len += 1;
+            if self.pending_len == 4 {
+                self.print_chunk();
+            }
+        }
+        Ok(read)
+    }
+}
@@ -0,0 +1,162 @@
+use std::fs::File;
+use std::io::{self, Read, Seek};
+use std::path::Path;
+
+use n64swap::{swapper_simd, RomType, Sha256};
+
+/// Reader adapter hashing everything read through it
+pub struct HashReader<R: Read> {
+    inner: R,
+    hasher: Option<Sha256>,
+    length: u64,
+}
+
+impl<R: Read> HashReader<R> {
+    pub fn new(inner: R, enabled: bool) -> HashReader<R> {
+        HashReader {
+            inner,
+            hasher: enabled.then(Sha256::new),
+            length: 0,
+        }
+    }
+
+    /// The digest and length of everything read, if hashing was enabled
+    pub fn finish(self) -> Option<([u8; 32], u64)> {
+        let length = self.length;
+        self.hasher.map(|hasher| (hasher.finish(), length))
+    }
+}
+
+impl<R: Read> Read for HashReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        if let Some(hasher) = &mut self.hasher {
+            hasher.update(&buf[..read]);
+            self.length += read as u64;
+        }
+        Ok(read)
+    }
+}
+
+/// A converted rom and how to read it back in its source byte order
+pub struct Written<'a> {
+    pub file: &'a mut File,
+    pub src_type: RomType,
+    pub dst_type: RomType,
+    /// Length of the source, the output may be longer when padded
+    pub length: u64,
+    pub buffer_size: usize,
+}
+
+impl Written<'_> {
+    // Hand the output back to `check` a chunk at a time, swapped back into the
+    // source byte order, until `check` returns false
+    fn read_back<F>(&mut self, mut check: F) -> io::Result<()>
+    where
+        F: FnMut(&[u8]) -> io::Result<bool>,
+    {
+        self.file.rewind()?;
+        let mut bytes = vec![0; self.buffer_size];
+        let mut remaining = self.length;
+
+        while remaining > 0 {
+            let take = remaining.min(bytes.len() as u64) as usize;
+            // A padded tail was written as a whole word
+            let aligned = take.next_multiple_of(4);
+            self.file.read_exact(&mut bytes[..aligned])?;
+            swapper_simd(&mut bytes[..aligned], self.dst_type, self.src_type);
+            if !check(&bytes[..take])? {
+                break;
+            }
+            remaining -= take as u64;
+        }
+        Ok(())
+    }
+
+    /// Check the SHA-256 of the output swapped back against the source's
+    pub fn matches(&mut self, expected: &[u8; 32]) -> io::Result<bool> {
+        let mut hasher = Sha256::new();
+        self.read_back(|bytes| {
+            hasher.update(bytes);
+            Ok(true)
+        })?;
+        Ok(hasher.finish() == *expected)
+    }
+
+    /// Compare the output against the source file, returning the first differing offset.
+    /// `magic` stands in for the first 4 bytes of the source when it was converted
+    /// with a repaired header.
+    pub fn first_mismatch(
+        &mut self,
+        source: &Path,
+        magic: Option<&[u8; 4]>,
+    ) -> io::Result<Option<u64>> {
+        let mut source = File::open(source)?;
+        let mut expected = vec![0; self.buffer_size];
+        let mut offset = 0;
+        let mut mismatch = None;
+
+        self.read_back(|bytes| {
+            source.read_exact(&mut expected[..bytes.len()])?;
+            if let (0, Some(magic)) = (offset, magic) {
+                let len = bytes.len().min(magic.len());
+                expected[..len].copy_from_slice(&magic[..len]);
+            }
+            if let Some(idx) = bytes.iter().zip(&expected).position(|(a, b)| a != b) {
+                mismatch = Some(offset + idx as u64);
+                return Ok(false);
+            }
+            offset += bytes.len() as u64;
+            Ok(true)
+        })?;
+        Ok(mismatch)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::Write;
+
+    use n64swap::{convert_bytes, BIG_ENDIAN};
+
+    use super::*;
+
+    #[test]
+    fn mismatch_after_a_repaired_magic() {
+        let dir = std::env::temp_dir();
+        let source = dir.join(format!("n64swap-{}-verify.z64", std::process::id()));
+        let output = dir.join(format!("n64swap-{}-verify.v64", std::process::id()));
+        let mut rom = BIG_ENDIAN.to_vec();
+        rom.extend(1..=12);
+        // The source has a damaged magic, the output the repaired one
+        let mut damaged = rom.clone();
+        damaged[3] = 0x41;
+        fs::write(&source, &damaged).unwrap();
+        convert_bytes(RomType::BigEndian, RomType::ByteSwap, &mut rom).unwrap();
+        rom[9] ^= 0xFF;
+        let mut file = File::options()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&output)
+            .unwrap();
+        file.write_all(&rom).unwrap();
+
+        let mut written = Written {
+            file: &mut file,
+            src_type: RomType::BigEndian,
+            dst_type: RomType::ByteSwap,
+            length: rom.len() as u64,
+            buffer_size: 4,
+        };
+        assert_eq!(
+            written.first_mismatch(&source, Some(&BIG_ENDIAN)).unwrap(),
+            Some(8)
+        );
+        assert_eq!(written.first_mismatch(&source, None).unwrap(), Some(3));
+        fs::remove_file(&source).unwrap();
+        fs::remove_file(&output).unwrap();
+    }
+}
@@ -0,0 +1,95 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use n64swap::{scan_roms, ConversionError, RomType, ScanOptions};
+
+use crate::batch::{convert_batch_file, BatchOutcome};
+use crate::interrupt;
+use crate::report::{check_dat, conversion_failed, print_digests, print_identify};
+use crate::stats::BatchStats;
+use crate::Args;
+
+// How often --watch scans its directory
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+const WATCH_STEPS: u32 = 10;
+
+/// Convert the roms added to a --watch directory, until Ctrl-C. A rom is only
+/// picked up once its size stayed the same between two scans, when whatever is
+/// writing it is done.
+pub fn watch_directory(args: &Args, directory: &Path) -> Result<(), ConversionError> {
+    let options = ScanOptions {
+        include_hidden: args.include_hidden,
+        symlinks: args.symlinks(),
+        ..ScanOptions::default()
+    };
+    let scan = || {
+        scan_roms(directory, &options).map_err(|error| {
+            let message = format!(
+                "Unable to read directory {}: {}",
+                directory.display(),
+                error
+            );
+            io::Error::new(error.kind(), message)
+        })
+    };
+    let outfiletype = args.romtype.unwrap_or(RomType::BigEndian);
+    let used = Mutex::new(HashSet::new());
+    let stats = BatchStats::new(0);
+
+    // Only roms added from now on are converted
+    let mut seen: HashSet<PathBuf> = scan()?.roms.into_iter().collect();
+    let mut growing: HashMap<PathBuf, u64> = HashMap::new();
+    interrupt::install();
+    info!(
+        args,
+        "Watching {}, press Ctrl-C to stop",
+        directory.display()
+    );
+    while !interrupt::interrupted() {
+        let roms = scan()?.roms;
+        growing.retain(|rom, _| roms.contains(rom));
+        for rom in roms {
+            if seen.contains(&rom) || interrupt::interrupted() {
+                continue;
+            }
+            let Ok(metadata) = fs::metadata(&rom) else {
+                continue;
+            };
+            if growing.insert(rom.clone(), metadata.len()) != Some(metadata.len()) {
+                continue;
+            }
+            growing.remove(&rom);
+            seen.insert(rom.clone());
+            let dat_names = check_dat(args, std::slice::from_ref(&rom))?;
+            let dat_name = dat_names.get(&rom).map(String::as_str);
+            match convert_batch_file(args, &rom, outfiletype, dat_name, &used, &stats, true) {
+                Ok(BatchOutcome::Identified(identity)) => print_identify(args, &rom, &identity),
+                Ok(BatchOutcome::Converted(outpath, digests)) => {
+                    success!(args, "Converted {} -> {}", rom.display(), outpath.display());
+                    print_digests(args, &digests);
+                    // Our own output isn't a new rom
+                    seen.insert(outpath);
+                }
+                Ok(BatchOutcome::Planned) => {}
+                Err(ConversionError::SameType(filetype)) => {
+                    info!(args, "Skipped {}, already {}", rom.display(), filetype);
+                }
+                Err(error) => conversion_failed(args, &rom, &error),
+            }
+        }
+        // In steps, to notice Ctrl-C quickly
+        for _ in 0..WATCH_STEPS {
+            if interrupt::interrupted() {
+                break;
+            }
+            thread::sleep(WATCH_INTERVAL / WATCH_STEPS);
+        }
+    }
+    info!(args, "Stopped watching {}", directory.display());
+    Ok(())
+}
@@ -0,0 +1,479 @@
+use std::collections::HashSet;
+use std::fs::{self, File, FileTimes};
+use std::io::{self, BufWriter, IsTerminal, Read, Seek, Write};
+use std::path::{Path, PathBuf};
+
+use n64swap::{
+    apply_bps, apply_bps_unverified, apply_ips, convert_buffered, fix_crc, format_size,
+    next_power_of_two_size, padding_byte, set_country_code, trimmed_len, unique_output_path_with,
+    ConversionError, RomType,
+};
+
+use crate::hash::{HashAlgorithm, HashWriter, Hashes};
+use crate::json::JsonObject;
+use crate::progress::{Progress, ProgressStyle};
+use crate::trace::TraceReader;
+use crate::verify::{HashReader, Written};
+use crate::zip;
+use crate::{Args, STDIO};
+
+/// Create a missing --output-dir, asking first unless forced
+pub fn ensure_output_dir(args: &Args) -> Result<(), ConversionError> {
+    let Some(dir) = args.output_dir.as_deref() else {
+        return Ok(());
+    };
+    if dir.is_dir() {
+        return Ok(());
+    }
+    if args.dry_run {
+        println!("Would create directory {}", dir.display());
+        return Ok(());
+    }
+
+    let create = args.force || {
+        let stdin = io::stdin();
+        if stdin.is_terminal() {
+            eprint!(
+                "Output directory {} doesn't exist, create it? [y/N] ",
+                dir.display()
+            );
+            let mut answer = String::new();
+            stdin.read_line(&mut answer)?;
+            answer.trim().eq_ignore_ascii_case("y")
+        } else {
+            false
+        }
+    };
+    if !create {
+        let message = format!(
+            "Output directory {} doesn't exist, use --force to create it",
+            dir.display()
+        );
+        return Err(io::Error::new(io::ErrorKind::NotFound, message).into());
+    }
+
+    fs::create_dir_all(dir).map_err(|error| {
+        let message = format!("Unable to create directory {}: {}", dir.display(), error);
+        io::Error::new(error.kind(), message).into()
+    })
+}
+
+/// What's being converted, shared by the single file and batch paths
+pub struct Job<'a> {
+    pub filename: &'a Path,
+    pub outfilename: &'a Path,
+    pub filetype: RomType,
+    pub outfiletype: RomType,
+    pub size: Option<u64>,
+    // The input's magic was damaged, what's converted has the one of `filetype`
+    pub repaired: bool,
+    // Parallel batches leave out the bar, it can't be shared on one line
+    pub progress: bool,
+}
+
+/// The --dry-run stand-in for writing the output
+pub fn plan_output(
+    args: &Args,
+    job: &Job,
+    outpath: &Path,
+    overwrite: bool,
+) -> Result<(), ConversionError> {
+    if !overwrite && outpath.exists() {
+        return Err(ConversionError::OutputExists(outpath.to_path_buf()));
+    }
+    let mut plan = format!(
+        "Would convert {} ({}) -> {} ({})",
+        job.filename.display(),
+        job.filetype.name(),
+        job.outfilename.display(),
+        job.outfiletype.name()
+    );
+    if let Some(size) = job.size {
+        plan.push_str(&format!(", {}", format_size(size)));
+    }
+    if args.in_place {
+        plan.push_str(", in place");
+    }
+    if let Some(suffix) = args.backup_suffix() {
+        for replaced in replaced_files(args, job, outpath) {
+            let backup = backup_path(&replaced, suffix);
+            if !args.force && backup.exists() {
+                return Err(ConversionError::BackupExists(backup));
+            }
+            plan.push_str(&format!(", backing up {}", backup.display()));
+        }
+    }
+    println!("{}", plan);
+    Ok(())
+}
+
+/// Number a batch output like "name_2.z64" while its name is taken, by
+/// an earlier rom of the batch or, unless forced, by an existing file
+pub fn unique_path(
+    args: &Args,
+    path: PathBuf,
+    input: &Path,
+    used: &mut HashSet<PathBuf>,
+) -> PathBuf {
+    let taken = |path: &Path| {
+        used.contains(path) || (!args.force && path.exists() && !(args.in_place && path == input))
+    };
+    let unique = unique_output_path_with(
+        path.parent().unwrap_or(Path::new("")),
+        &path.file_stem().unwrap_or_default().to_string_lossy(),
+        &path.extension().unwrap_or_default().to_string_lossy(),
+        taken,
+    );
+    used.insert(unique.clone());
+    unique
+}
+
+/// The existing files a conversion replaces, given it was allowed to
+pub fn replaced_files(args: &Args, job: &Job, outpath: &Path) -> Vec<PathBuf> {
+    let mut replaced = Vec::new();
+    if outpath.exists() {
+        replaced.push(outpath.to_path_buf());
+    }
+    if args.in_place && job.filename != outpath {
+        replaced.push(job.filename.to_path_buf());
+    }
+    replaced
+}
+
+fn backup_path(path: &Path, suffix: &str) -> PathBuf {
+    let mut backup = path.as_os_str().to_owned();
+    backup.push(suffix);
+    PathBuf::from(backup)
+}
+
+/// Move a file that's about to be replaced out of the way, if --backup asks for it.
+/// Returns whether it was moved.
+pub fn backup_file(args: &Args, path: &Path) -> Result<bool, ConversionError> {
+    let Some(suffix) = args.backup_suffix() else {
+        return Ok(false);
+    };
+    let backup = backup_path(path, suffix);
+    if !args.force && backup.exists() {
+        return Err(ConversionError::BackupExists(backup));
+    }
+    fs::rename(path, &backup)?;
+    info!(args, "Backed up {} to {}", path.display(), backup.display());
+    Ok(true)
+}
+
+/// Copy what --preserve-permissions and --preserve-timestamps ask for from the
+/// input to the finished output
+pub fn preserve_attributes(
+    args: &Args,
+    input: &fs::Metadata,
+    output: &File,
+) -> Result<(), ConversionError> {
+    #[cfg(unix)]
+    if args.preserve_permissions {
+        use std::os::unix::fs::PermissionsExt;
+        output.set_permissions(fs::Permissions::from_mode(input.permissions().mode()))?;
+    }
+    if args.preserve_timestamps {
+        let mut times = FileTimes::new().set_modified(input.modified()?);
+        if let Ok(accessed) = input.accessed() {
+            times = times.set_accessed(accessed);
+        }
+        output.set_times(times)?;
+    }
+    Ok(())
+}
+
+/// Run the conversion, drawing a progress bar when stderr is a terminal
+pub fn convert_with_progress<R: Read, W: Write>(
+    args: &Args,
+    job: &Job,
+    input: &mut R,
+    output: W,
+) -> Result<W, ConversionError> {
+    let style = if args.machine {
+        // Parallel batches leave them out too, they'd be mixed up
+        if job.progress {
+            ProgressStyle::Events
+        } else {
+            ProgressStyle::Hidden
+        }
+    } else if args.verbose {
+        ProgressStyle::Log
+    } else if !args.quiet && !args.debug && job.progress && io::stderr().is_terminal() {
+        ProgressStyle::Bar
+    } else {
+        ProgressStyle::Hidden
+    };
+
+    if args.verbose {
+        eprintln!("Input: {} ({})", job.filename.display(), job.filetype);
+        eprintln!(
+            "Output: {} ({})",
+            job.outfilename.display(),
+            job.outfiletype
+        );
+        match job.size {
+            Some(size) => eprintln!("Size: {} bytes", size),
+            None => eprintln!("Size: unknown"),
+        }
+        eprintln!("Buffer size: {} bytes", args.buffer_size);
+    }
+    if args.machine {
+        let event = JsonObject::new()
+            .string("event", "start")
+            .string("src", &job.filename.to_string_lossy())
+            .string("dst", &job.outfilename.to_string_lossy())
+            .string("src_type", &format!("{:?}", job.filetype))
+            .string("dst_type", &format!("{:?}", job.outfiletype));
+        println!("{}", event);
+    }
+
+    let limit = match (args.debug, args.debug_limit) {
+        (false, _) => 0,
+        (true, Some(limit)) => limit as u64,
+        (true, None) => u64::MAX,
+    };
+    let mut input = TraceReader::new(input, job.filetype, job.outfiletype, limit);
+    let mut output = Progress::new(output, job.size, style);
+    let mut written = convert_buffered(
+        &mut input,
+        &mut output,
+        job.filetype,
+        job.outfiletype,
+        args.pad,
+        args.buffer_size,
+    )?;
+
+    // Zeros read the same in every byte order, so they can go after swapping.
+    // With --trim the output file is padded once it's trimmed.
+    if args.pad_pow2 && !args.trim {
+        let padded = next_power_of_two_size(written);
+        let added = io::copy(&mut io::repeat(0).take(padded - written), &mut output)?;
+        output.flush()?;
+        written = padded;
+        if added > 0 {
+            info!(
+                args,
+                "Padded to {}, {} bytes added",
+                format_size(padded),
+                added
+            );
+        }
+    }
+
+    if args.verbose {
+        eprintln!("Wrote {} bytes", written);
+    }
+    Ok(output.into_inner())
+}
+
+pub fn write_output<R: Read>(
+    args: &Args,
+    job: &Job,
+    outfile: &mut File,
+    input: &mut R,
+) -> Result<Vec<(HashAlgorithm, String)>, ConversionError> {
+    let mut input = HashReader::new(input, args.verify);
+    let outbuf = BufWriter::with_capacity(args.buffer_size, outfile);
+    let outbuf = HashWriter::new(outbuf, Hashes::new(&args.computed_hashes()));
+    let (outbuf, mut hashes) = convert_with_progress(args, job, &mut input, outbuf)?.into_parts();
+    let outfile = outbuf.into_inner().map_err(|error| error.into_error())?;
+
+    // Before fixing the CRCs, which changes the output on purpose
+    if let Some((digest, length)) = input.finish() {
+        verify_output(args, job, outfile, &digest, length)?;
+    }
+    let patched = patch_output(args, outfile)?;
+    let resized = args.trim && trim_output(args, outfile)?;
+    if let Some(country) = args.region_override {
+        set_country_code(outfile, job.outfiletype, country)?;
+        info!(
+            args,
+            "Set the region of {} to {}",
+            job.outfilename.display(),
+            country
+        );
+    }
+    if args.crc_fix {
+        fix_output_crc(args, outfile, job.outfilename, job.outfiletype)?;
+    }
+    // The output changed after it was hashed
+    let changed = patched || resized || args.region_override.is_some() || args.crc_fix;
+    if changed && !hashes.is_empty() {
+        hashes = Hashes::new(&args.computed_hashes());
+        outfile.rewind()?;
+        io::copy(outfile, &mut hashes)?;
+    }
+    let digests = hashes.finish();
+    if args.machine {
+        let sha256 = digests
+            .iter()
+            .find(|(algorithm, _)| *algorithm == HashAlgorithm::Sha256)
+            .map_or("", |(_, digest)| digest);
+        let event = JsonObject::new()
+            .string("event", "complete")
+            .number("bytes_written", outfile.metadata()?.len())
+            .string("sha256", sha256);
+        println!("{}", event);
+    }
+    Ok(digests)
+}
+
+/// Replace the converted rom with a zip archive holding it, for --zip-output
+pub fn archive_output(outfile: &mut File, name: &str) -> io::Result<()> {
+    let mut rom = Vec::new();
+    outfile.rewind()?;
+    outfile.read_to_end(&mut rom)?;
+    outfile.rewind()?;
+    outfile.set_len(0)?;
+    let mut writer = BufWriter::new(outfile);
+    zip::write_rom(&mut writer, name, &rom)?;
+    writer.flush()
+}
+
+// Apply the --patch-ips or --patch-bps patch to the converted output, returning
+// whether there was one
+fn patch_output(args: &Args, outfile: &mut File) -> Result<bool, ConversionError> {
+    let Some(patch) = args.patch_ips.as_ref().or(args.patch_bps.as_ref()) else {
+        return Ok(false);
+    };
+    let patch_bytes = fs::read(patch).map_err(|error| {
+        let message = format!("Unable to read patch {}: {}", patch.display(), error);
+        io::Error::new(error.kind(), message)
+    })?;
+    let mut rom = Vec::new();
+    outfile.rewind()?;
+    outfile.read_to_end(&mut rom)?;
+
+    if args.patch_ips.is_some() {
+        apply_ips(&mut rom, &patch_bytes)?;
+    } else if args.force {
+        rom = apply_bps_unverified(&rom, &patch_bytes)?;
+    } else {
+        rom = apply_bps(&rom, &patch_bytes)?;
+    }
+    outfile.rewind()?;
+    outfile.write_all(&rom)?;
+    // BPS patches can shrink the rom
+    outfile.set_len(rom.len() as u64)?;
+    info!(args, "Applied {}", patch.display());
+    Ok(true)
+}
+
+// Cut the trailing padding off the output and pad it again with --pad-pow2,
+// returning whether its size changed
+fn trim_output(args: &Args, outfile: &mut File) -> Result<bool, ConversionError> {
+    let len = outfile.metadata()?.len();
+    let mut trimmed = len;
+    if let Some(byte) = padding_byte(outfile)? {
+        trimmed = trimmed_len(outfile, byte)?;
+        info!(
+            args,
+            "Trimmed {} bytes of 0x{:02X} padding, {} bytes left",
+            len - trimmed,
+            byte,
+            trimmed
+        );
+    }
+
+    let padded = if args.pad_pow2 {
+        next_power_of_two_size(trimmed)
+    } else {
+        trimmed
+    };
+    if padded > trimmed {
+        info!(
+            args,
+            "Padded to {}, {} bytes added",
+            format_size(padded),
+            padded - trimmed
+        );
+    }
+    // Outputs are written in whole words, so this never splits one
+    outfile.set_len(padded)?;
+    Ok(padded != len)
+}
+
+// Read the output back and compare its hash against the input's
+fn verify_output(
+    args: &Args,
+    job: &Job,
+    outfile: &mut File,
+    digest: &[u8; 32],
+    length: u64,
+) -> Result<(), ConversionError> {
+    let mut written = Written {
+        file: outfile,
+        src_type: job.filetype,
+        dst_type: job.outfiletype,
+        length,
+        buffer_size: args.buffer_size,
+    };
+    if written.matches(digest)? {
+        success!(args, "Verified {}", job.outfilename.display());
+        return Ok(());
+    }
+
+    // Only an input file can be read again to find where it went wrong
+    let offset = if job.filename == Path::new(STDIO) || args.zip_input {
+        None
+    } else {
+        let magic = job.repaired.then(|| job.filetype.get_header_bytes());
+        written.first_mismatch(job.filename, magic)?
+    };
+    Err(ConversionError::VerifyFailed {
+        path: job.outfilename.to_path_buf(),
+        offset,
+    })
+}
+
+// Patch fresh CRCs into a converted rom, a rom we can't checksum is only warned about
+fn fix_output_crc(
+    args: &Args,
+    file: &mut File,
+    filename: &Path,
+    rom_type: RomType,
+) -> Result<(), ConversionError> {
+    match fix_crc(file, rom_type) {
+        Ok((crc1, crc2)) => {
+            success!(
+                args,
+                "Updated CRCs of {}: 0x{:08X} 0x{:08X}",
+                filename.display(),
+                crc1,
+                crc2
+            );
+            Ok(())
+        }
+        Err(ConversionError::Checksum(error)) => {
+            warning!("CRCs of {} not updated. {}", filename.display(), error);
+            Ok(())
+        }
+        Err(error) => Err(error),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::parse;
+
+    #[test]
+    fn only_pad_pow2_pads_to_a_power_of_two() {
+        let path = std::env::temp_dir().join(format!("n64swap-{}-pad.z64", std::process::id()));
+        for (flag, len) in [("--pad", 12), ("--pad-pow2", 16)] {
+            let mut file = File::options()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .open(&path)
+                .unwrap();
+            file.write_all(&[[1; 12], [0; 12]].concat()).unwrap();
+            let args = parse(&["-q", "--trim", flag, "rom.z64"]);
+            trim_output(&args, &mut file).unwrap();
+            assert_eq!(file.metadata().unwrap().len(), len, "{}", flag);
+        }
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,248 @@
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::SystemTime;
+
+use n64swap::{crc32, guess_path_type, is_alias_ext};
+
+use crate::deflate::{deflate, inflate};
+use crate::time::UtcTime;
+
+const LOCAL_HEADER: u32 = 0x04034b50;
+const CENTRAL_HEADER: u32 = 0x02014b50;
+const END_OF_CENTRAL_DIRECTORY: u32 = 0x06054b50;
+const END_SIZE: usize = 22;
+const STORED: u16 = 0;
+const DEFLATED: u16 = 8;
+// Version 2.0, the first with deflate
+const VERSION: u16 = 20;
+// General purpose flag bit 11, the entry name is UTF-8
+const UTF8_NAME: u16 = 1 << 11;
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn read_u16(data: &[u8], offset: usize) -> io::Result<u16> {
+    match data.get(offset..offset + 2) {
+        Some(&[a, b]) => Ok(u16::from_le_bytes([a, b])),
+        _ => Err(invalid("Truncated zip archive")),
+    }
+}
+
+fn read_u32(data: &[u8], offset: usize) -> io::Result<u32> {
+    match data.get(offset..offset + 4) {
+        Some(&[a, b, c, d]) => Ok(u32::from_le_bytes([a, b, c, d])),
+        _ => Err(invalid("Truncated zip archive")),
+    }
+}
+
+// A rom extension, or an alias one since the header isn't known yet
+fn is_rom_name(name: &str) -> bool {
+    let path = Path::new(name);
+    guess_path_type(path).is_some()
+        || path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| is_alias_ext(&format!(".{}", ext)))
+}
+
+/// Extract the first entry with a rom extension from a zip archive in memory,
+/// returning its name and contents
+pub fn read_rom(archive: &[u8]) -> io::Result<(String, Vec<u8>)> {
+    // The end of central directory record is followed by a comment of up to 64 KiB
+    let search_start = archive
+        .len()
+        .saturating_sub(END_SIZE + usize::from(u16::MAX));
+    let end = (search_start..=archive.len().saturating_sub(END_SIZE))
+        .rev()
+        .find(|&offset| read_u32(archive, offset).is_ok_and(|sig| sig == END_OF_CENTRAL_DIRECTORY))
+        .ok_or_else(|| invalid("Not a zip archive"))?;
+    let entries = read_u16(archive, end + 10)?;
+    let mut offset = read_u32(archive, end + 16)? as usize;
+
+    for _ in 0..entries {
+        if read_u32(archive, offset)? != CENTRAL_HEADER {
+            return Err(invalid("Corrupt zip central directory"));
+        }
+        let method = read_u16(archive, offset + 10)?;
+        let crc = read_u32(archive, offset + 16)?;
+        let compressed_size = read_u32(archive, offset + 20)?;
+        let size = read_u32(archive, offset + 24)?;
+        let name_len = usize::from(read_u16(archive, offset + 28)?);
+        let extra_len = usize::from(read_u16(archive, offset + 30)?);
+        let comment_len = usize::from(read_u16(archive, offset + 32)?);
+        let local_offset = read_u32(archive, offset + 42)? as usize;
+        let name = archive
+            .get(offset + 46..offset + 46 + name_len)
+            .ok_or_else(|| invalid("Truncated zip archive"))?;
+        let name = String::from_utf8_lossy(name).into_owned();
+        offset += 46 + name_len + extra_len + comment_len;
+        if !is_rom_name(&name) {
+            continue;
+        }
+
+        if [compressed_size, size, local_offset as u32].contains(&u32::MAX) {
+            return Err(invalid("Zip64 archives aren't supported"));
+        }
+        if read_u32(archive, local_offset)? != LOCAL_HEADER {
+            return Err(invalid("Corrupt zip entry"));
+        }
+        let data_start = local_offset
+            + 30
+            + usize::from(read_u16(archive, local_offset + 26)?)
+            + usize::from(read_u16(archive, local_offset + 28)?);
+        let data = archive
+            .get(data_start..data_start + compressed_size as usize)
+            .ok_or_else(|| invalid("Truncated zip archive"))?;
+        let rom = match method {
+            STORED => data.to_vec(),
+            DEFLATED => inflate(data, size as usize)?,
+            _ => {
+                let message = format!("{} uses an unsupported compression method", name);
+                return Err(invalid(&message));
+            }
+        };
+        if rom.len() != size as usize || crc32(&rom) != crc {
+            return Err(invalid(&format!("{} is corrupt in the zip archive", name)));
+        }
+        return Ok((name, rom));
+    }
+    Err(invalid("No rom found in the zip archive"))
+}
+
+// MS-DOS time and date fields, counting from 1980
+fn dos_time(time: SystemTime) -> (u16, u16) {
+    let time = UtcTime::from_system(time);
+    let year = time.year.clamp(1980, 2107) as u16 - 1980;
+    (
+        (time.hour << 11 | time.minute << 5 | (time.second / 2)) as u16,
+        year << 9 | (time.month << 5 | time.day) as u16,
+    )
+}
+
+/// Write a zip archive holding `rom` as a single entry named `name`, deflated
+/// unless that doesn't make it any smaller
+pub fn write_rom<W: Write>(writer: &mut W, name: &str, rom: &[u8]) -> io::Result<()> {
+    let too_large = || invalid("Roms of 4 GiB or more can't go in a zip archive");
+    let size = u32::try_from(rom.len()).map_err(|_| too_large())?;
+    let deflated = deflate(rom);
+    let (method, data) = if deflated.len() < rom.len() {
+        (DEFLATED, deflated.as_slice())
+    } else {
+        (STORED, rom)
+    };
+    let compressed_size = u32::try_from(data.len()).map_err(|_| too_large())?;
+    let crc = crc32(rom);
+    let (time, date) = dos_time(SystemTime::now());
+    let flags = if name.is_ascii() { 0 } else { UTF8_NAME };
+    let name_len = u16::try_from(name.len()).map_err(|_| invalid("Zip entry name too long"))?;
+
+    // Fields the local and central headers share
+    let mut common = Vec::new();
+    for field in [flags, method, time, date] {
+        common.extend_from_slice(&field.to_le_bytes());
+    }
+    for field in [crc, compressed_size, size] {
+        common.extend_from_slice(&field.to_le_bytes());
+    }
+    common.extend_from_slice(&name_len.to_le_bytes());
+    // No extra field
+    common.extend_from_slice(&0u16.to_le_bytes());
+
+    let mut local = Vec::new();
+    local.extend_from_slice(&LOCAL_HEADER.to_le_bytes());
+    local.extend_from_slice(&VERSION.to_le_bytes());
+    local.extend_from_slice(&common);
+    local.extend_from_slice(name.as_bytes());
+    writer.write_all(&local)?;
+    writer.write_all(data)?;
+
+    let mut central = Vec::new();
+    central.extend_from_slice(&CENTRAL_HEADER.to_le_bytes());
+    // Made by and needed
+    central.extend_from_slice(&VERSION.to_le_bytes());
+    central.extend_from_slice(&VERSION.to_le_bytes());
+    central.extend_from_slice(&common);
+    // Comment length, disk, internal and external attributes, local header offset
+    central.extend_from_slice(&[0; 14]);
+    central.extend_from_slice(name.as_bytes());
+    writer.write_all(&central)?;
+
+    let central_offset = u32::try_from(local.len() + data.len()).map_err(|_| too_large())?;
+    let mut end = Vec::new();
+    end.extend_from_slice(&END_OF_CENTRAL_DIRECTORY.to_le_bytes());
+    // This disk and the one with the central directory
+    end.extend_from_slice(&[0; 4]);
+    // One entry on this disk, and in total
+    end.extend_from_slice(&1u16.to_le_bytes());
+    end.extend_from_slice(&1u16.to_le_bytes());
+    end.extend_from_slice(&(central.len() as u32).to_le_bytes());
+    end.extend_from_slice(&central_offset.to_le_bytes());
+    // No comment
+    end.extend_from_slice(&0u16.to_le_bytes());
+    writer.write_all(&end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn archive(name: &str, rom: &[u8]) -> Vec<u8> {
+        let mut archive = Vec::new();
+        write_rom(&mut archive, name, rom).unwrap();
+        archive
+    }
+
+    // Bytes that don't compress, from a xorshift generator
+    fn noise(len: usize) -> Vec<u8> {
+        let mut state = 0x2545_F491u32;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn deflated_round_trip() {
+        let rom: Vec<u8> = (0..8192).map(|idx| (idx % 13) as u8).collect();
+        let archive = archive("game.z64", &rom);
+        assert_eq!(read_u16(&archive, 8).unwrap(), DEFLATED);
+        assert_eq!(read_rom(&archive).unwrap(), ("game.z64".to_string(), rom));
+    }
+
+    #[test]
+    fn stored_round_trip() {
+        let rom = noise(4096);
+        let archive = archive("game.v64", &rom);
+        assert_eq!(read_u16(&archive, 8).unwrap(), STORED);
+        assert_eq!(read_rom(&archive).unwrap(), ("game.v64".to_string(), rom));
+    }
+
+    #[test]
+    fn truncated_archive_fails() {
+        let archive = archive("game.z64", &noise(256));
+        for len in 0..archive.len() {
+            assert!(read_rom(&archive[..len]).is_err(), "{} bytes", len);
+        }
+    }
+
+    #[test]
+    fn corrupt_archive_fails() {
+        let rom: Vec<u8> = (0..8192).map(|idx| (idx % 13) as u8).collect();
+        let mut deflated = archive("game.z64", &rom);
+        deflated[40] ^= 0xFF;
+        assert!(read_rom(&deflated).is_err());
+
+        let mut stored = archive("game.z64", &noise(256));
+        stored[100] ^= 0xFF;
+        let error = read_rom(&stored).unwrap_err();
+        assert_eq!(error.to_string(), "game.z64 is corrupt in the zip archive");
+
+        let error = read_rom(&archive("readme.txt", b"hello")).unwrap_err();
+        assert_eq!(error.to_string(), "No rom found in the zip archive");
+    }
+}